@@ -18,7 +18,6 @@ pub struct Extraction {
     pub caller: String,
     pub cargo_path: String,
     pub original_path: String,
-    pub mut_methods_path: String,
     pub notes: Option<String>,
     pub intellij_old_rust: ExtractionResultOld,
     pub rust_analyzer: ExtractionResultOld,
@@ -47,7 +46,6 @@ impl Extraction {
         let src_path = format!("{}/{}", project_path, src_path);
 
         let original_path = format!("{}_ORIGINAL", src_path);
-        let mut_methods_path = format!("{}_MUTABLE_METHOD_CALLS", src_path);
         let cargo_path = format!("{}/{}", project_path, cargo_path);
 
         Self {
@@ -56,7 +54,6 @@ impl Extraction {
             caller: caller.to_string(),
             cargo_path,
             original_path,
-            mut_methods_path,
             notes: notes.map(|s| s.to_string()),
             intellij_old_rust,
             rust_analyzer,
@@ -68,7 +65,6 @@ impl Extraction {
         let paths = vec![
             self.src_path.as_str(),
             self.original_path.as_str(),
-            self.mut_methods_path.as_str(),
             self.cargo_path.as_str(),
         ];
         paths.iter().for_each(|path| {
@@ -14,7 +14,7 @@ use crate::utils::ExtractionFeature::{
     StructHasLifetimeSlot,
 };
 use rem_borrower::borrow::inner_make_borrows;
-use rem_controller::non_local_controller::inner_make_controls;
+use rem_controller::non_local_controller::{check_controls_source, inner_make_controls};
 use rem_repairer::common::RepairSystem;
 use rem_repairer::repair_lifetime_loosest_bound_first::Repairer;
 use rem_utils::{check_project, find_caller, format_source};
@@ -476,6 +476,23 @@ pub fn run_controller(
             CALLEE_NAME,
             extraction.caller.as_str(),
         );
+        let (res, success) = match res {
+            Ok(res) => (res, true),
+            Err(e) => {
+                debug!("controller: {}", e);
+                // what the callee does still counts when the controller gives
+                // up on it, the source being left as it was
+                let checked = fs::read_to_string(&extraction.src_path)
+                    .map_err(rem_utils::error::Error::IO)
+                    .and_then(|source| {
+                        check_controls_source(&source, CALLEE_NAME, extraction.caller.as_str())
+                    });
+                match checked {
+                    Ok(res) => (res, false),
+                    Err(_) => return false,
+                }
+            }
+        };
         extraction_result.num_inputs = res.num_inputs;
 
         if res.has_break || res.has_continue {
@@ -484,7 +501,7 @@ pub fn run_controller(
         if res.has_return {
             extraction_result.features_inner.push(NonLocalReturn);
         }
-        res.success
+        success
     };
     let (success, duration) = time_exec("controller", &mut f);
     either!(
@@ -503,11 +520,17 @@ pub fn run_borrower(
         let res = inner_make_borrows(
             extraction.src_path.as_str(),
             extraction.src_path.as_str(),
-            extraction.mut_methods_path.as_str(),
             CALLEE_NAME,
             extraction.caller.as_str(),
             extraction.original_path.as_str(),
         );
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                debug!("borrower: {}", e);
+                return false;
+            }
+        };
 
         let make_ref: Vec<String> = res
             .make_ref
@@ -521,7 +544,7 @@ pub fn run_borrower(
         if res.make_mut.len() > 0 {
             extraction_result.features_inner.push(MutableBorrow);
         }
-        res.success
+        true
     };
     let (success, duration) = time_exec("borrower", &mut f);
     either!(
@@ -541,8 +564,19 @@ pub fn run_repairer(
         let res = repairer.repair_project(
             extraction.src_path.as_str(),
             extraction.cargo_path.as_str(),
+            None,
             CALLEE_NAME,
         );
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                debug!("cargo: {}", e);
+                if let rem_utils::error::Error::RepairExhausted { repair_count } = e {
+                    extraction_result.cargo_cycles = repair_count;
+                }
+                return false;
+            }
+        };
         debug!("cargo repair counted: {}", res.repair_count);
         extraction_result.cargo_cycles = res.repair_count;
         if res.has_non_elidible_lifetime || res.repair_count > 0 {
@@ -552,7 +586,7 @@ pub fn run_repairer(
         if res.has_struct_lt {
             extraction_result.features_inner.push(StructHasLifetimeSlot);
         }
-        res.success
+        true
     };
 
     let (success, duration) = time_exec("cargo", &mut f);
@@ -0,0 +1,15 @@
+fn new_foo() {
+    let mut v: Vec<i32> = vec![];
+    let x = 1;
+    bar(v, x);
+    println!("{}", v.len());
+}
+
+fn bar(v: Vec<i32>, x: i32) {
+    let r = &mut v;
+    r.push(x);
+}
+
+fn main() {
+    new_foo();
+}
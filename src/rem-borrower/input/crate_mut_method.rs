@@ -0,0 +1,24 @@
+struct Stack {
+    items: Vec<i32>,
+}
+
+impl Stack {
+    fn push_twice(&mut self, x: i32) {
+        self.items.push(x);
+        self.items.push(x);
+    }
+}
+
+fn new_foo() {
+    let mut s = Stack { items: vec![] };
+    bar(s);
+    println!("{}", s.items.len());
+}
+
+fn bar(s: Stack) {
+    s.push_twice(1);
+}
+
+fn main() {
+    new_foo();
+}
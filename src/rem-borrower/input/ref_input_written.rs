@@ -0,0 +1,14 @@
+fn new_foo() {
+    let mut x = 1;
+    let y = &mut x;
+    bar(y);
+    println!("{}", x);
+}
+
+fn bar(y: &i32) {
+    *y += 1;
+}
+
+fn main() {
+    new_foo();
+}
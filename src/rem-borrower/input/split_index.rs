@@ -0,0 +1,22 @@
+struct Grid {
+    cells: Vec<i32>,
+    total: i32,
+}
+
+fn new_foo() {
+    let mut g = Grid {
+        cells: vec![1, 2, 3],
+        total: 0,
+    };
+    let first = &g.cells[0];
+    bar(g);
+    println!("{} {}", first, g.total);
+}
+
+fn bar(g: Grid) {
+    g.total += g.cells[1] + g.cells[2];
+}
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,25 @@
+struct Job {
+    name: String,
+    log: Vec<String>,
+    runs: u32,
+}
+
+fn new_foo() {
+    let mut job = Job {
+        name: String::from("build"),
+        log: vec![],
+        runs: 1,
+    };
+    let runs = &job.runs;
+    bar(job);
+    println!("{} {}", runs, job.log.len());
+}
+
+fn bar(job: Job) {
+    let name = job.name;
+    job.log.push(name);
+}
+
+fn main() {
+    new_foo();
+}
@@ -1 +0,0 @@
-a.push(x)
\ No newline at end of file
@@ -1 +0,0 @@
-x.push('a')
\ No newline at end of file
@@ -1 +0,0 @@
-x.push(1)
\ No newline at end of file
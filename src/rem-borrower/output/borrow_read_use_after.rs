@@ -15,15 +15,15 @@ fn helper(x: i32) {
 #[allow(dead_code)]
 pub fn new_foo() {
     let x = 1;
-    bar(&x);
+    bar(x);
     println!("x={}", x);
     helper(x);
 }
-fn bar(x: &i32) {
-    let y = *x;
+fn bar(x: i32) {
+    let y = x;
     println!("x={}", x);
-    helper(*x);
+    helper(x);
     let z = y;
-    let _n = z + *x;
+    let _n = z + x;
 }
 fn main() {}
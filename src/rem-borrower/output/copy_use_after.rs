@@ -0,0 +1,20 @@
+#[derive(Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn new_foo() {
+    let p = Point { x: 1, y: 2 };
+    let n = 3;
+    bar(p, n);
+    println!("{} {} {}", p.x, p.y, n);
+}
+
+fn bar(p: Point, n: i32) {
+    println!("{}", p.x * n + p.y);
+}
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,25 @@
+struct Job {
+    name: String,
+    log: Vec<String>,
+    runs: u32,
+}
+
+fn new_foo() {
+    let mut job = Job {
+        name: String::from("build"),
+        log: vec![],
+        runs: 1,
+    };
+    let runs = &job.runs;
+    bar(job.name, &mut job.log);
+    println!("{} {}", runs, job.log.len());
+}
+
+fn bar(job_name: String, job_log: &mut Vec<String>) {
+    let name = job_name;
+    (*job_log).push(name);
+}
+
+fn main() {
+    new_foo();
+}
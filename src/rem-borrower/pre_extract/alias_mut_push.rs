@@ -0,0 +1,11 @@
+fn new_foo() {
+    let mut v: Vec<i32> = vec![];
+    let x = 1;
+    let r = &mut v;
+    r.push(x);
+    println!("{}", v.len());
+}
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,16 @@
+#[derive(Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn new_foo() {
+    let p = Point { x: 1, y: 2 };
+    let n = 3;
+    println!("{}", p.x * n + p.y);
+    println!("{} {} {}", p.x, p.y, n);
+}
+
+fn main() {
+    new_foo();
+}
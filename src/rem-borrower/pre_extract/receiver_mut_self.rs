@@ -0,0 +1,16 @@
+struct Counter {
+    count: i32,
+    step: i32,
+}
+
+impl Counter {
+    fn new_foo(&mut self) -> i32 {
+        self.count += self.step;
+        self.count
+    }
+}
+
+fn main() {
+    let mut c = Counter { count: 0, step: 2 };
+    c.new_foo();
+}
@@ -0,0 +1,10 @@
+fn new_foo() {
+    let mut x = 1;
+    let y = &mut x;
+    *y += 1;
+    println!("{}", x);
+}
+
+fn main() {
+    new_foo();
+}
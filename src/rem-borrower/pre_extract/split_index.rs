@@ -0,0 +1,18 @@
+struct Grid {
+    cells: Vec<i32>,
+    total: i32,
+}
+
+fn new_foo() {
+    let mut g = Grid {
+        cells: vec![1, 2, 3],
+        total: 0,
+    };
+    let first = &g.cells[0];
+    g.total += g.cells[1] + g.cells[2];
+    println!("{} {}", first, g.total);
+}
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,21 @@
+struct Job {
+    name: String,
+    log: Vec<String>,
+    runs: u32,
+}
+
+fn new_foo() {
+    let mut job = Job {
+        name: String::from("build"),
+        log: vec![],
+        runs: 1,
+    };
+    let runs = &job.runs;
+    let name = job.name;
+    job.log.push(name);
+    println!("{} {}", runs, job.log.len());
+}
+
+fn main() {
+    new_foo();
+}
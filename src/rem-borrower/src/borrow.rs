@@ -1,22 +1,28 @@
-use quote::ToTokens;
+use quote::{format_ident, ToTokens};
 use std::collections::HashMap;
 
-use proc_macro2::Ident;
-use std::fs;
+use proc_macro2::{Ident, TokenStream, TokenTree};
 
 use itertools::Itertools;
-use regex::Regex;
-use rem_constraint::common::AliasConstraints;
-use rem_constraint::ConstraintManager;
+use rem_constraint::common::{AliasConstraints, MutConstraints};
+use rem_constraint::{ConstraintManager, LocalConstraint};
 use syn::punctuated::Punctuated;
 use syn::{
-    visit_mut::VisitMut, Block, Expr, ExprAssign, ExprAssignOp, ExprCall, ExprMethodCall,
-    ExprReference, ExprReturn, FnArg, ImplItemMethod, ItemFn, Local, Macro, Pat, Signature, Stmt,
-    Token, TraitItemMethod, Type, TypeReference,
+    visit_mut::VisitMut, Block, Expr, ExprArray, ExprAssign, ExprAssignOp, ExprAsync, ExprAwait,
+    ExprCall, ExprClosure, ExprMethodCall, ExprPath, ExprReference, ExprReturn, ExprStruct,
+    ExprTuple, Fields, FnArg, ImplItemMethod, Item, ItemFn, ItemImpl, Local, Macro, Pat, PatIdent,
+    Signature, Stmt, Token, TraitItemMethod, Type, TypeReference, UnOp,
 };
 
 use log::debug;
-use rem_utils::{format_source, FindCallee};
+use rem_utils::error::Error;
+use rem_utils::filesystem::{FileSystem, RealFileSystem};
+use rem_utils::labelling::Label;
+use rem_utils::mutability::MutMethods;
+use rem_utils::resolve::{resolve_fn, CallSites, FnId};
+use rem_utils::{edit::splice, format_source, FindCallee};
+
+use crate::copy::CopyTypes;
 
 struct RefBorrowAssignerHelper<'a> {
     make_ref: &'a Vec<String>,
@@ -99,7 +105,7 @@ impl VisitMut for RefBorrowAssignerHelper<'_> {
 }
 
 struct CalleeBorrowAssigner<'a> {
-    fn_name: &'a str,
+    callee: &'a FnId,
     make_ref: &'a Vec<String>,
     make_mut: &'a Vec<String>,
     ref_inputs: &'a Vec<String>,
@@ -108,9 +114,8 @@ struct CalleeBorrowAssigner<'a> {
 
 impl VisitMut for CalleeBorrowAssigner<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.callee_borrow_assigner(&mut i.sig, &mut i.block),
         }
@@ -118,17 +123,16 @@ impl VisitMut for CalleeBorrowAssigner<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.callee_borrow_assigner(&mut i.sig, &mut i.block),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
@@ -150,13 +154,729 @@ impl CalleeBorrowAssigner<'_> {
             ref_inputs: self.ref_inputs,
             mut_ref_inputs: self.mut_ref_inputs,
         };
-        sig.inputs
-            .iter_mut()
-            .for_each(|fn_arg| borrow_assigner.visit_fn_arg_mut(fn_arg));
-        block
-            .stmts
-            .iter_mut()
-            .for_each(|stmt| borrow_assigner.visit_stmt_mut(stmt))
+        sig.inputs
+            .iter_mut()
+            .for_each(|fn_arg| borrow_assigner.visit_fn_arg_mut(fn_arg));
+        block
+            .stmts
+            .iter_mut()
+            .for_each(|stmt| borrow_assigner.visit_stmt_mut(stmt))
+    }
+}
+
+/// Gives the callee the weakest receiver its uses of `self` need: `&self`,
+/// `&mut self`, or `self` if it is moved and the caller has it by value.
+struct ReceiverAssigner<'a> {
+    callee: &'a FnId,
+    is_mut: bool,
+    moves: bool,
+}
+
+impl ReceiverAssigner<'_> {
+    fn assign_receiver(&self, sig: &mut Signature) {
+        match sig.inputs.first_mut() {
+            Some(FnArg::Receiver(r)) => {
+                if !self.moves && r.reference.is_none() {
+                    r.reference = Some((Default::default(), None));
+                }
+                r.mutability = match self.is_mut {
+                    true => Some(Default::default()),
+                    false => None,
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl VisitMut for ReceiverAssigner<'_> {
+    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+        if self.callee.is(&i.sig.ident) {
+            self.assign_receiver(&mut i.sig)
+        }
+    }
+
+    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+        if self.callee.is(&i.sig.ident) {
+            self.assign_receiver(&mut i.sig)
+        }
+    }
+}
+
+/// Runs `visitor` on the body of the function `id`.
+struct InFn<'a, V> {
+    id: &'a FnId,
+    visitor: &'a mut V,
+}
+
+impl<V: VisitMut> VisitMut for InFn<'_, V> {
+    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+        if self.id.is(&i.sig.ident) {
+            self.visitor.visit_block_mut(&mut i.block)
+        }
+        syn::visit_mut::visit_impl_item_method_mut(self, i);
+    }
+
+    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+        if self.id.is(&i.sig.ident) {
+            self.visitor.visit_block_mut(&mut i.block)
+        }
+        syn::visit_mut::visit_item_fn_mut(self, i);
+    }
+
+    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+        match &mut i.default {
+            Some(block) if self.id.is(&i.sig.ident) => self.visitor.visit_block_mut(block),
+            _ => (),
+        }
+        syn::visit_mut::visit_trait_item_method_mut(self, i);
+    }
+}
+
+/// The function `id`, with methods taken as functions of `self`.
+struct CalleeFn<'a> {
+    id: &'a FnId,
+    found: Option<ItemFn>,
+}
+
+impl VisitMut for CalleeFn<'_> {
+    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+        if self.id.is(&i.sig.ident) {
+            self.found = Some(ItemFn {
+                attrs: i.attrs.clone(),
+                vis: i.vis.clone(),
+                sig: i.sig.clone(),
+                block: Box::new(i.block.clone()),
+            })
+        }
+        syn::visit_mut::visit_impl_item_method_mut(self, i);
+    }
+
+    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+        if self.id.is(&i.sig.ident) {
+            self.found = Some(i.clone())
+        }
+        syn::visit_mut::visit_item_fn_mut(self, i);
+    }
+
+    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+        match &i.default {
+            Some(block) if self.id.is(&i.sig.ident) => {
+                self.found = Some(ItemFn {
+                    attrs: i.attrs.clone(),
+                    vis: syn::Visibility::Inherited,
+                    sig: i.sig.clone(),
+                    block: Box::new(block.clone()),
+                })
+            }
+            _ => (),
+        }
+        syn::visit_mut::visit_trait_item_method_mut(self, i);
+    }
+}
+
+/// Whether `expr` is the variable `name`, maybe dereferenced as in `(*name)`.
+fn is_var(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Path(p) => p.qself.is_none() && p.path.is_ident(name),
+        Expr::Paren(p) => is_var(&p.expr, name),
+        Expr::Unary(u) => matches!(u.op, UnOp::Deref(_)) && is_var(&u.expr, name),
+        _ => false,
+    }
+}
+
+/// The fields `expr` goes through from the variable `root`, e.g. `[a, b]`
+/// for `s.a.b` under `s`, and `[]` for `s` itself.
+fn place_of(expr: &Expr, root: &str) -> Option<Vec<String>> {
+    match expr {
+        _ if is_var(expr, root) => Some(vec![]),
+        Expr::Field(f) => place_of(&f.base, root).map(|mut place| {
+            place.push(f.member.to_token_stream().to_string());
+            place
+        }),
+        Expr::Paren(p) => place_of(&p.expr, root),
+        _ => None,
+    }
+}
+
+/// The place under `root` that borrowing `expr` borrows: indexing borrows
+/// the whole container, e.g. `s.v` for `s.v[i]`, as `i` is only known at run
+/// time.
+fn borrowed_place(expr: &Expr, root: &str) -> Option<Vec<String>> {
+    match expr {
+        Expr::Index(i) => borrowed_place(&i.expr, root),
+        Expr::Paren(p) if !is_var(expr, root) => borrowed_place(&p.expr, root),
+        _ => place_of(expr, root),
+    }
+}
+
+/// Whether two places under the same variable share memory.
+fn overlaps(a: &[String], b: &[String]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+fn mentions_var(tokens: TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(id) => id == name,
+        TokenTree::Group(g) => mentions_var(g.stream(), name),
+        _ => false,
+    })
+}
+
+/// How the callee uses one of its inputs, or `self`: the places under it
+/// that it goes through, and whether it also needs it as a whole, to call
+/// methods on it, borrow it, index it or move it.
+struct PlaceUses<'a> {
+    root: &'a str,
+    places: Vec<Vec<String>>,
+    whole: bool,
+    moves: bool,
+}
+
+impl VisitMut for PlaceUses<'_> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        match i {
+            _ if is_var(i, self.root) => {
+                self.whole = true;
+                self.moves = true;
+            }
+            Expr::MethodCall(m) if is_var(&m.receiver, self.root) => {
+                self.whole = true;
+                m.args.iter_mut().for_each(|arg| self.visit_expr_mut(arg))
+            }
+            Expr::Reference(r) if is_var(&r.expr, self.root) => self.whole = true,
+            Expr::Index(index) if is_var(&index.expr, self.root) => {
+                self.whole = true;
+                self.visit_expr_mut(&mut index.index)
+            }
+            Expr::Field(_) => match place_of(i, self.root) {
+                Some(place) => {
+                    if !self.places.contains(&place) {
+                        self.places.push(place)
+                    }
+                }
+                None => syn::visit_mut::visit_expr_mut(self, i),
+            },
+            _ => syn::visit_mut::visit_expr_mut(self, i),
+        }
+    }
+
+    fn visit_macro_mut(&mut self, i: &mut Macro) {
+        // the tokens of a macro cannot be rewritten place by place
+        if mentions_var(i.tokens.clone(), self.root) {
+            self.whole = true;
+        }
+    }
+
+    // `self` is another one in there
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// The places under `root` that the callee mutates.
+struct PlaceMutations<'a> {
+    root: &'a str,
+    mut_methods: &'a MutMethods,
+    mutated: Vec<Vec<String>>,
+}
+
+impl PlaceMutations<'_> {
+    fn mutates(&mut self, expr: &Expr) {
+        self.mutated.extend(borrowed_place(expr, self.root))
+    }
+}
+
+impl VisitMut for PlaceMutations<'_> {
+    fn visit_expr_assign_mut(&mut self, i: &mut ExprAssign) {
+        self.mutates(&i.left);
+        syn::visit_mut::visit_expr_assign_mut(self, i)
+    }
+
+    fn visit_expr_assign_op_mut(&mut self, i: &mut ExprAssignOp) {
+        self.mutates(&i.left);
+        syn::visit_mut::visit_expr_assign_op_mut(self, i)
+    }
+
+    fn visit_expr_reference_mut(&mut self, i: &mut ExprReference) {
+        if i.mutability.is_some() {
+            self.mutates(&i.expr)
+        }
+        syn::visit_mut::visit_expr_reference_mut(self, i)
+    }
+
+    fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
+        if self.mut_methods.contains(&i.method) {
+            self.mutates(&i.receiver)
+        }
+        syn::visit_mut::visit_expr_method_call_mut(self, i)
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// The places under `root` that the callee moves out, by using them as a
+/// value: they cannot be lent to it, only handed over.
+struct PlaceMoves<'a> {
+    root: &'a str,
+    moved: Vec<Vec<String>>,
+}
+
+impl PlaceMoves<'_> {
+    fn moves(&mut self, expr: &Expr) {
+        match place_of(expr, self.root) {
+            Some(place) if !place.is_empty() => self.moved.push(place),
+            _ => (),
+        }
+    }
+}
+
+impl VisitMut for PlaceMoves<'_> {
+    fn visit_local_mut(&mut self, i: &mut Local) {
+        if let Some((_, init)) = &i.init {
+            self.moves(init)
+        }
+        syn::visit_mut::visit_local_mut(self, i)
+    }
+
+    fn visit_expr_assign_mut(&mut self, i: &mut ExprAssign) {
+        self.moves(&i.right);
+        syn::visit_mut::visit_expr_assign_mut(self, i)
+    }
+
+    fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
+        i.args.iter().for_each(|arg| self.moves(arg));
+        syn::visit_mut::visit_expr_call_mut(self, i)
+    }
+
+    fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
+        i.args.iter().for_each(|arg| self.moves(arg));
+        syn::visit_mut::visit_expr_method_call_mut(self, i)
+    }
+
+    fn visit_expr_return_mut(&mut self, i: &mut ExprReturn) {
+        if let Some(expr) = &i.expr {
+            self.moves(expr)
+        }
+        syn::visit_mut::visit_expr_return_mut(self, i)
+    }
+
+    fn visit_expr_struct_mut(&mut self, i: &mut ExprStruct) {
+        i.fields.iter().for_each(|field| self.moves(&field.expr));
+        syn::visit_mut::visit_expr_struct_mut(self, i)
+    }
+
+    fn visit_expr_tuple_mut(&mut self, i: &mut ExprTuple) {
+        i.elems.iter().for_each(|elem| self.moves(elem));
+        syn::visit_mut::visit_expr_tuple_mut(self, i)
+    }
+
+    fn visit_expr_array_mut(&mut self, i: &mut ExprArray) {
+        i.elems.iter().for_each(|elem| self.moves(elem));
+        syn::visit_mut::visit_expr_array_mut(self, i)
+    }
+
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        if let Some(Stmt::Expr(tail)) = i.stmts.last() {
+            self.moves(tail)
+        }
+        syn::visit_mut::visit_block_mut(self, i)
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// The variables bound by patterns and used by paths.
+struct Names {
+    names: Vec<String>,
+}
+
+impl VisitMut for Names {
+    fn visit_pat_ident_mut(&mut self, i: &mut PatIdent) {
+        self.names.push(i.ident.to_string());
+        syn::visit_mut::visit_pat_ident_mut(self, i)
+    }
+
+    fn visit_expr_path_mut(&mut self, i: &mut ExprPath) {
+        if let Some(id) = i.path.get_ident() {
+            self.names.push(id.to_string())
+        }
+    }
+
+    // whatever the macro makes of them
+    fn visit_macro_mut(&mut self, i: &mut Macro) {
+        fn idents(tokens: TokenStream, names: &mut Vec<String>) {
+            tokens.into_iter().for_each(|token| match token {
+                TokenTree::Ident(id) => names.push(id.to_string()),
+                TokenTree::Group(g) => idents(g.stream(), names),
+                _ => (),
+            })
+        }
+        idents(i.tokens.clone(), &mut self.names)
+    }
+}
+
+/// Places under `root` borrowed by an expression, with whether mutably.
+struct PlaceRefs<'a> {
+    root: &'a str,
+    refs: Vec<(Vec<String>, bool)>,
+}
+
+impl VisitMut for PlaceRefs<'_> {
+    fn visit_expr_reference_mut(&mut self, i: &mut ExprReference) {
+        match borrowed_place(&i.expr, self.root) {
+            Some(place) => self.refs.push((place, i.mutability.is_some())),
+            None => syn::visit_mut::visit_expr_reference_mut(self, i),
+        }
+    }
+}
+
+/// Places under `root` that the caller borrows into locals still used once
+/// the callee is called, with whether mutably, and whether it moved some of
+/// them out of `root` before the call.
+struct LiveBorrows<'a> {
+    callee: &'a CallSites,
+    root: &'a str,
+    live: Vec<(Vec<String>, bool)>,
+    moved: bool,
+}
+
+impl VisitMut for LiveBorrows<'_> {
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        let mut borrows = vec![];
+        let mut called = false;
+        for stmt in i.stmts.iter_mut() {
+            if !called {
+                let mut callee_finder = FindCallee {
+                    found: false,
+                    callee: self.callee,
+                };
+                callee_finder.visit_stmt_mut(stmt);
+                called = callee_finder.found;
+            }
+            match stmt {
+                _ if called => {
+                    let mut used = Names { names: vec![] };
+                    used.visit_stmt_mut(stmt);
+                    borrows
+                        .iter()
+                        .filter(|(names, _): &&(Vec<String>, Vec<(Vec<String>, bool)>)| {
+                            names.iter().any(|name| used.names.contains(name))
+                        })
+                        .for_each(|(_, refs)| self.live.extend(refs.iter().cloned()))
+                }
+                Stmt::Local(local) => {
+                    let mut refs = PlaceRefs {
+                        root: self.root,
+                        refs: vec![],
+                    };
+                    if let Some((_, init)) = &mut local.init {
+                        // `let x = s.a;` may move `s.a` out of `s`
+                        self.moved |= place_of(init, self.root).map_or(false, |p| !p.is_empty());
+                        refs.visit_expr_mut(init)
+                    }
+                    let mut bound = Names { names: vec![] };
+                    bound.visit_pat_mut(&mut local.pat);
+                    if !refs.refs.is_empty() {
+                        borrows.push((bound.names, refs.refs))
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn type_ident(ty: &Type) -> Option<&Ident> {
+    match ty {
+        Type::Path(p) if p.qself.is_none() => p.path.get_ident(),
+        Type::Paren(p) => type_ident(&p.elem),
+        Type::Group(g) => type_ident(&g.elem),
+        _ => None,
+    }
+}
+
+/// The named fields of the struct `name`, if the file defines one that is
+/// not generic.
+fn named_fields(items: &[Item], name: &Ident) -> Option<Vec<(String, Type)>> {
+    items.iter().find_map(|item| match item {
+        Item::Struct(s) if &s.ident == name && s.generics.params.is_empty() => match &s.fields {
+            Fields::Named(fields) => Some(
+                fields
+                    .named
+                    .iter()
+                    .filter_map(|f| Some((f.ident.as_ref()?.to_string(), f.ty.clone())))
+                    .collect(),
+            ),
+            _ => None,
+        },
+        Item::Mod(m) => m
+            .content
+            .as_ref()
+            .and_then(|(_, items)| named_fields(items, name)),
+        _ => None,
+    })
+}
+
+/// The longest prefix of `place` under a value of type `ty` whose type the
+/// structs of the file tell, with that type.
+fn place_type(items: &[Item], ty: &Type, place: &[String]) -> Option<(Vec<String>, Type)> {
+    let (field, rest) = place.split_first()?;
+    let (_, field_ty) = named_fields(items, type_ident(ty)?)?
+        .into_iter()
+        .find(|(name, _)| name == field)?;
+    match place_type(items, &field_ty, rest) {
+        Some((mut deeper, ty)) => {
+            deeper.insert(0, field.clone());
+            Some((deeper, ty))
+        }
+        None => Some((vec![field.clone()], field_ty)),
+    }
+}
+
+/// An input of the callee, or `self`, that it may take place by place.
+struct Root {
+    name: String,
+    /// position in the signature
+    index: usize,
+    /// position in the arguments of a call, none for the receiver
+    arg: Option<usize>,
+    ty: Type,
+    is_mut: bool,
+    by_value: bool,
+}
+
+/// The signature of the callee, with the type it is a method of if any.
+struct CalleeSig<'a> {
+    callee: &'a FnId,
+    self_ty: Option<Type>,
+    found: Option<(Signature, Option<Type>)>,
+}
+
+impl VisitMut for CalleeSig<'_> {
+    fn visit_item_impl_mut(&mut self, i: &mut ItemImpl) {
+        let outer = self.self_ty.replace(i.self_ty.as_ref().clone());
+        syn::visit_mut::visit_item_impl_mut(self, i);
+        self.self_ty = outer;
+    }
+
+    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+        if self.callee.is(&i.sig.ident) {
+            self.found = Some((i.sig.clone(), self.self_ty.clone()))
+        }
+    }
+
+    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+        if self.callee.is(&i.sig.ident) {
+            self.found = Some((i.sig.clone(), None))
+        }
+        let outer = self.self_ty.take();
+        syn::visit_mut::visit_item_fn_mut(self, i);
+        self.self_ty = outer;
+    }
+
+    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+        if self.callee.is(&i.sig.ident) {
+            self.found = Some((i.sig.clone(), None))
+        }
+    }
+}
+
+/// The inputs of the callee named by a plain identifier, and `self` if the
+/// callee is a method of a type.
+fn roots(sig: &Signature, self_ty: Option<Type>) -> Vec<Root> {
+    let has_receiver = sig.receiver().is_some() as usize;
+    sig.inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, arg)| match arg {
+            FnArg::Receiver(r) => Some(Root {
+                name: "self".to_string(),
+                index,
+                arg: None,
+                ty: self_ty.clone()?,
+                is_mut: r.mutability.is_some(),
+                by_value: r.reference.is_none(),
+            }),
+            FnArg::Typed(t) => {
+                let name = match t.pat.as_ref() {
+                    Pat::Ident(p) if p.subpat.is_none() => p.ident.to_string(),
+                    _ => return None,
+                };
+                let (ty, is_mut, by_value) = match t.ty.as_ref() {
+                    Type::Reference(r) => (r.elem.as_ref().clone(), r.mutability.is_some(), false),
+                    ty => (ty.clone(), false, true),
+                };
+                Some(Root {
+                    name,
+                    index,
+                    arg: Some(index - has_receiver),
+                    ty,
+                    is_mut,
+                    by_value,
+                })
+            }
+        })
+        .collect()
+}
+
+/// A place under a root, e.g. `s.a.b`, that the callee takes as a parameter
+/// of its own instead of the whole root.
+struct SplitPlace {
+    place: Vec<String>,
+    param: Ident,
+    ty: Type,
+    is_mut: bool,
+    by_value: bool,
+}
+
+impl SplitPlace {
+    /// The place under `base`, as lent to the callee.
+    fn arg(&self, base: &Expr) -> Expr {
+        let place = self.place.iter().fold(base.clone(), |e, field| {
+            let field = format_ident!("{}", field);
+            syn::parse_quote! {#e.#field}
+        });
+        match (self.by_value, self.is_mut) {
+            (true, _) => place,
+            (false, true) => syn::parse_quote! {&mut #place},
+            (false, false) => syn::parse_quote! {&#place},
+        }
+    }
+
+    fn param(&self) -> FnArg {
+        let (param, ty) = (&self.param, &self.ty);
+        match (self.by_value, self.is_mut) {
+            (true, true) => syn::parse_quote! {mut #param: #ty},
+            (true, false) => syn::parse_quote! {#param: #ty},
+            (false, true) => syn::parse_quote! {#param: &mut #ty},
+            (false, false) => syn::parse_quote! {#param: &#ty},
+        }
+    }
+}
+
+/// Turns the uses of split places into uses of their parameters.
+struct PlaceReplacer<'a> {
+    root: &'a str,
+    places: &'a Vec<SplitPlace>,
+}
+
+impl VisitMut for PlaceReplacer<'_> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        let split = match i {
+            Expr::Field(_) => place_of(i, self.root)
+                .and_then(|place| self.places.iter().find(|s| s.place == place)),
+            _ => None,
+        };
+        match split {
+            Some(SplitPlace {
+                param, by_value, ..
+            }) => match by_value {
+                true => *i = syn::parse_quote! {#param},
+                false => *i = syn::parse_quote! {(*#param)},
+            },
+            None => syn::visit_mut::visit_expr_mut(self, i),
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// Makes the callee take the places under `root` it uses instead of the
+/// whole of it.
+struct PlaceSplitter<'a> {
+    callee: &'a FnId,
+    root: &'a Root,
+    places: &'a Vec<SplitPlace>,
+}
+
+impl PlaceSplitter<'_> {
+    fn split(&self, sig: &mut Signature, block: &mut Block) {
+        let params = self.places.iter().map(SplitPlace::param);
+        sig.inputs = sig
+            .inputs
+            .iter()
+            .take(self.root.index)
+            .cloned()
+            .chain(params)
+            .chain(sig.inputs.iter().skip(self.root.index + 1).cloned())
+            .collect();
+        let mut replacer = PlaceReplacer {
+            root: self.root.name.as_str(),
+            places: self.places,
+        };
+        replacer.visit_block_mut(block);
+    }
+}
+
+impl VisitMut for PlaceSplitter<'_> {
+    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+        if self.callee.is(&i.sig.ident) {
+            self.split(&mut i.sig, &mut i.block)
+        }
+        syn::visit_mut::visit_impl_item_method_mut(self, i);
+    }
+
+    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+        if self.callee.is(&i.sig.ident) {
+            self.split(&mut i.sig, &mut i.block)
+        }
+        syn::visit_mut::visit_item_fn_mut(self, i);
+    }
+
+    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+        match &mut i.default {
+            Some(block) if self.callee.is(&i.sig.ident) => self.split(&mut i.sig, block),
+            _ => (),
+        }
+        syn::visit_mut::visit_trait_item_method_mut(self, i);
+    }
+}
+
+/// Makes the calls to the callee lend the split places one by one.
+struct CallSplitter<'a> {
+    callee: &'a CallSites,
+    root: &'a Root,
+    places: &'a Vec<SplitPlace>,
+}
+
+impl CallSplitter<'_> {
+    fn split_arg(&self, args: &mut Punctuated<Expr, Token![,]>, index: usize) {
+        let base = match args.iter().nth(index) {
+            Some(Expr::Reference(r)) => r.expr.as_ref().clone(),
+            Some(arg) => arg.clone(),
+            None => return,
+        };
+        let places = self.places.iter().map(|s| s.arg(&base));
+        *args = args
+            .iter()
+            .take(index)
+            .cloned()
+            .chain(places)
+            .chain(args.iter().skip(index + 1).cloned())
+            .collect();
+    }
+}
+
+impl VisitMut for CallSplitter<'_> {
+    fn visit_expr_mut(&mut self, i: &mut Expr) {
+        syn::visit_mut::visit_expr_mut(self, i);
+        match i {
+            Expr::Call(c) if self.callee.is_call(&c.func) => match self.root.arg {
+                Some(index) => self.split_arg(&mut c.args, index),
+                None => (),
+            },
+            Expr::MethodCall(m) if self.callee.is_method_call(m) => match self.root.arg {
+                Some(index) => self.split_arg(&mut m.args, index),
+                // the receiver goes, so it is no longer a method call
+                None => {
+                    let receiver = m.receiver.as_ref();
+                    let places = self.places.iter().map(|s| s.arg(receiver));
+                    let args: Vec<Expr> = places.chain(m.args.iter().cloned()).collect();
+                    let method = &m.method;
+                    *i = syn::parse_quote! {Self::#method(#(#args),*)}
+                }
+            },
+            _ => (),
+        }
     }
 }
 
@@ -227,22 +947,48 @@ impl VisitMut for CalleeReturnsHelper<'_> {
             _ => syn::visit_mut::visit_stmt_mut(self, i),
         }
     }
+    // what these return is not returned by the callee
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+struct AwaitedInputs {
+    awaited: Vec<String>,
+}
+
+impl VisitMut for AwaitedInputs {
+    fn visit_expr_await_mut(&mut self, i: &mut ExprAwait) {
+        match i.base.as_ref() {
+            Expr::Path(p) => self.awaited.push(p.into_token_stream().to_string()),
+            _ => syn::visit_mut::visit_expr_await_mut(self, i),
+        }
+    }
+
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
 }
 
 struct CalleeInputs<'a> {
-    fn_name: &'a str,
+    callee: &'a FnId,
     inputs: &'a mut Vec<String>,
     refs_inputs: &'a mut Vec<String>,
     mut_refs_inputs: &'a mut Vec<String>,
+    copy_types: &'a CopyTypes,
+    copy_inputs: &'a mut Vec<String>,
     make_ref: &'a mut Vec<String>,
     found: bool,
 }
 
 impl VisitMut for CalleeInputs<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.callee_inputs(&mut i.sig, &mut i.block),
         }
@@ -250,17 +996,16 @@ impl VisitMut for CalleeInputs<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             true => self.callee_inputs(&mut i.sig, &mut i.block),
             false => (),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
@@ -295,12 +1040,20 @@ impl CalleeInputs<'_> {
                             }
                         }
                     }
-                    _ => self
-                        .inputs
-                        .push(t.pat.as_ref().into_token_stream().to_string()),
+                    ty => {
+                        let input = t.pat.as_ref().into_token_stream().to_string();
+                        if self.copy_types.is_copy(ty) {
+                            self.copy_inputs.push(input.clone())
+                        }
+                        self.inputs.push(input)
+                    }
                 }
             }
         });
+        // a future is awaited by value, so it is moved in whatever happens after
+        let mut awaited = AwaitedInputs { awaited: vec![] };
+        awaited.visit_block_mut(block);
+        self.inputs.retain(|input| !awaited.awaited.contains(input));
         let mut ret_helper = CalleeReturnsHelper {
             inputs: self.inputs,
             make_ref: self.make_ref,
@@ -310,8 +1063,7 @@ impl CalleeInputs<'_> {
 }
 
 struct CallerCheckCallee<'a> {
-    callee_fn_name: &'a str,
-    decl_mut: &'a mut Vec<String>,
+    callee: &'a CallSites,
     found: bool,
     check_input_visitor: &'a mut CallerCheckInput<'a>,
 }
@@ -322,13 +1074,12 @@ impl VisitMut for CallerCheckCallee<'_> {
         syn::visit_mut::visit_expr_mut(self, i)
     }
     fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
-        let id = i.func.as_ref().into_token_stream().to_string();
         // println!(
         //     "expression call: {}",
         //     i.clone().into_token_stream().to_string()
         // );
         // println!("func call: {}", id.as_str());
-        match id == self.callee_fn_name {
+        match self.callee.is_call(&i.func) {
             false => syn::visit_mut::visit_expr_call_mut(self, i),
             true => {
                 self.found = true;
@@ -338,8 +1089,7 @@ impl VisitMut for CallerCheckCallee<'_> {
     }
 
     fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let callee = i.method.clone().into_token_stream().to_string();
-        match callee == self.callee_fn_name {
+        match self.callee.is_method_call(i) {
             true => {
                 self.found = true;
                 *self.check_input_visitor.found = true;
@@ -347,39 +1097,6 @@ impl VisitMut for CallerCheckCallee<'_> {
             false => syn::visit_mut::visit_expr_method_call_mut(self, i),
         }
     }
-
-    fn visit_local_mut(&mut self, i: &mut Local) {
-        // // println!("decl mut: {}", i.clone().into_token_stream().to_string());
-        match &mut i.pat {
-            Pat::Ident(id) => match &id.mutability {
-                None => (),
-                Some(_) => {
-                    // // println!(
-                    //     "decl mut: {}",
-                    //     id.ident.clone().into_token_stream().to_string()
-                    // );
-                    self.decl_mut
-                        .push(id.ident.clone().into_token_stream().to_string());
-                }
-            },
-            Pat::Type(t) => match t.pat.as_ref() {
-                Pat::Ident(id) => match id.mutability {
-                    None => (),
-                    Some(_) => {
-                        // // println!(
-                        //     "decl mut: {}",
-                        //     id.ident.clone().into_token_stream().to_string()
-                        // );
-                        self.decl_mut
-                            .push(id.ident.clone().into_token_stream().to_string());
-                    }
-                },
-                _ => (),
-            },
-            _ => (),
-        };
-        syn::visit_mut::visit_local_mut(self, i)
-    }
 }
 
 struct CallerCheckInput<'a> {
@@ -427,10 +1144,9 @@ impl VisitMut for CallerCheckInput<'_> {
 }
 
 struct CallerHelper<'a> {
-    caller_fn_name: &'a str,
-    callee_fn_name: &'a str,
+    caller: &'a FnId,
+    callee: &'a CallSites,
     callee_inputs: &'a Vec<String>,
-    decl_mut: &'a mut Vec<String>,
     make_ref: &'a mut Vec<String>, // must be ref (not deciding whether immutable/mut yet
     use_after: &'a mut Vec<String>,
     found: bool,
@@ -438,34 +1154,32 @@ struct CallerHelper<'a> {
 
 impl VisitMut for CallerHelper<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             false => (),
-            true => self.caller_checker(&mut i.sig, &mut i.block),
+            true => self.caller_checker(&mut i.block),
         }
         syn::visit_mut::visit_impl_item_method_mut(self, i);
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             false => (),
-            true => self.caller_checker(&mut i.sig, &mut i.block),
+            true => self.caller_checker(&mut i.block),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
                     .default
                     .as_mut()
-                    .and_then(|block| Some(self.caller_checker(&mut i.sig, block)));
+                    .and_then(|block| Some(self.caller_checker(block)));
             }
         }
         syn::visit_mut::visit_trait_item_method_mut(self, i);
@@ -473,22 +1187,9 @@ impl VisitMut for CallerHelper<'_> {
 }
 
 impl CallerHelper<'_> {
-    fn caller_checker(&mut self, sig: &mut Signature, block: &mut Block) {
+    fn caller_checker(&mut self, block: &mut Block) {
         self.found = true;
         //println!("found the caller");
-        sig.inputs.clone().iter().for_each(|input| match input {
-            FnArg::Receiver(_) => (),
-            FnArg::Typed(t) => match t.ty.as_ref() {
-                Type::Reference(r) => match r.mutability {
-                    None => (),
-                    Some(_) => self
-                        .decl_mut
-                        .push(t.pat.as_ref().into_token_stream().to_string()),
-                },
-                _ => (),
-            },
-        });
-
         let mut check_input = CallerCheckInput {
             input: &self.callee_inputs,
             found: &mut true,
@@ -505,8 +1206,7 @@ impl CallerHelper<'_> {
             use_after: &mut temp_use_after,
         };
         let mut check_callee = CallerCheckCallee {
-            callee_fn_name: self.callee_fn_name,
-            decl_mut: self.decl_mut,
+            callee: self.callee,
             found: false,
             check_input_visitor: &mut check_input_temp,
         };
@@ -532,249 +1232,24 @@ impl CallerHelper<'_> {
     }
 }
 
-struct MutBorrowLHSChecker<'a> {
-    make_mut: &'a mut Vec<String>,
-    make_ref: &'a mut Vec<String>,
-    ref_inputs: &'a Vec<String>,
-    callee_inputs: &'a Vec<String>,
-}
-
-impl VisitMut for MutBorrowLHSChecker<'_> {
-    fn visit_expr_mut(&mut self, i: &mut Expr) {
-        let id = i.clone().into_token_stream().to_string();
-        match self.make_ref.contains(&id) || self.ref_inputs.contains(&id) {
-            true => self.make_mut.push(id),
-            false => {
-                if self.callee_inputs.contains(&id) {
-                    self.make_mut.push(id);
-                };
-                syn::visit_mut::visit_expr_mut(self, i);
-            }
-        }
-    }
-}
-
-struct ReceiverHelper<'a> {
-    check_match: &'a Vec<String>,
-    found: bool,
-    exprs: &'a mut Vec<String>,
-}
-
-impl VisitMut for ReceiverHelper<'_> {
-    fn visit_expr_mut(&mut self, i: &mut Expr) {
-        let id = i.clone().into_token_stream().to_string();
-        match self.check_match.contains(&id) {
-            false => syn::visit_mut::visit_expr_mut(self, i),
-            true => {
-                self.found = true;
-                self.exprs.push(id);
-            }
-        }
-    }
-}
-
-struct MutableBorrowerHelper<'a> {
-    make_ref: &'a mut Vec<String>,
-    make_mut: &'a mut Vec<String>,
-    ref_inputs: &'a Vec<String>,
-    decl_mut: &'a mut Vec<String>,
-    callee_inputs: &'a Vec<String>,
-    mut_methods: &'a Vec<ExprMethodCall>,
-}
-
-impl VisitMut for MutableBorrowerHelper<'_> {
-    fn visit_expr_assign_mut(&mut self, i: &mut ExprAssign) {
-        let id = i.left.clone().into_token_stream().to_string();
-        match self.make_ref.contains(&id) {
-            true => self.make_mut.push(id),
-            false => {
-                let mut lhs_checker = MutBorrowLHSChecker {
-                    make_mut: self.make_mut,
-                    make_ref: self.make_ref,
-                    ref_inputs: self.ref_inputs,
-                    callee_inputs: self.callee_inputs,
-                };
-                lhs_checker.visit_expr_mut(&mut i.left.clone());
-            }
-        }
-    }
-
-    fn visit_expr_assign_op_mut(&mut self, i: &mut ExprAssignOp) {
-        let id = i.left.clone().into_token_stream().to_string();
-        match self.make_ref.contains(&id) {
-            true => self.make_mut.push(id),
-            false => {
-                let mut lhs_checker = MutBorrowLHSChecker {
-                    make_mut: self.make_mut,
-                    make_ref: self.make_ref,
-                    ref_inputs: self.ref_inputs,
-                    callee_inputs: self.callee_inputs,
-                };
-                lhs_checker.visit_expr_mut(&mut i.left.clone());
-            }
-        }
-    }
-
-    fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let id = i.receiver.as_ref().into_token_stream().to_string();
-        debug!(
-            "call decl, {:?}, id: {}, {}",
-            i.receiver,
-            id,
-            i.clone().into_token_stream().to_string()
-        );
-        let mut input_exprs = vec![];
-        let mut input_in_receiver = ReceiverHelper {
-            check_match: self.callee_inputs,
-            found: false,
-            exprs: &mut input_exprs,
-        };
-        input_in_receiver.visit_expr_mut(i.receiver.as_mut());
-        match self.decl_mut.contains(&id) || input_in_receiver.found {
-            true => self.mut_methods.clone().iter_mut().for_each(|mut_call| {
-                if i.clone().method == mut_call.method {
-                    let mut mut_exprs = vec![];
-                    let mut mut_methods_receiver = ReceiverHelper {
-                        check_match: &input_exprs,
-                        found: false,
-                        exprs: &mut mut_exprs,
-                    };
-                    mut_methods_receiver.visit_expr_mut(mut_call.receiver.as_mut());
-                    for x in mut_exprs {
-                        self.make_mut.push(x.clone())
-                    }
-                }
-            }),
-            false => (),
-        }
-        syn::visit_mut::visit_expr_method_call_mut(self, i)
-    }
-
-    fn visit_expr_reference_mut(&mut self, i: &mut ExprReference) {
-        match &mut i.mutability {
-            None => {}
-            Some(_) => {
-                let id = i.expr.clone().into_token_stream().to_string();
-                match self.make_ref.contains(&id) {
-                    true => self.make_mut.push(id),
-                    false => {
-                        let mut lhs_checker = MutBorrowLHSChecker {
-                            make_mut: self.make_mut,
-                            make_ref: self.make_ref,
-                            ref_inputs: self.ref_inputs,
-                            callee_inputs: self.callee_inputs,
-                        };
-                        lhs_checker.visit_expr_mut(i.expr.as_mut());
-                    }
-                }
-            }
-        }
-    }
-}
-
-struct MutableBorrower<'a> {
-    fn_name: &'a str,
-    make_ref: &'a mut Vec<String>,
-    make_mut: &'a mut Vec<String>,
-    decl_mut: &'a mut Vec<String>,
-    ref_inputs: &'a Vec<String>,
-    callee_inputs: &'a Vec<String>,
-    mut_methods: &'a Vec<ExprMethodCall>,
-}
-
-impl VisitMut for MutableBorrower<'_> {
-    fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name {
-            false => (),
-            true => {
-                let mut mut_borrower_helper = MutableBorrowerHelper {
-                    make_ref: self.make_ref,
-                    make_mut: self.make_mut,
-                    ref_inputs: self.ref_inputs,
-                    decl_mut: self.decl_mut,
-                    callee_inputs: self.callee_inputs,
-                    mut_methods: self.mut_methods,
-                };
-                i.block
-                    .stmts
-                    .iter_mut()
-                    .for_each(|stmt| mut_borrower_helper.visit_stmt_mut(stmt))
-            }
-        }
-        syn::visit_mut::visit_impl_item_method_mut(self, i);
-    }
-
-    fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name {
-            false => (),
-            true => {
-                let mut mut_borrower_helper = MutableBorrowerHelper {
-                    make_ref: self.make_ref,
-                    make_mut: self.make_mut,
-                    ref_inputs: self.ref_inputs,
-                    decl_mut: self.decl_mut,
-                    callee_inputs: self.callee_inputs,
-                    mut_methods: self.mut_methods,
-                };
-                i.block
-                    .stmts
-                    .iter_mut()
-                    .for_each(|stmt| mut_borrower_helper.visit_stmt_mut(stmt))
-            }
-        }
-        syn::visit_mut::visit_item_fn_mut(self, i);
-    }
-
-    fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name {
-            false => (),
-            true => {
-                let mut mut_borrower_helper = MutableBorrowerHelper {
-                    make_ref: self.make_ref,
-                    make_mut: self.make_mut,
-                    ref_inputs: self.ref_inputs,
-                    decl_mut: self.decl_mut,
-                    callee_inputs: self.callee_inputs,
-                    mut_methods: self.mut_methods,
-                };
-                let _ = i.default.as_mut().and_then(|block| {
-                    Some(
-                        block
-                            .stmts
-                            .iter_mut()
-                            .for_each(|stmt| mut_borrower_helper.visit_stmt_mut(stmt)),
-                    )
-                });
-            }
-        }
-        syn::visit_mut::visit_trait_item_method_mut(self, i);
-    }
-}
-
 struct CallerFnArgHelper<'a> {
-    callee_fn_name: &'a str,
+    callee: &'a CallSites,
     mut_ref_inputs: &'a Vec<String>,
     ref_inputs: &'a Vec<String>,
-    // decl_mut: &'a Vec<String>,
     make_ref: &'a Vec<String>,
     make_mut: &'a Vec<String>,
 }
 
 impl VisitMut for CallerFnArgHelper<'_> {
     fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
-        let callee = i.func.as_ref().into_token_stream().to_string();
-        match callee == self.callee_fn_name {
+        match self.callee.is_call(&i.func) {
             false => syn::visit_mut::visit_expr_call_mut(self, i),
             true => self.caller_fn_arg_helper(&mut i.args),
         }
     }
 
     fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let callee = i.method.clone().into_token_stream().to_string();
-        match callee == self.callee_fn_name {
+        match self.callee.is_method_call(i) {
             true => self.caller_fn_arg_helper(&mut i.args),
             false => syn::visit_mut::visit_expr_method_call_mut(self, i),
         }
@@ -785,7 +1260,10 @@ impl CallerFnArgHelper<'_> {
     fn caller_fn_arg_helper(&mut self, args: &mut Punctuated<Expr, Token![,]>) {
         args.iter_mut().for_each(|arg| {
             let id = arg.into_token_stream().to_string();
-            match self.make_mut.contains(&id) && (!self.mut_ref_inputs.contains(&id)) {
+            // the references of the caller are passed as they are
+            match self.make_mut.contains(&id)
+                && !(self.ref_inputs.contains(&id) || self.mut_ref_inputs.contains(&id))
+            {
                 true => {
                     *arg = syn::parse_quote! {&mut #arg};
                 }
@@ -803,10 +1281,9 @@ impl CallerFnArgHelper<'_> {
 }
 
 struct CallerFnArg<'a> {
-    caller_fn_name: &'a str,
+    caller: &'a FnId,
     callee_finder: &'a mut FindCallee<'a>,
-    callee_fn_name: &'a str,
-    decl_mut: &'a Vec<String>,
+    callee: &'a CallSites,
     ref_inputs: &'a Vec<String>,
     mut_ref_inputs: &'a Vec<String>,
     make_ref: &'a Vec<String>,
@@ -819,18 +1296,16 @@ impl VisitMut for CallerFnArg<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_impl_item_method_mut(i);
                 if !self.callee_finder.found {
                     return;
                 }
                 let mut helper = CallerFnArgHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    callee: self.callee,
                     mut_ref_inputs: self.mut_ref_inputs,
                     ref_inputs: self.ref_inputs,
-                    // decl_mut: self.decl_mut,
                     make_ref: self.make_ref,
                     make_mut: self.make_mut,
                 };
@@ -850,18 +1325,16 @@ impl VisitMut for CallerFnArg<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_trait_item_method_mut(i);
                 if !self.callee_finder.found {
                     return;
                 }
                 let mut helper = CallerFnArgHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    callee: self.callee,
                     mut_ref_inputs: self.mut_ref_inputs,
                     ref_inputs: self.ref_inputs,
-                    // decl_mut: self.decl_mut,
                     make_ref: self.make_ref,
                     make_mut: self.make_mut,
                 };
@@ -884,8 +1357,7 @@ impl VisitMut for CallerFnArg<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 self.callee_finder.visit_item_fn_mut(i);
@@ -894,10 +1366,9 @@ impl VisitMut for CallerFnArg<'_> {
                 }
 
                 let mut helper = CallerFnArgHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    callee: self.callee,
                     mut_ref_inputs: self.mut_ref_inputs,
                     ref_inputs: self.ref_inputs,
-                    // decl_mut: self.decl_mut,
                     make_ref: self.make_ref,
                     make_mut: self.make_mut,
                 };
@@ -907,11 +1378,12 @@ impl VisitMut for CallerFnArg<'_> {
                     .for_each(|stmt| helper.visit_stmt_mut(stmt))
             }
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 }
 
 struct PreExtracter<'a> {
-    caller_fn_name: &'a str,
+    caller: &'a FnId,
     callee_finder: &'a mut FindCallee<'a>,
     inputs: &'a Vec<String>,
     ref_inputs: &'a Vec<String>,
@@ -919,6 +1391,21 @@ struct PreExtracter<'a> {
     use_after: &'a Vec<String>,
 }
 
+/// The variables `i` writes to, directly or through any reference aliasing
+/// them, e.g. `v` in `let r = &mut v; r.push(x)`.
+fn run_mut_analysis(i: &ItemFn, mut_methods: &MutMethods) -> Vec<String> {
+    let annot_ast = rem_utils::annotation::annotate_ast(i);
+    let (_, _, bindings) = &annot_ast;
+    MutConstraints::solve(&MutConstraints::collect_with(&annot_ast, mut_methods))
+        .into_iter()
+        .filter_map(|constraint| match constraint {
+            MutConstraints::Mut(l) => bindings.get(&l).map(|ident| ident.to_string()),
+            _ => None,
+        })
+        .unique()
+        .collect()
+}
+
 fn run_alias_analysis(
     i: &mut ItemFn,
     inputs: &Vec<String>,
@@ -932,26 +1419,19 @@ fn run_alias_analysis(
 
     cs.add_constraint::<AliasConstraints>();
 
-    cs.analyze(&annot_ast);
+    if let Err(e) = cs.analyze(&annot_ast) {
+        debug!("alias analysis failed: {}", e);
+        return;
+    }
     let constraints = cs.get_constraints::<AliasConstraints>();
     let constraints: Vec<AliasConstraints> = constraints.into_iter().unique().collect();
 
-    let mut lookup = HashMap::new();
-    let lookup_str: String = fs::read_to_string(rem_utils::annotation::LOOKUP_FILE)
-        .unwrap()
-        .parse()
-        .unwrap();
-    let re_lookup = Regex::new(r"(?P<label>\S+) -> (?P<expr>\S+)").unwrap();
-    lookup_str.split("\n").for_each(|x| {
-        let lookup_inner = re_lookup.captures_iter(x);
-        for captured in lookup_inner {
-            // // println!(
-            //     "label: {:?} -> expr: {:?}",
-            //     &captured["label"], &captured["expr"]
-            // );
-            lookup.insert(captured["label"].to_string(), captured["expr"].to_string());
-        }
-    });
+    // the variables the labels were made for
+    let (_, _, bindings) = &annot_ast;
+    let lookup: HashMap<Label, String> = bindings
+        .iter()
+        .map(|(label, ident)| (*label, ident.to_string()))
+        .collect();
 
     for constraint in constraints {
         match constraint {
@@ -959,17 +1439,17 @@ fn run_alias_analysis(
                 // // println!(
                 //     "{}, {:?} -> {:?}",
                 //     constraint,
-                //     lookup.get(l.to_string().as_str()),
-                //     lookup.get(r.to_string().as_str())
+                //     lookup.get(&l),
+                //     lookup.get(&r)
                 // );
-                match lookup.get(r.to_string().as_str()) {
+                match lookup.get(&r) {
                     None => {}
                     Some(expr_r) => {
                         if inputs.contains(&expr_r.trim().to_string())
                             || ref_inputs.contains(&expr_r.trim().to_string())
                         {
                             // // println!("r is in input");
-                            match lookup.get(l.to_string().as_str()) {
+                            match lookup.get(&l) {
                                 None => {}
                                 Some(expr_l) => {
                                     let id = expr_l.trim().to_string();
@@ -996,8 +1476,7 @@ impl VisitMut for PreExtracter<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_impl_item_method_mut(i);
                 if !self.callee_finder.found {
@@ -1026,8 +1505,7 @@ impl VisitMut for PreExtracter<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_trait_item_method_mut(i);
                 if !self.callee_finder.found {
@@ -1057,8 +1535,7 @@ impl VisitMut for PreExtracter<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id == self.caller_fn_name {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_item_fn_mut(i);
                 if !self.callee_finder.found {
@@ -1074,122 +1551,297 @@ impl VisitMut for PreExtracter<'_> {
             }
             false => (),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 }
 
 pub struct BorrowResult {
-    pub success: bool,
     pub make_mut: Vec<String>,
     pub make_ref: Vec<String>,
 }
 
-pub fn inner_make_borrows(
-    file_name: &str,
-    new_file_name: &str,
-    mut_method_call_expr_file: &str,
+/// The places under `root` the callee should take one by one rather than the
+/// whole of `root`: when the caller still holds a borrow of a place under it
+/// across the call that lending or moving the whole would conflict with, or
+/// has moved a place out of it.
+fn split_places(
+    file: &mut syn::File,
+    callee: &FnId,
+    caller: &FnId,
+    call_sites: &CallSites,
+    root: &Root,
+    mut_methods: &MutMethods,
+    copy_types: &CopyTypes,
+) -> Option<Vec<SplitPlace>> {
+    let mut uses = PlaceUses {
+        root: root.name.as_str(),
+        places: vec![],
+        whole: false,
+        moves: false,
+    };
+    InFn {
+        id: callee,
+        visitor: &mut uses,
+    }
+    .visit_file_mut(file);
+    if uses.whole || uses.places.is_empty() {
+        return None;
+    }
+    let mut live = LiveBorrows {
+        callee: call_sites,
+        root: root.name.as_str(),
+        live: vec![],
+        moved: false,
+    };
+    InFn {
+        id: caller,
+        visitor: &mut live,
+    }
+    .visit_file_mut(file);
+    debug!(
+        "places of {} borrowed across the call: {:?}",
+        root.name, live.live
+    );
+    let conflicts = |place: &[String], is_mut: bool, by_value: bool| {
+        live.live
+            .iter()
+            .any(|(live, mutable)| overlaps(place, live) && (is_mut || by_value || *mutable))
+    };
+    if !live.moved && !conflicts(&[], root.is_mut, root.by_value) {
+        return None;
+    }
+
+    // as deep as the structs of the file tell the types, without overlaps
+    let mut places: Vec<(Vec<String>, Type)> = vec![];
+    for place in &uses.places {
+        let (place, ty) = place_type(&file.items, &root.ty, place)?;
+        if !places.iter().any(|(p, _)| p == &place) {
+            places.push((place, ty))
+        }
+    }
+    let outermost: Vec<(Vec<String>, Type)> = places
+        .iter()
+        .filter(|(p, _)| {
+            !places
+                .iter()
+                .any(|(q, _)| q.len() < p.len() && p.starts_with(q))
+        })
+        .cloned()
+        .collect();
+
+    let mut mutations = PlaceMutations {
+        root: root.name.as_str(),
+        mut_methods,
+        mutated: vec![],
+    };
+    InFn {
+        id: callee,
+        visitor: &mut mutations,
+    }
+    .visit_file_mut(file);
+    let mut moves = PlaceMoves {
+        root: root.name.as_str(),
+        moved: vec![],
+    };
+    InFn {
+        id: callee,
+        visitor: &mut moves,
+    }
+    .visit_file_mut(file);
+    let mut names = Names { names: vec![] };
+    InFn {
+        id: callee,
+        visitor: &mut names,
+    }
+    .visit_file_mut(file);
+    let mut fresh = |stem: String| {
+        let mut name = stem.clone();
+        let mut n = 1;
+        while names.names.contains(&name) {
+            name = format!("{}{}", stem, n);
+            n += 1;
+        }
+        names.names.push(name.clone());
+        format_ident!("{}", name)
+    };
+    let split: Vec<SplitPlace> = outermost
+        .into_iter()
+        .map(|(place, ty)| {
+            let is_mut = mutations.mutated.iter().any(|m| overlaps(m, &place));
+            // a place moved out, or out of, is handed over whatever its type
+            let moved = !copy_types.is_copy(&ty) && moves.moved.iter().any(|m| overlaps(m, &place));
+            SplitPlace {
+                param: fresh(match root.name.as_str() {
+                    "self" => place.join("_"),
+                    name => format!("{}_{}", name, place.join("_")),
+                }),
+                // a `Copy` place only read is copied rather than lent
+                by_value: root.by_value || moved || (!is_mut && copy_types.is_copy(&ty)),
+                is_mut,
+                place,
+                ty,
+            }
+        })
+        .collect();
+    // copying a place out conflicts with what lending it would
+    match split
+        .iter()
+        .any(|s| conflicts(&s.place, s.is_mut, s.by_value && !copy_types.is_copy(&s.ty)))
+    {
+        true => None,
+        false => Some(split),
+    }
+}
+
+/// Make the callee borrow its inputs, in place on a parsed file. `pre_extract_file`
+/// is the file before extraction, with the callee's code still in the caller.
+pub fn make_borrows_file(
+    file: &mut syn::File,
+    pre_extract_file: &syn::File,
+    mut_methods: &MutMethods,
+    copy_types: &CopyTypes,
     callee_fn_name: &str,
     caller_fn_name: &str,
-    pre_extract_file_name: &str,
-) -> BorrowResult {
-    let pre_extract: String = fs::read_to_string(&pre_extract_file_name)
-        .unwrap()
-        .parse()
-        .unwrap();
-
-    let mut pre_extract_file = syn::parse_str::<syn::File>(pre_extract.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
-
-    let mut_methods_content: String = fs::read_to_string(&mut_method_call_expr_file)
-        .unwrap()
-        .parse()
-        .unwrap();
-    let mut mut_methods = vec![];
-    for call in mut_methods_content.split("\n") {
-        match syn::parse_str::<syn::ExprMethodCall>(call).map_err(|e| format!("{:?}", e)) {
-            Ok(call) => mut_methods.push(call),
-            Err(_) => (),
-        }
-    }
-
-    let file_content: String = fs::read_to_string(&file_name).unwrap().parse().unwrap();
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
+) -> Result<BorrowResult, Error> {
+    let caller = resolve_fn(file, caller_fn_name)?
+        .ok_or_else(|| Error::CallerNotFound(caller_fn_name.to_string()))?;
+    let callee = resolve_fn(file, callee_fn_name)?
+        .ok_or_else(|| Error::CalleeNotFound(callee_fn_name.to_string()))?;
+    // the caller as it was before extraction
+    let pre_extract_caller = resolve_fn(pre_extract_file, caller_fn_name)?
+        .ok_or_else(|| Error::CallerNotFound(caller_fn_name.to_string()))?;
+    let call_sites = CallSites::resolve(file, &caller, &callee);
+    let callee_fn_name = callee.name.as_str();
+    let mut mut_methods = mut_methods.clone();
+    mut_methods.add_file(file);
+    let mut_methods = &mut_methods;
+    let mut copy_types = copy_types.clone();
+    copy_types.add_file(file);
+    let copy_types = &copy_types;
+    let mut pre_extract_file = pre_extract_file.clone();
     let mut callee_inputs = vec![];
     let mut callee_ref_inputs = vec![];
     let mut callee_mut_ref_inputs = vec![];
+    let mut callee_copy_inputs = vec![];
     let mut make_ref = vec![];
     let mut callee_input_helper = CalleeInputs {
-        fn_name: callee_fn_name,
+        callee: &callee,
         inputs: &mut callee_inputs,
         refs_inputs: &mut callee_ref_inputs,
         mut_refs_inputs: &mut callee_mut_ref_inputs,
+        copy_types,
+        copy_inputs: &mut callee_copy_inputs,
         make_ref: &mut make_ref,
         found: false,
     };
-    callee_input_helper.visit_file_mut(&mut file);
+    callee_input_helper.visit_file_mut(file);
 
     if !callee_input_helper.found {
         debug!("no callee found!");
-        return BorrowResult {
-            success: false,
-            make_mut: vec![],
-            make_ref: vec![],
-        };
+        return Err(Error::CalleeNotFound(callee_fn_name.to_string()));
     }
+    // the inputs the callee returns references to
+    let mut lent = make_ref.clone();
 
     let mut use_after = vec![];
 
-    let mut decl_mut = vec![];
     let mut caller_helper = CallerHelper {
-        caller_fn_name,
-        callee_fn_name,
+        caller: &caller,
+        callee: &call_sites,
         callee_inputs: &callee_inputs,
         make_ref: &mut make_ref,
-        decl_mut: &mut decl_mut,
         use_after: &mut use_after,
         found: false,
     };
-    caller_helper.visit_file_mut(&mut file);
+    caller_helper.visit_file_mut(file);
 
     if !caller_helper.found {
         debug!("no caller found!");
-        return BorrowResult {
-            success: false,
-            make_mut: vec![],
-            make_ref: vec![],
-        };
+        return Err(Error::CallerNotFound(caller_fn_name.to_string()));
     }
 
+    // the callee is not defined in the file before extraction
+    let pre_extract_call_sites = CallSites::named(callee_fn_name);
     let mut callee_finder = FindCallee {
         found: false,
-        callee_fn_name,
+        callee: &pre_extract_call_sites,
     };
 
+    let mut aliased = vec![];
     let mut constraint_visitor = PreExtracter {
-        caller_fn_name,
+        caller: &pre_extract_caller,
         callee_finder: &mut callee_finder,
         inputs: &callee_inputs,
         ref_inputs: &callee_ref_inputs,
-        make_ref: &mut make_ref,
+        make_ref: &mut aliased,
         use_after: &use_after,
     };
     constraint_visitor.visit_file_mut(&mut pre_extract_file);
+    make_ref.extend(aliased.iter().cloned());
+    lent.extend(aliased);
+
+    // the inputs the callee writes to, directly or through an alias of them,
+    // e.g. `v` in `let r = &mut v; r.push(x)`, and the `&T` ones it writes
+    // through, which become `&mut T`
+    let mut callee_fn = CalleeFn {
+        id: &callee,
+        found: None,
+    };
+    callee_fn.visit_file_mut(file);
+    let written = callee_fn
+        .found
+        .map(|fun| run_mut_analysis(&fun, mut_methods))
+        .unwrap_or_default();
+    let mut make_mut: Vec<String> = written
+        .iter()
+        .filter(|var| callee_inputs.contains(var) || callee_ref_inputs.contains(var))
+        .cloned()
+        .collect();
+    debug!("written inputs: {:?}", make_mut);
+
+    // an input used after the call only needs lending if it is not `Copy`,
+    // or if the callee writes to it or hands out a reference to it
+    make_ref.retain(|input| {
+        let copied = callee_copy_inputs.contains(input)
+            && !make_mut.contains(input)
+            && !lent.contains(input);
+        if copied {
+            debug!("passing {} by value, it is Copy", input);
+        }
+        !copied
+    });
 
-    let mut make_mut = vec![];
-    let mut mut_borrower = MutableBorrower {
-        fn_name: callee_fn_name,
-        make_ref: &mut make_ref,
-        make_mut: &mut make_mut,
-        decl_mut: &mut decl_mut,
-        ref_inputs: &callee_ref_inputs,
-        callee_inputs: &callee_inputs,
-        mut_methods: &mut_methods,
+    // `self` is one more input, borrowed through the receiver
+    let mut self_uses = PlaceUses {
+        root: "self",
+        places: vec![],
+        whole: false,
+        moves: false,
+    };
+    InFn {
+        id: &callee,
+        visitor: &mut self_uses,
+    }
+    .visit_file_mut(file);
+    let mut self_mutations = PlaceMutations {
+        root: "self",
+        mut_methods,
+        mutated: vec![],
+    };
+    InFn {
+        id: &callee,
+        visitor: &mut self_mutations,
+    }
+    .visit_file_mut(file);
+    let mut receiver_assigner = ReceiverAssigner {
+        callee: &callee,
+        is_mut: !self_mutations.mutated.is_empty() || written.iter().any(|var| var == "self"),
+        moves: self_uses.moves,
     };
-    mut_borrower.visit_file_mut(&mut file);
+    receiver_assigner.visit_file_mut(file);
+
     let mut callee_assigner = CalleeBorrowAssigner {
-        fn_name: callee_fn_name,
+        callee: &callee,
         make_ref: &make_ref,
         make_mut: &make_mut,
         ref_inputs: &callee_ref_inputs,
@@ -1202,48 +1854,149 @@ pub fn inner_make_borrows(
     // for s in &make_mut {
     //     // println!("make {} mut", s);
     // }
-    callee_assigner.visit_file_mut(&mut file);
+    callee_assigner.visit_file_mut(file);
 
     callee_finder = FindCallee {
         found: false,
-        callee_fn_name,
+        callee: &call_sites,
     };
 
     let mut caller_assigner = CallerFnArg {
-        caller_fn_name,
+        caller: &caller,
         callee_finder: &mut callee_finder,
-        callee_fn_name,
-        decl_mut: &decl_mut,
+        callee: &call_sites,
         ref_inputs: &callee_ref_inputs,
         mut_ref_inputs: &callee_mut_ref_inputs,
         make_ref: &make_ref,
         make_mut: &make_mut,
     };
-    caller_assigner.visit_file_mut(&mut file);
-    let file = file.into_token_stream().to_string();
-    fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
-    BorrowResult {
-        success: true,
-        make_mut,
-        make_ref,
+    caller_assigner.visit_file_mut(file);
+
+    // an input used through its fields may be lent place by place instead
+    let mut callee_sig = CalleeSig {
+        callee: &callee,
+        self_ty: None,
+        found: None,
+    };
+    callee_sig.visit_file_mut(file);
+    let callee_roots = match callee_sig.found {
+        Some((sig, self_ty)) => roots(&sig, self_ty),
+        None => vec![],
+    };
+    // the last first, so that splitting does not move the others
+    for root in callee_roots.iter().rev() {
+        let places = match split_places(
+            file,
+            &callee,
+            &caller,
+            &call_sites,
+            root,
+            mut_methods,
+            copy_types,
+        ) {
+            Some(places) => places,
+            None => continue,
+        };
+        make_ref.retain(|name| name != &root.name);
+        make_mut.retain(|name| name != &root.name);
+        for split in places.iter().filter(|split| !split.by_value) {
+            let place = format!("{}.{}", root.name, split.place.join("."));
+            debug!("lending {} instead of {}", place, root.name);
+            match split.is_mut {
+                true => make_mut.push(place),
+                false => make_ref.push(place),
+            }
+        }
+        let mut place_splitter = PlaceSplitter {
+            callee: &callee,
+            root,
+            places: &places,
+        };
+        place_splitter.visit_file_mut(file);
+        let mut call_splitter = CallSplitter {
+            callee: &call_sites,
+            root,
+            places: &places,
+        };
+        InFn {
+            id: &caller,
+            visitor: &mut call_splitter,
+        }
+        .visit_file_mut(file);
     }
+    Ok(BorrowResult { make_mut, make_ref })
+}
+
+/// Same as `make_borrows_file` on source strings.
+pub fn make_borrows_source(
+    source: &str,
+    pre_extract_source: &str,
+    mut_methods: &MutMethods,
+    copy_types: &CopyTypes,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+) -> Result<(String, BorrowResult), Error> {
+    let pre_extract_file = syn::parse_str::<syn::File>(pre_extract_source)?;
+    let mut file = syn::parse_str::<syn::File>(source)?;
+    let res = make_borrows_file(
+        &mut file,
+        &pre_extract_file,
+        mut_methods,
+        copy_types,
+        callee_fn_name,
+        caller_fn_name,
+    )?;
+    let file = file.into_token_stream().to_string();
+    Ok((splice(source, &format_source(&file)), res))
+}
+
+/// Same as `inner_make_borrows` on any file system, e.g. a
+/// `SymbolicFileSystem` holding the sources in memory.
+pub fn make_borrows_fs<S: FileSystem>(
+    fs: &S,
+    file_name: &str,
+    new_file_name: &str,
+    mut_methods: &MutMethods,
+    copy_types: &CopyTypes,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+    pre_extract_file_name: &str,
+) -> Result<BorrowResult, Error>
+where
+    Error: From<S::FSError>,
+{
+    let pre_extract = fs.read(pre_extract_file_name)?;
+    let file_content = fs.read(file_name)?;
+    let (new_file_content, res) = make_borrows_source(
+        file_content.as_str(),
+        pre_extract.as_str(),
+        mut_methods,
+        copy_types,
+        callee_fn_name,
+        caller_fn_name,
+    )?;
+    fs.write(new_file_name, new_file_content)?;
+    Ok(res)
 }
 
-pub fn make_borrows(
+/// Make the callee borrow its inputs. Which methods take `&mut self` is
+/// known from the standard library and the methods of the file. Which types
+/// are `Copy` is known from the primitives and the types of the file.
+pub fn inner_make_borrows(
     file_name: &str,
     new_file_name: &str,
-    mut_method_call_expr_file: &str,
     callee_fn_name: &str,
     caller_fn_name: &str,
     pre_extract_file_name: &str,
-) -> bool {
-    inner_make_borrows(
+) -> Result<BorrowResult, Error> {
+    make_borrows_fs(
+        &RealFileSystem,
         file_name,
         new_file_name,
-        mut_method_call_expr_file,
+        &MutMethods::std(),
+        &CopyTypes::default(),
         callee_fn_name,
         caller_fn_name,
         pre_extract_file_name,
     )
-    .success
 }
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use proc_macro2::Ident;
+use syn::visit::Visit;
+use syn::{Attribute, ItemEnum, ItemImpl, ItemStruct, ItemUnion, Meta, NestedMeta, Type};
+
+use rem_utils::error::Error;
+use rem_utils::mutability::crate_files;
+use rem_utils::typ::{alias_context, is_copy};
+
+/// The types of the program that are `Copy`, by name, so that the borrower
+/// can pass their values on instead of lending them.
+#[derive(Debug, Clone, Default)]
+pub struct CopyTypes {
+    names: HashSet<Ident>,
+}
+
+struct CopyImpls<'a> {
+    names: &'a mut HashSet<Ident>,
+}
+
+fn derives_copy(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("derive"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|derived| match derived {
+            NestedMeta::Meta(Meta::Path(path)) => {
+                path.segments.last().is_some_and(|s| s.ident == "Copy")
+            }
+            _ => false,
+        })
+}
+
+impl<'ast> Visit<'ast> for CopyImpls<'_> {
+    fn visit_item_struct(&mut self, i: &'ast ItemStruct) {
+        if derives_copy(&i.attrs) {
+            self.names.insert(i.ident.clone());
+        }
+        syn::visit::visit_item_struct(self, i)
+    }
+
+    fn visit_item_enum(&mut self, i: &'ast ItemEnum) {
+        if derives_copy(&i.attrs) {
+            self.names.insert(i.ident.clone());
+        }
+        syn::visit::visit_item_enum(self, i)
+    }
+
+    fn visit_item_union(&mut self, i: &'ast ItemUnion) {
+        if derives_copy(&i.attrs) {
+            self.names.insert(i.ident.clone());
+        }
+        syn::visit::visit_item_union(self, i)
+    }
+
+    // `impl Copy for T {}`
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        let implements_copy = i.trait_.as_ref().is_some_and(|(negated, path, _)| {
+            negated.is_none() && path.segments.last().is_some_and(|s| s.ident == "Copy")
+        });
+        if let (true, Type::Path(ty)) = (implements_copy, i.self_ty.as_ref()) {
+            if let Some(segment) = ty.path.segments.last() {
+                self.names.insert(segment.ident.clone());
+            }
+        }
+        syn::visit::visit_item_impl(self, i)
+    }
+}
+
+impl CopyTypes {
+    pub fn is_copy(&self, ty: &Type) -> bool {
+        is_copy(ty, &self.names)
+    }
+
+    /// Add the types defined in `file` deriving or implementing `Copy`, and
+    /// the aliases of `Copy` types.
+    pub fn add_file(&mut self, file: &syn::File) {
+        CopyImpls {
+            names: &mut self.names,
+        }
+        .visit_file(file);
+        let (aliases, _) = alias_context(file);
+        let copy_aliases: Vec<Ident> = aliases
+            .into_iter()
+            .filter(|(_, ty)| ty.is_copy(&self.names))
+            .map(|(name, _)| name)
+            .collect();
+        self.names.extend(copy_aliases)
+    }
+
+    /// Add the `Copy` types of the sources of the crate of `manifest_path`.
+    /// Sources that do not parse are skipped.
+    pub fn add_crate(&mut self, manifest_path: &str) -> Result<(), Error> {
+        crate_files(manifest_path)?
+            .iter()
+            .for_each(|file| self.add_file(file));
+        Ok(())
+    }
+}
@@ -1 +1,3 @@
 pub mod borrow;
+pub mod copy;
+pub use rem_utils::mutability;
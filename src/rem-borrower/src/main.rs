@@ -1,8 +1,8 @@
-mod borrow;
-
 use clap::{Parser, Subcommand};
 use colored::Colorize;
+use rem_borrower::borrow;
 use rem_utils::compile_file;
+use std::process::exit;
 use std::time::SystemTime;
 use std::{env, fs};
 
@@ -18,7 +18,6 @@ enum Commands {
     Run {
         file_name: String,
         new_file_name: String,
-        mut_method_call_expr_file: String,
         caller_fn_name: String,
         callee_fn_name: String,
         pre_extract_file_name: String,
@@ -36,19 +35,20 @@ fn main() {
         Commands::Run {
             file_name,
             new_file_name,
-            mut_method_call_expr_file,
             caller_fn_name,
             callee_fn_name,
             pre_extract_file_name,
         } => {
-            let _ = borrow::make_borrows(
+            if let Err(e) = borrow::inner_make_borrows(
                 file_name.as_str(),
                 new_file_name.as_str(),
-                mut_method_call_expr_file.as_str(),
                 callee_fn_name.as_str(),
                 caller_fn_name.as_str(),
                 pre_extract_file_name.as_str(),
-            );
+            ) {
+                eprintln!("{}", e);
+                exit(1)
+            }
         }
     }
 }
@@ -61,16 +61,13 @@ fn test() {
         }
         let file_name = format!("./input/{}", test_name.to_str().unwrap());
         let new_file_name = format!("./output/{}", test_name.to_str().unwrap());
-        let mut_method_call_expr_file =
-            format!("./method_call_mut/{}", test_name.to_str().unwrap());
         let pre_extract_file_name = format!("./pre_extract/{}", test_name.to_str().unwrap());
         let callee_fn_name = "bar";
         let caller_fn_name = "new_foo";
         let now = SystemTime::now();
-        borrow::make_borrows(
+        let _ = borrow::inner_make_borrows(
             file_name.as_str(),
             new_file_name.as_str(),
-            mut_method_call_expr_file.as_str(),
             callee_fn_name,
             caller_fn_name,
             pre_extract_file_name.as_str(),
@@ -0,0 +1,222 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+use std::time::SystemTime;
+
+use rem_cli::pipeline::{run_extraction, run_extraction_source, Extraction};
+use rem_controller::non_local_controller::ControlEnum;
+use rem_extractor::extract::{CalleeVisibility, Placement, Position};
+use rem_repairer::common::RepairSystem;
+use rem_repairer::{
+    repair_lifetime_loosest_bound_first, repair_lifetime_simple,
+    repair_lifetime_tightest_bound_first,
+};
+use rem_utils::edit::{unified_diff, TextEdit};
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Fix up a naive extraction: non-local control flow, borrows and lifetimes
+    Extract {
+        file_name: String,
+        caller_fn_name: String,
+        callee_fn_name: String,
+        /// Cut the callee out of the caller from here (LINE:COLUMN or byte offset)
+        /// instead of expecting FILE_NAME to already contain it
+        #[arg(long, requires = "end")]
+        start: Option<Position>,
+        /// End of the statements to cut (LINE:COLUMN or byte offset)
+        #[arg(long, requires = "start")]
+        end: Option<Position>,
+        /// Where the callee cut out of the statements goes
+        #[arg(long, value_enum, default_value_t = Placement::Auto, requires = "start")]
+        placement: Placement,
+        /// The visibility of the callee cut out of the statements
+        #[arg(long, value_enum, default_value_t = CalleeVisibility::Private, requires = "start")]
+        visibility: CalleeVisibility,
+        /// Write the result here instead of rewriting FILE_NAME
+        #[arg(short, long)]
+        output: Option<String>,
+        /// The file before extraction, defaults to FILE_NAME_ORIGINAL
+        #[arg(long)]
+        pre_extract_file_name: Option<String>,
+        /// More method calls needing `&mut self` than REM infers, if any, defaults to
+        /// FILE_NAME_MUTABLE_METHOD_CALLS
+        #[arg(long)]
+        mut_methods_file_name: Option<String>,
+        /// Repair lifetimes with `cargo check` on this manifest instead of `rustc` on the file
+        #[arg(long)]
+        manifest_path: Option<String>,
+        /// What the callee returns to tell its call sites it returned, broke or continued
+        #[arg(long, value_enum, default_value_t = ControlEnum::Dedicated)]
+        control: ControlEnum,
+        #[arg(long, value_enum, default_value_t = RepairerType::LoosestBoundsFirst)]
+        repairer: RepairerType,
+        /// Print the changes to stdout instead of writing them to the output file
+        #[arg(long, value_enum, default_value_t = Emit::File)]
+        emit: Emit,
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RepairerType {
+    Simple,
+    LoosestBoundsFirst,
+    TightestBoundsFirst,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Emit {
+    /// Write the extracted file
+    File,
+    /// Print a unified diff against FILE_NAME
+    Diff,
+    /// Print the byte ranges of FILE_NAME to replace, and their replacement
+    Json,
+}
+
+fn main() {
+    let args = Cli::parse();
+    match &args.command {
+        Commands::Extract {
+            file_name,
+            caller_fn_name,
+            callee_fn_name,
+            start,
+            end,
+            placement,
+            visibility,
+            output,
+            pre_extract_file_name,
+            mut_methods_file_name,
+            manifest_path,
+            control,
+            repairer,
+            emit,
+            verbose,
+        } => {
+            if *verbose {
+                env::set_var("RUST_LOG", "debug");
+            }
+            env_logger::init();
+            let repair_system: &dyn RepairSystem = match repairer {
+                RepairerType::Simple => &repair_lifetime_simple::Repairer {},
+                RepairerType::TightestBoundsFirst => {
+                    &repair_lifetime_tightest_bound_first::Repairer {}
+                }
+                RepairerType::LoosestBoundsFirst => {
+                    &repair_lifetime_loosest_bound_first::Repairer {}
+                }
+            };
+            let mut extraction = Extraction::new(file_name, caller_fn_name, callee_fn_name);
+            if let Some(output) = output {
+                extraction.new_file_name = output.clone();
+            }
+            if let Some(path) = pre_extract_file_name {
+                extraction.pre_extract_file_name = path.clone();
+            }
+            if let Some(path) = mut_methods_file_name {
+                extraction.mut_methods_file_name = path.clone();
+            }
+            extraction.manifest_path = manifest_path.clone();
+            extraction.control = *control;
+            if let (Some(start), Some(end)) = (start, end) {
+                extraction.selection = Some((*start, *end));
+            }
+            extraction.style.placement = *placement;
+            extraction.style.visibility = *visibility;
+
+            let source = match fs::read_to_string(file_name) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("{}: {}", format!("FAILED").red(), e);
+                    exit(1)
+                }
+            };
+
+            let now = SystemTime::now();
+            // only printing the changes, so nothing is written to disk
+            let result = match emit {
+                Emit::File => run_extraction(&extraction, repair_system),
+                _ => run_extraction_source(&source, &extraction, repair_system)
+                    .map(|(_, result)| result),
+            };
+            let time_elapsed = now.elapsed().unwrap();
+            let status = match &result {
+                Ok(_) => format!(
+                    "{}: extracted {} from {} in {:#?}",
+                    format!("PASSED").green(),
+                    callee_fn_name,
+                    caller_fn_name,
+                    time_elapsed
+                ),
+                Err(e) => format!("{}: {} in {:#?}", format!("FAILED").red(), e, time_elapsed),
+            };
+            // keep stdout for the changes
+            match emit {
+                Emit::File => println!("{}", status),
+                _ => eprintln!("{}", status),
+            }
+            let result = match result {
+                Ok(result) => result,
+                Err(_) => exit(1),
+            };
+            match emit {
+                Emit::File => (),
+                Emit::Diff => {
+                    print!("{}", unified_diff(file_name, &source, &result.edits));
+                    if let Some(root) = &result.crate_root {
+                        let root_name = relative(&root.file_name);
+                        print!("{}", unified_diff(&root_name, &root.source, &root.edits));
+                    }
+                }
+                Emit::Json => {
+                    let mut json = edits_json(file_name, &result.edits);
+                    if let Some(root) = &result.crate_root {
+                        json["crate_root"] = edits_json(&relative(&root.file_name), &root.edits);
+                    }
+                    println!("{}", serde_json::to_string_pretty(&json).unwrap());
+                }
+            }
+        }
+    }
+}
+
+fn edits_json(file_name: &str, edits: &[TextEdit]) -> serde_json::Value {
+    let edits: Vec<serde_json::Value> = edits
+        .iter()
+        .map(|edit| {
+            serde_json::json!({
+                "start": edit.start,
+                "end": edit.end,
+                "text": edit.text,
+            })
+        })
+        .collect();
+    serde_json::json!({ "file": file_name, "edits": edits })
+}
+
+/// `path` relative to the current directory if it is in it, as the other
+/// file names are given.
+fn relative(path: &str) -> String {
+    env::current_dir()
+        .and_then(fs::canonicalize)
+        .ok()
+        .and_then(|dir| {
+            Path::new(path)
+                .strip_prefix(dir)
+                .ok()
+                .map(|rel| rel.display().to_string())
+        })
+        .unwrap_or_else(|| path.to_string())
+}
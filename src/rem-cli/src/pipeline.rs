@@ -0,0 +1,466 @@
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rem_borrower::borrow::{make_borrows_source, BorrowResult};
+use rem_borrower::copy::CopyTypes;
+use rem_borrower::mutability::MutMethods;
+use rem_controller::non_local_controller::{
+    add_shared_enum, make_controls_source, ControlEnum, NonLocalControlFlowResult,
+};
+use rem_extractor::extract::{
+    extract_source, move_to_trait, CalleeStyle, ExtractResult, Placement, Position,
+};
+use rem_repairer::common::{RepairResult, RepairSystem};
+use rem_utils::edit::{apply_edits, item_edits, TextEdit};
+use rem_utils::error::Error;
+use rem_utils::filesystem::RealFileSystem;
+
+/// The stage of the pipeline an extraction failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Extractor,
+    Controller,
+    Borrower,
+    Repairer,
+    /// Moving the repaired callee to its trait, and turning the repaired file
+    /// into edits of the original
+    Output,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // same names as the `failed_at` column of the experiments
+        match self {
+            Stage::Extractor => write!(f, "extractor"),
+            Stage::Controller => write!(f, "controller"),
+            Stage::Borrower => write!(f, "borrower"),
+            Stage::Repairer => write!(f, "cargo"),
+            Stage::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// A naive extraction to be fixed up, i.e. `file_name` already contains the
+/// callee and `pre_extract_file_name` is the same file before the cut, unless
+/// `selection` is given and REM does the cut itself.
+pub struct Extraction {
+    pub file_name: String,
+    pub new_file_name: String,
+    pub caller_fn_name: String,
+    pub callee_fn_name: String,
+    pub pre_extract_file_name: String,
+    /// More method calls needing `&mut self` than REM infers, if the file
+    /// exists.
+    pub mut_methods_file_name: String,
+    /// Statements of the caller to cut out into the callee.
+    pub selection: Option<(Position, Position)>,
+    /// Where the callee cut out of the selection goes and how visible it is.
+    pub style: CalleeStyle,
+    /// Repair against the whole crate instead of compiling `new_file_name`
+    /// on its own.
+    pub manifest_path: Option<String>,
+    /// What the callee returns when it has non-local control flow.
+    pub control: ControlEnum,
+}
+
+impl Extraction {
+    pub fn new(file_name: &str, caller_fn_name: &str, callee_fn_name: &str) -> Self {
+        Extraction {
+            file_name: file_name.to_string(),
+            new_file_name: file_name.to_string(),
+            caller_fn_name: caller_fn_name.to_string(),
+            callee_fn_name: callee_fn_name.to_string(),
+            pre_extract_file_name: format!("{}_ORIGINAL", file_name),
+            mut_methods_file_name: format!("{}_MUTABLE_METHOD_CALLS", file_name),
+            selection: None,
+            style: CalleeStyle::default(),
+            manifest_path: None,
+            control: ControlEnum::default(),
+        }
+    }
+}
+
+pub struct ExtractionResult {
+    pub extractor: Option<ExtractResult>,
+    pub controller: NonLocalControlFlowResult,
+    pub borrower: BorrowResult,
+    pub repairer: RepairResult,
+    /// Edits of the items REM changed or added, against the file before the
+    /// pipeline ran. Everything else, comments included, is left as it was.
+    pub edits: Vec<TextEdit>,
+    /// The shared control enum to add to the crate root, if it is another
+    /// file and does not define it yet.
+    pub crate_root: Option<CrateRootEdits>,
+}
+
+/// Edits of the crate root, against `source`, its contents before the
+/// pipeline ran.
+pub struct CrateRootEdits {
+    pub file_name: String,
+    pub source: String,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Why an extraction was refused, and by which stage.
+#[derive(Debug)]
+pub struct StageError {
+    pub stage: Stage,
+    pub error: Error,
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed at {}: {}", self.stage, self.error)
+    }
+}
+
+fn at(stage: Stage) -> impl FnOnce(Error) -> StageError {
+    move |error| {
+        debug!("extraction failed at {}: {}", stage, error);
+        StageError { stage, error }
+    }
+}
+
+/// Cut out the selection if there is one, then run the controller, the
+/// borrower and the repairer one after the other on `e.file_name`, stopping
+/// at the first stage that fails, and write the result to `e.new_file_name`.
+/// The stages reformat the whole file, so only the items they changed end up
+/// in `e.new_file_name`.
+pub fn run_extraction(
+    e: &Extraction,
+    repairer: &dyn RepairSystem,
+) -> Result<ExtractionResult, StageError> {
+    let first_stage = match e.selection {
+        Some(_) => Stage::Extractor,
+        None => Stage::Controller,
+    };
+    let source = fs::read_to_string(&e.file_name)
+        .map_err(Error::from)
+        .map_err(at(first_stage))?;
+    let (extracted, result) = run_extraction_source(&source, e, repairer)?;
+    debug!("extracted: {}", extracted);
+    fs::write(&e.new_file_name, extracted)
+        .map_err(Error::from)
+        .map_err(at(Stage::Output))?;
+    if let Some(root) = &result.crate_root {
+        fs::write(&root.file_name, apply_edits(&root.source, &root.edits))
+            .map_err(Error::from)
+            .map_err(at(Stage::Output))?;
+    }
+    Ok(result)
+}
+
+/// Same as `run_extraction` on `source`, the contents of `e.file_name`, as an
+/// editor hands it over: nothing is written to disk, the new source is
+/// returned along with the edits turning `source` into it, and those of the
+/// crate root if it needs the shared control enum.
+/// Without `e.manifest_path`, the callee is repaired by compiling the source
+/// on its own. With it, the callee is repaired with `cargo check` in a scratch
+/// copy of the workspace, so the file on disk is never touched.
+pub fn run_extraction_source(
+    source: &str,
+    e: &Extraction,
+    repairer: &dyn RepairSystem,
+) -> Result<(String, ExtractionResult), StageError> {
+    let caller_fn_name = e.caller_fn_name.as_str();
+    let callee_fn_name = e.callee_fn_name.as_str();
+    let (extracted, extractor) = match e.selection {
+        Some((start, end)) => {
+            let (extracted, extractor) =
+                extract_source(source, caller_fn_name, callee_fn_name, start, end, e.style)
+                    .map_err(at(Stage::Extractor))?;
+            (extracted, Some(extractor))
+        }
+        None => (source.to_string(), None),
+    };
+
+    let (controlled, controller) = make_controls_source(
+        extracted.as_str(),
+        callee_fn_name,
+        caller_fn_name,
+        e.control,
+    )
+    .map_err(at(Stage::Controller))?;
+    let (controlled, crate_root) = match controller.shared_enum {
+        true => with_shared_enum(controlled, e).map_err(at(Stage::Controller))?,
+        false => (controlled, None),
+    };
+
+    let manifest_path = e.manifest_path.as_deref();
+    let (borrowed, borrower) = mut_methods(manifest_path)
+        .and_then(|mut mut_methods| {
+            mut_methods.add_calls_file(&RealFileSystem, e.mut_methods_file_name.as_str())?;
+            // REM cut the selection itself, so `source` is the file before
+            let pre_extract = match e.selection {
+                Some(_) => source.to_string(),
+                None => fs::read_to_string(&e.pre_extract_file_name)?,
+            };
+            make_borrows_source(
+                controlled.as_str(),
+                pre_extract.as_str(),
+                &mut_methods,
+                &copy_types(manifest_path)?,
+                callee_fn_name,
+                caller_fn_name,
+            )
+        })
+        .map_err(at(Stage::Borrower))?;
+
+    let (repaired, repairer) = match manifest_path {
+        None => repairer.repair_source(borrowed.as_str(), callee_fn_name),
+        Some(manifest_path) => repair_in_project(
+            &borrowed,
+            e.file_name.as_str(),
+            manifest_path,
+            crate_root.as_ref(),
+            callee_fn_name,
+            repairer,
+        ),
+    }
+    .map_err(at(Stage::Repairer))?;
+
+    // only now that its signature is final can the callee go in the trait
+    let repaired = match extractor.as_ref().and_then(|res| res.trait_name.as_ref()) {
+        Some(trait_name) => {
+            let required = e.style.placement == Placement::TraitRequired;
+            move_to_trait(&repaired, callee_fn_name, trait_name, required)
+                .map_err(at(Stage::Output))?
+        }
+        None => repaired,
+    };
+    let edits = item_edits(source, repaired.as_str()).map_err(at(Stage::Output))?;
+    Ok((
+        apply_edits(source, &edits),
+        ExtractionResult {
+            extractor,
+            controller,
+            borrower,
+            repairer,
+            edits,
+            crate_root,
+        },
+    ))
+}
+
+/// Define the shared control enum `controlled` refers to at the root of its
+/// crate: in `controlled` itself if it is the root, or without a crate,
+/// otherwise in edits of the root.
+fn with_shared_enum(
+    controlled: String,
+    e: &Extraction,
+) -> Result<(String, Option<CrateRootEdits>), Error> {
+    let root = match e.manifest_path.as_deref() {
+        Some(manifest_path) => crate_root(&e.file_name, manifest_path)?,
+        None => None,
+    };
+    let root = match root {
+        Some(root) => root,
+        None => return Ok((add_shared_enum(&controlled)?, None)),
+    };
+    let source = fs::read_to_string(&root)?;
+    let edits = item_edits(&source, &add_shared_enum(&source)?)?;
+    debug!("shared enum at {}: {:?}", root.display(), edits);
+    let crate_root = match edits.is_empty() {
+        true => None,
+        false => Some(CrateRootEdits {
+            file_name: root.to_string_lossy().to_string(),
+            source,
+            edits,
+        }),
+    };
+    Ok((controlled, crate_root))
+}
+
+/// The root of the crate of `manifest_path`, `src/lib.rs` or else
+/// `src/main.rs`, unless it is `file_name` itself or `file_name` is the root
+/// of a binary of its own, in `src/bin`.
+fn crate_root(file_name: &str, manifest_path: &str) -> Result<Option<PathBuf>, Error> {
+    let src = fs::canonicalize(manifest_path)?
+        .parent()
+        .map(|dir| dir.join("src"))
+        .ok_or_else(|| Error::Other(format!("no directory for {}", manifest_path)))?;
+    let file = fs::canonicalize(file_name)?;
+    if file.parent() == Some(src.join("bin").as_path()) {
+        return Ok(None);
+    }
+    Ok(["lib.rs", "main.rs"]
+        .iter()
+        .map(|root| src.join(root))
+        .find(|root| root.exists())
+        .filter(|root| root != &file))
+}
+
+/// The methods taking `&mut self` of the standard library, and of the crate
+/// if there is one.
+fn mut_methods(manifest_path: Option<&str>) -> Result<MutMethods, Error> {
+    let mut mut_methods = MutMethods::std();
+    if let Some(manifest_path) = manifest_path {
+        mut_methods.add_crate(manifest_path)?;
+    }
+    Ok(mut_methods)
+}
+
+/// The `Copy` types of the crate if there is one, besides the primitives.
+fn copy_types(manifest_path: Option<&str>) -> Result<CopyTypes, Error> {
+    let mut copy_types = CopyTypes::default();
+    if let Some(manifest_path) = manifest_path {
+        copy_types.add_crate(manifest_path)?;
+    }
+    Ok(copy_types)
+}
+
+/// Repair `source` as the contents of `src_path` without touching the crate:
+/// the workspace is copied into the temp directory and repaired there, along
+/// with the edits of the crate root if any. The copies build in a target
+/// directory of their own, kept across extractions so dependencies are not
+/// rebuilt, and left apart from the one of the crate.
+fn repair_in_project(
+    source: &str,
+    src_path: &str,
+    manifest_path: &str,
+    crate_root: Option<&CrateRootEdits>,
+    fn_name: &str,
+    repairer: &dyn RepairSystem,
+) -> Result<(String, RepairResult), Error> {
+    let root = workspace_root(manifest_path)?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let scratch =
+        std::env::temp_dir().join(format!("rem_project_{}_{}", std::process::id(), nanos));
+    let res = copy_workspace(&root, &scratch).and_then(|_| {
+        let src_path = in_copy(&root, &scratch, src_path)?;
+        let manifest_path = in_copy(&root, &scratch, manifest_path)?;
+        fs::write(&src_path, source)?;
+        if let Some(crate_root) = crate_root {
+            fs::write(
+                in_copy(&root, &scratch, &crate_root.file_name)?,
+                apply_edits(&crate_root.source, &crate_root.edits),
+            )?;
+        }
+        let target_dir = std::env::temp_dir().join("rem_target");
+        let res = repairer.repair_project(
+            &src_path.to_string_lossy(),
+            &manifest_path.to_string_lossy(),
+            Some(&target_dir.to_string_lossy()),
+            fn_name,
+        )?;
+        Ok((fs::read_to_string(&src_path)?, res))
+    });
+    fs::remove_dir_all(&scratch).unwrap_or_default();
+    res
+}
+
+/// The directory of the workspace `manifest_path` belongs to, or of the crate
+/// itself if cargo cannot tell.
+fn workspace_root(manifest_path: &str) -> Result<PathBuf, Error> {
+    let out = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .arg(format!("--manifest-path={}", manifest_path))
+        .output()?;
+    let workspace_manifest = match out.status.success() {
+        true => PathBuf::from(String::from_utf8(out.stdout)?.trim()),
+        false => {
+            debug!("no workspace for {}", manifest_path);
+            fs::canonicalize(manifest_path)?
+        }
+    };
+    let root = workspace_manifest
+        .parent()
+        .ok_or_else(|| Error::Other(format!("no directory for {}", manifest_path)))?;
+    Ok(fs::canonicalize(root)?)
+}
+
+/// Copy the sources of the workspace at `from` into `to`, leaving out hidden
+/// files and the build directory.
+fn copy_workspace(from: &Path, to: &Path) -> Result<(), Error> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') || name == "target" {
+            continue;
+        }
+        let (from, to) = (entry.path(), to.join(&name));
+        if entry.file_type()?.is_dir() {
+            copy_workspace(&from, &to)?;
+        } else {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Where `path`, a file of the workspace at `root`, is in its copy at
+/// `scratch`.
+fn in_copy(root: &Path, scratch: &Path, path: &str) -> Result<PathBuf, Error> {
+    let path = fs::canonicalize(path)?;
+    path.strip_prefix(root)
+        .map(|rel| scratch.join(rel))
+        .map_err(|_| Error::Other(format!("{} is not in {}", path.display(), root.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rem_repairer::repair_lifetime_loosest_bound_first::Repairer;
+
+    /// The selection, the last two statements of the loop, breaks out of it
+    /// and adds to a variable of the caller.
+    const FIXTURE: &str = "fn main() {
+    let mut total = 0;
+    for i in 0..10 {
+        if i == 5 {
+            break;
+        }
+        total += i;
+    }
+    println!(\"{}\", total);
+}
+";
+
+    const EXTRACTED: &str = "fn main() {
+    let mut total = 0;
+    for i in 0..10 {
+        match bar(&mut total, i) {
+            RetBar::Ok(x) => x,
+            RetBar::Break => break,
+        };
+    }
+    println!(\"{}\", total);
+}
+
+fn bar(total: &mut i32, i: i32) -> RetBar<()> {
+    if i == 5 {
+        return RetBar::Break;
+    }
+    (*total) += i;
+    RetBar::Ok(())
+}
+
+enum RetBar<A> {
+    Ok(A),
+    Break,
+}
+";
+
+    #[test]
+    fn test_run_extraction_source_through_every_stage() {
+        let mut e = Extraction::new("fixture.rs", "main", "bar");
+        e.selection = Some((Position::LineColumn(4, 9), Position::LineColumn(7, 20)));
+        let (extracted, result) = match run_extraction_source(FIXTURE, &e, &Repairer {}) {
+            Ok(res) => res,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(result.extractor.is_some());
+        assert!(result.controller.has_break);
+        assert_eq!(result.borrower.make_mut, vec!["total".to_string()]);
+        assert_eq!(extracted, apply_edits(FIXTURE, &result.edits));
+        assert_eq!(extracted, EXTRACTED);
+    }
+}
@@ -3,6 +3,7 @@ use std::io::prelude::*;
 use std::io::Write;
 
 use log::debug;
+use rem_utils::error::Error;
 use rem_utils::parser::ws;
 use std::process::{Command, Stdio};
 
@@ -15,17 +16,17 @@ pub fn parse_constraints<C: crate::constraint::LocalConstraint>(
     terminated(separated_list1(char(','), ws(C::parse)), char('.'))(s)
 }
 
-pub fn chr_solve<C: constraint::LocalConstraint>(constraints: &Vec<C>) -> Vec<C> {
+/// Solve constraints by running `swipl` on `C::CHR_RULES`. REM solves them
+/// natively (see `LocalConstraint::solve`); this is the reference for it.
+/// Fails if `swipl` cannot be run or its answer cannot be parsed.
+pub fn chr_solve<C: constraint::LocalConstraint>(constraints: &Vec<C>) -> Result<Vec<C>, Error> {
     if constraints.is_empty() {
-        return constraints.to_vec();
+        return Ok(constraints.to_vec());
     }
     let chr_constraint_rules = C::CHR_RULES;
 
-    let tmp =
-        mktemp::Temp::new_file().expect("could not open a temp file - are you running on MacOS?");
-    let tmp_path: String = tmp.to_str().unwrap().into();
-    std::fs::write(&tmp_path, chr_constraint_rules.as_bytes())
-        .expect("could not write chr rules to temp file");
+    let tmp = mktemp::Temp::new_file()?;
+    std::fs::write(&tmp, chr_constraint_rules.as_bytes())?;
 
     let mut query = "call((".to_string();
     for constraint in constraints.iter() {
@@ -35,30 +36,33 @@ pub fn chr_solve<C: constraint::LocalConstraint>(constraints: &Vec<C>) -> Vec<C>
     debug!("query for chr: {})).", &query);
     query.pop();
     write!(query, ")).").unwrap();
-    let process = Command::new("swipl")
+    let mut process = Command::new("swipl")
         .arg("-q") // quiet
         .arg("-f")
-        .arg(tmp_path) // open chr constraint rules
+        .arg(tmp.as_path()) // open chr constraint rules
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .spawn()
-        .expect("failed");
+        .spawn()?;
 
-    process
-        .stdin
-        .unwrap()
-        .write_all(query.as_bytes())
-        .expect("failed");
+    if let Some(mut stdin) = process.stdin.take() {
+        stdin.write_all(query.as_bytes())?;
+    }
     let mut output = String::new();
-    process
-        .stdout
-        .unwrap()
-        .read_to_string(&mut output)
-        .expect("failed");
+    if let Some(mut stdout) = process.stdout.take() {
+        stdout.read_to_string(&mut output)?;
+    }
+    process.wait()?;
     // println!("output is:\n{}", output);
     drop(tmp);
 
     parse_constraints(&output)
-        .expect("could not parse output of parser")
-        .1
+        .map(|(_, constraints)| constraints)
+        .map_err(|e| Error::Other(format!("could not parse the output of swipl: {}", e)))
+}
+
+/// Whether `swipl` can be run, for the tests comparing it with the other
+/// solvers to skip the comparison if not.
+#[cfg(test)]
+pub(crate) fn has_swipl() -> bool {
+    Command::new("swipl").arg("--version").output().is_ok()
 }
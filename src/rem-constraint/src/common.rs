@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use itertools::Itertools;
 
 use nom::{
@@ -11,7 +13,16 @@ use proc_macro2::{Ident, Span};
 
 use rem_utils::annotation::Annotations;
 use rem_utils::labelling::Label;
-use syn::{visit_mut::VisitMut, Expr, ExprAssign, FnArg, Stmt, Type};
+use rem_utils::mutability::MutMethods;
+use syn::visit::Visit;
+use syn::{
+    visit_mut::VisitMut, Expr, ExprAssign, ExprAssignOp, ExprMethodCall, ExprReference, FnArg,
+    Stmt, Type, UnOp,
+};
+
+use crate::datalog::Datalog;
+use crate::native::{find_pair, remove_pair, Unifier};
+use crate::LocalConstraint;
 
 /// Aliasing Constraints
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -63,8 +74,88 @@ impl crate::LocalConstraint for AliasConstraints {
         alt((ref_, alias, assign))(s)
     }
 
+    fn solve(constraints: &[Self]) -> Vec<Self> {
+        let refs: HashSet<Label> = constraints
+            .iter()
+            .filter_map(|constraint| match constraint {
+                AliasConstraints::Ref(r) => Some(*r),
+                _ => None,
+            })
+            .collect();
+        let mut store = vec![];
+        let mut aliases = vec![];
+        for constraint in constraints {
+            match constraint {
+                // ref(P) \ assign(Q, P) <=> alias(Q, P).
+                // ref(Q) \ assign(Q, P) <=> alias(Q, P).
+                AliasConstraints::Assign(q, p) if refs.contains(q) || refs.contains(p) => {
+                    aliases.push((*q, *p))
+                }
+                AliasConstraints::Alias(q, p) => aliases.push((*q, *p)),
+                constraint => store.push(constraint.clone()),
+            }
+        }
+        // alias(Q, P), alias(R, Q) ==> alias(R, P), as a set
+        let mut aliases: Vec<(Label, Label)> = aliases.into_iter().unique().collect();
+        let mut known: HashSet<(Label, Label)> = aliases.iter().cloned().collect();
+        let mut i = 0;
+        while i < aliases.len() {
+            let (a, b) = aliases[i];
+            // as alias(Q, P) and as alias(R, Q), against every alias so far
+            let closed: Vec<(Label, Label)> = aliases
+                .iter()
+                .filter_map(|(r, q)| (*q == a).then_some((*r, b)))
+                .chain(
+                    aliases
+                        .iter()
+                        .filter_map(|(q, p)| (*q == b).then_some((a, *p))),
+                )
+                .collect();
+            for alias in closed {
+                if known.insert(alias) {
+                    aliases.push(alias)
+                }
+            }
+            i += 1;
+        }
+        store.extend(
+            aliases
+                .into_iter()
+                .map(|(q, p)| AliasConstraints::Alias(q, p)),
+        );
+        store
+    }
+
+    fn datalog() -> Option<Datalog<Self>> {
+        use AliasConstraints::*;
+        Some(Datalog {
+            rules: vec![
+                // alias(Q, P) <-- ref(P), assign(Q, P).
+                |a, b| match (a, b) {
+                    (Ref(r), Assign(q, p)) if r == p => Some(Alias(*q, *p)),
+                    _ => None,
+                },
+                // alias(Q, P) <-- ref(Q), assign(Q, P).
+                |a, b| match (a, b) {
+                    (Ref(r), Assign(q, p)) if r == q => Some(Alias(*q, *p)),
+                    _ => None,
+                },
+                // alias(R, P) <-- alias(Q, P), alias(R, Q).
+                |a, b| match (a, b) {
+                    (Alias(q, p), Alias(r, q2)) if q == q2 => Some(Alias(*r, *p)),
+                    _ => None,
+                },
+            ],
+            // the assignments the first two rules simplify away
+            retract: |fact, facts| match fact {
+                Assign(q, p) => facts.contains(&Ref(*q)) || facts.contains(&Ref(*p)),
+                _ => false,
+            },
+        })
+    }
+
     fn collect<'a>(
-        (map, fun): &rem_utils::annotation::Annotated<'a, &'a syn::ItemFn>,
+        (map, fun, _): &rem_utils::annotation::Annotated<'a, &'a syn::ItemFn>,
     ) -> Vec<Self> {
         use rem_utils::labelling::ASTKey;
 
@@ -337,3 +428,730 @@ impl crate::LocalConstraint for AliasConstraints {
         constraints.into_iter().unique().collect()
     }
 }
+
+/// Array Constraints, telling the pointers of translated C that are used as
+/// arrays, and those that can be vectors
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ArrayConstraints {
+    /// `deref(Ptr, Res)`: `Res` is `*Ptr`
+    Deref(Label, Label),
+    /// `offset(Ptr, OffsetPtr)`: `OffsetPtr` is `Ptr` moved by some offset
+    Offset(Label, Label),
+    /// `index(Ptr, Output)`: `Output` is an element of the array at `Ptr`
+    Index(Label, Label),
+    /// `shouldindex(Ptr, Output)`: `Output` is read as `Ptr[i]`
+    ShouldIndex(Label, Label),
+    /// `ref(Ptr, ReferredTo)`: `Ptr` refers to `ReferredTo`
+    Ref(Label, Label),
+    /// `malloc(Ptr)`: `Ptr` is allocated on the heap
+    Malloc(Label),
+    /// `vec(Ptr)`: `Ptr` can be a vector
+    Vec(Label),
+}
+
+impl std::fmt::Display for ArrayConstraints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrayConstraints::Deref(l, r) => write!(f, "deref({}, {})", l, r),
+            ArrayConstraints::Offset(l, r) => write!(f, "offset({}, {})", l, r),
+            ArrayConstraints::Index(l, r) => write!(f, "index({}, {})", l, r),
+            ArrayConstraints::ShouldIndex(l, r) => write!(f, "shouldindex({}, {})", l, r),
+            ArrayConstraints::Ref(l, r) => write!(f, "ref({}, {})", l, r),
+            ArrayConstraints::Malloc(l) => write!(f, "malloc({})", l),
+            ArrayConstraints::Vec(l) => write!(f, "vec({})", l),
+        }
+    }
+}
+
+impl ArrayConstraints {
+    fn resolve(&self, unifier: &Unifier) -> Self {
+        let u = |l: &Label| unifier.find(*l);
+        match self {
+            ArrayConstraints::Deref(l, r) => ArrayConstraints::Deref(u(l), u(r)),
+            ArrayConstraints::Offset(l, r) => ArrayConstraints::Offset(u(l), u(r)),
+            ArrayConstraints::Index(l, r) => ArrayConstraints::Index(u(l), u(r)),
+            ArrayConstraints::ShouldIndex(l, r) => ArrayConstraints::ShouldIndex(u(l), u(r)),
+            ArrayConstraints::Ref(l, r) => ArrayConstraints::Ref(u(l), u(r)),
+            ArrayConstraints::Malloc(l) => ArrayConstraints::Malloc(u(l)),
+            ArrayConstraints::Vec(l) => ArrayConstraints::Vec(u(l)),
+        }
+    }
+
+    /// Apply the first rule of `arr_constraint_rules.pl` that matches the
+    /// store, if any.
+    fn step(store: &mut Vec<Self>, unifier: &mut Unifier) -> bool {
+        use ArrayConstraints::*;
+        // deref(OffsetPtr, Res), offset(Ptr, OffsetPtr) <=> index(Ptr, Res).
+        if let Some((i, j)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (Deref(op, _), Offset(_, op2)) if op == op2),
+        ) {
+            let (Deref(_, res), Offset(ptr, _)) = (store[i].clone(), store[j].clone()) else {
+                unreachable!()
+            };
+            remove_pair(store, (i, j));
+            store.push(Index(ptr, res));
+            return true;
+        }
+        // index(Ptr, Output) \ shouldindex(Ptr, NextOutput) <=> Output = NextOutput.
+        if let Some((i, j)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (Index(p, _), ShouldIndex(p2, _)) if p == p2),
+        ) {
+            let (Index(_, output), ShouldIndex(_, next)) = (store[i].clone(), store[j].clone())
+            else {
+                unreachable!()
+            };
+            store.remove(j);
+            unifier.unify(output, next);
+            return true;
+        }
+        // deref(Ptr, Res), ref(Ptr, ReferredTo) <=> Res=ReferredTo.
+        if let Some((i, j)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (Deref(p, _), Ref(p2, _)) if p == p2),
+        ) {
+            let (Deref(_, res), Ref(_, referred)) = (store[i].clone(), store[j].clone()) else {
+                unreachable!()
+            };
+            remove_pair(store, (i, j));
+            unifier.unify(res, referred);
+            return true;
+        }
+        // offset(Ptr, OffsetPtr), offset(OffsetPtr, Result) <=> OffsetPtr = Result, offset(Ptr, Result).
+        if let Some((i, j)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (Offset(_, op), Offset(op2, _)) if op == op2),
+        ) {
+            let (Offset(ptr, op), Offset(_, res)) = (store[i].clone(), store[j].clone()) else {
+                unreachable!()
+            };
+            remove_pair(store, (i, j));
+            unifier.unify(op, res);
+            store.push(Offset(ptr, res));
+            return true;
+        }
+        // index(Ptr, Output) \ deref(Ptr, Result) <=> Output = Result, index(Ptr, Result).
+        if let Some((i, j)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (Index(p, _), Deref(p2, _)) if p == p2),
+        ) {
+            let (Index(ptr, output), Deref(_, res)) = (store[i].clone(), store[j].clone()) else {
+                unreachable!()
+            };
+            store.remove(j);
+            unifier.unify(output, res);
+            store.push(Index(ptr, res));
+            return true;
+        }
+        // shouldindex(Ptr, _), malloc(Ptr) ==> vec(Ptr).
+        if let Some((i, _)) = find_pair(
+            store,
+            |a, b| matches!((a, b), (ShouldIndex(p, _), Malloc(p2)) if p == p2 && !store.contains(&Vec(*p))),
+        ) {
+            let ShouldIndex(ptr, _) = store[i] else {
+                unreachable!()
+            };
+            store.push(Vec(ptr));
+            return true;
+        }
+        false
+    }
+}
+
+impl crate::LocalConstraint for ArrayConstraints {
+    const CHR_RULES: &'static str = include_str!("constraint_rules/arr_constraint_rules.pl");
+    fn parse(s: &str) -> nom::IResult<&str, Self> {
+        use rem_utils::parser::{label, ws};
+        fn pair<'a>(
+            name: &'static str,
+            constraint: fn(Label, Label) -> ArrayConstraints,
+        ) -> impl FnMut(&'a str) -> IResult<&'a str, ArrayConstraints> {
+            move |s| {
+                let (s, _) = tag(name)(s)?;
+                let (s, (l1, l2)) = delimited(
+                    char('('),
+                    sequence::separated_pair(label, ws(char(',')), label),
+                    char(')'),
+                )(s)?;
+                Ok((s, constraint(l1, l2)))
+            }
+        }
+        fn single<'a>(
+            name: &'static str,
+            constraint: fn(Label) -> ArrayConstraints,
+        ) -> impl FnMut(&'a str) -> IResult<&'a str, ArrayConstraints> {
+            move |s| {
+                let (s, _) = tag(name)(s)?;
+                let (s, l1) = delimited(char('('), label, char(')'))(s)?;
+                Ok((s, constraint(l1)))
+            }
+        }
+        alt((
+            pair("deref", ArrayConstraints::Deref),
+            pair("offset", ArrayConstraints::Offset),
+            pair("index", ArrayConstraints::Index),
+            pair("shouldindex", ArrayConstraints::ShouldIndex),
+            pair("ref", ArrayConstraints::Ref),
+            single("malloc", ArrayConstraints::Malloc),
+            single("vec", ArrayConstraints::Vec),
+        ))(s)
+    }
+
+    fn solve(constraints: &[Self]) -> Vec<Self> {
+        let mut store = constraints.to_vec();
+        let mut unifier = Unifier::default();
+        while ArrayConstraints::step(&mut store, &mut unifier) {
+            store = store.iter().map(|c| c.resolve(&unifier)).collect();
+        }
+        store.into_iter().unique().collect()
+    }
+
+    fn collect<'a>(
+        (map, fun, _): &rem_utils::annotation::Annotated<'a, &'a syn::ItemFn>,
+    ) -> Vec<Self> {
+        use rem_utils::labelling::ASTKey;
+
+        struct Traverse<'a> {
+            ast: &'a Annotations<'a>,
+            constraints: Vec<ArrayConstraints>,
+        }
+
+        impl Traverse<'_> {
+            fn lookup(&self, key: &dyn ASTKey) -> Option<Label> {
+                self.ast.get(&key).copied()
+            }
+
+            /// the constraints of `lhs` being assigned `rhs`
+            fn assign(&mut self, lhs: Label, rhs: &Expr) {
+                let constraint = match rhs {
+                    Expr::Paren(e) => return self.assign(lhs, &e.expr),
+                    Expr::Cast(e) => return self.assign(lhs, &e.expr),
+                    Expr::Unary(e) if matches!(e.op, UnOp::Deref(_)) => self
+                        .lookup(e.expr.as_ref())
+                        .map(|ptr| ArrayConstraints::Deref(ptr, lhs)),
+                    Expr::Reference(e) => self
+                        .lookup(e.expr.as_ref())
+                        .map(|referred| ArrayConstraints::Ref(lhs, referred)),
+                    Expr::Index(e) => self
+                        .lookup(e.expr.as_ref())
+                        .map(|ptr| ArrayConstraints::ShouldIndex(ptr, lhs)),
+                    Expr::MethodCall(e)
+                        if ["offset", "add", "sub", "wrapping_offset", "wrapping_add"]
+                            .iter()
+                            .any(|name| e.method == name) =>
+                    {
+                        self.lookup(e.receiver.as_ref())
+                            .map(|ptr| ArrayConstraints::Offset(ptr, lhs))
+                    }
+                    Expr::Call(e) => match e.func.as_ref() {
+                        Expr::Path(p)
+                            if p.path.segments.last().is_some_and(|segment| {
+                                ["malloc", "calloc", "realloc"]
+                                    .iter()
+                                    .any(|name| segment.ident == name)
+                            }) =>
+                        {
+                            Some(ArrayConstraints::Malloc(lhs))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                self.constraints.extend(constraint)
+            }
+        }
+
+        impl<'ast> Visit<'ast> for Traverse<'_> {
+            fn visit_local(&mut self, i: &'ast syn::Local) {
+                let ident = match &i.pat {
+                    syn::Pat::Ident(p) => Some(&p.ident),
+                    syn::Pat::Type(syn::PatType {
+                        pat: box syn::Pat::Ident(p),
+                        ..
+                    }) => Some(&p.ident),
+                    _ => None,
+                };
+                if let (Some(lhs), Some((_, init))) =
+                    (ident.and_then(|ident| self.lookup(ident)), &i.init)
+                {
+                    self.assign(lhs, init)
+                }
+                syn::visit::visit_local(self, i)
+            }
+
+            fn visit_expr_assign(&mut self, i: &'ast ExprAssign) {
+                if let Some(lhs) = self.lookup(i.left.as_ref()) {
+                    self.assign(lhs, &i.right)
+                }
+                syn::visit::visit_expr_assign(self, i)
+            }
+        }
+
+        let mut collector = Traverse {
+            ast: map,
+            constraints: vec![],
+        };
+        collector.visit_item_fn(fun);
+        collector.constraints.into_iter().unique().collect()
+    }
+}
+
+/// Mutability Constraints, telling the variables that are written to, either
+/// directly or through a reference aliasing them
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MutConstraints {
+    /// `mut(X)`: `X` is written to, or borrowed mutably
+    Mut(Label),
+    /// `alias(Q, P)`: `Q` refers to what `P` refers to
+    Alias(Label, Label),
+}
+
+impl std::fmt::Display for MutConstraints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MutConstraints::Mut(l) => write!(f, "mut({})", l),
+            MutConstraints::Alias(l, r) => write!(f, "alias({}, {})", l, r),
+        }
+    }
+}
+
+impl MutConstraints {
+    /// Same as `collect`, where calls to the methods of `mut_methods` write
+    /// to their receiver.
+    pub fn collect_with<'a>(
+        annotated: &rem_utils::annotation::Annotated<'a, &'a syn::ItemFn>,
+        mut_methods: &MutMethods,
+    ) -> Vec<Self> {
+        use rem_utils::labelling::ASTKey;
+
+        struct Traverse<'a> {
+            ast: &'a Annotations<'a>,
+            mut_methods: &'a MutMethods,
+            /// the variables written to, and whether their value certainly is,
+            /// rather than a reference being given a new referent
+            writes: Vec<(Label, bool)>,
+            /// the variables assigned a value outright, hence no references
+            values: Vec<Label>,
+        }
+
+        impl Traverse<'_> {
+            /// `place` is written to, its value certainly so, as when written
+            /// through some field, index or deref
+            fn write(&mut self, place: &Expr, value: bool) {
+                match place {
+                    Expr::Path(_) => {
+                        if let Some(label) = self.ast.get(&(place as &dyn ASTKey)) {
+                            self.writes.push((*label, value))
+                        }
+                    }
+                    Expr::Paren(e) => self.write(&e.expr, value),
+                    Expr::Field(e) => self.write(&e.base, true),
+                    Expr::Index(e) => self.write(&e.expr, true),
+                    Expr::Unary(e) if matches!(e.op, UnOp::Deref(_)) => self.write(&e.expr, true),
+                    _ => (),
+                }
+            }
+
+            /// `place` is assigned a value outright
+            fn value(&mut self, place: &Expr) {
+                match place {
+                    Expr::Path(_) => {
+                        if let Some(label) = self.ast.get(&(place as &dyn ASTKey)) {
+                            self.values.push(*label)
+                        }
+                    }
+                    Expr::Paren(e) => self.value(&e.expr),
+                    _ => (),
+                }
+            }
+        }
+
+        impl<'ast> Visit<'ast> for Traverse<'_> {
+            fn visit_expr_assign(&mut self, i: &'ast ExprAssign) {
+                // no reference is assigned a literal or the result of an
+                // arithmetic or logical operator, as in `y = *z + 1`
+                let value = match &*i.right {
+                    Expr::Lit(_) | Expr::Binary(_) => true,
+                    Expr::Unary(e) => !matches!(e.op, UnOp::Deref(_)),
+                    _ => false,
+                };
+                self.write(&i.left, value);
+                if value {
+                    self.value(&i.left)
+                }
+                syn::visit::visit_expr_assign(self, i)
+            }
+
+            fn visit_expr_assign_op(&mut self, i: &'ast ExprAssignOp) {
+                self.write(&i.left, true);
+                self.value(&i.left);
+                syn::visit::visit_expr_assign_op(self, i)
+            }
+
+            fn visit_expr_reference(&mut self, i: &'ast ExprReference) {
+                if i.mutability.is_some() {
+                    self.write(&i.expr, false)
+                }
+                syn::visit::visit_expr_reference(self, i)
+            }
+
+            fn visit_expr_method_call(&mut self, i: &'ast ExprMethodCall) {
+                if self.mut_methods.contains(&i.method) {
+                    self.write(&i.receiver, true)
+                }
+                syn::visit::visit_expr_method_call(self, i)
+            }
+        }
+
+        let (map, fun, _) = annotated;
+        let mut aliases: Vec<(Label, Label)> =
+            AliasConstraints::solve(&AliasConstraints::collect(annotated))
+                .into_iter()
+                .filter_map(|constraint| match constraint {
+                    AliasConstraints::Alias(q, p) => Some((q, p)),
+                    _ => None,
+                })
+                .collect();
+        let mut collector = Traverse {
+            ast: map,
+            mut_methods,
+            writes: vec![],
+            values: vec![],
+        };
+        collector.visit_item_fn(fun);
+        // the alias analysis has `y` alias `z` in `y = *z + 1`, though `y`
+        // is no reference
+        aliases.retain(|(q, _)| !collector.values.contains(q));
+        collector
+            .writes
+            .into_iter()
+            // assigning a reference a new referent, as in `r = &y`, writes
+            // to the reference, not to what it referred to
+            .filter(|(label, value)| *value || !aliases.iter().any(|(q, _)| q == label))
+            .map(|(label, _)| MutConstraints::Mut(label))
+            .chain(aliases.iter().map(|(q, p)| MutConstraints::Alias(*q, *p)))
+            .unique()
+            .collect()
+    }
+}
+
+impl crate::LocalConstraint for MutConstraints {
+    const CHR_RULES: &'static str = include_str!("constraint_rules/mut_constraint_rules.pl");
+    fn parse(s: &str) -> nom::IResult<&str, Self> {
+        use rem_utils::parser::{label, ws};
+        fn mut_(s: &str) -> IResult<&str, MutConstraints> {
+            let (s, _) = tag("mut")(s)?;
+            let (s, l1) = delimited(char('('), label, char(')'))(s)?;
+            Ok((s, MutConstraints::Mut(l1)))
+        }
+
+        fn alias(s: &str) -> IResult<&str, MutConstraints> {
+            let (s, _) = tag("alias")(s)?;
+            let (s, (l1, l2)) = delimited(
+                char('('),
+                sequence::separated_pair(label, ws(char(',')), label),
+                char(')'),
+            )(s)?;
+            Ok((s, MutConstraints::Alias(l1, l2)))
+        }
+
+        alt((mut_, alias))(s)
+    }
+
+    fn solve(constraints: &[Self]) -> Vec<Self> {
+        // alias(Q, P), alias(R, Q) ==> alias(R, P), as for aliasing
+        let aliases: Vec<(Label, Label)> = AliasConstraints::solve(
+            &constraints
+                .iter()
+                .filter_map(|constraint| match constraint {
+                    MutConstraints::Alias(q, p) => Some(AliasConstraints::Alias(*q, *p)),
+                    _ => None,
+                })
+                .collect_vec(),
+        )
+        .into_iter()
+        .filter_map(|constraint| match constraint {
+            AliasConstraints::Alias(q, p) => Some((q, p)),
+            _ => None,
+        })
+        .collect();
+        // alias(Q, P), mut(Q) ==> mut(P), as a set
+        let mut muts: Vec<Label> = constraints
+            .iter()
+            .filter_map(|constraint| match constraint {
+                MutConstraints::Mut(l) => Some(*l),
+                _ => None,
+            })
+            .unique()
+            .collect();
+        let mut known: HashSet<Label> = muts.iter().cloned().collect();
+        let mut i = 0;
+        while i < muts.len() {
+            let q = muts[i];
+            for (_, p) in aliases.iter().filter(|(q2, _)| *q2 == q) {
+                if known.insert(*p) {
+                    muts.push(*p)
+                }
+            }
+            i += 1;
+        }
+        muts.into_iter()
+            .map(MutConstraints::Mut)
+            .chain(
+                aliases
+                    .into_iter()
+                    .map(|(q, p)| MutConstraints::Alias(q, p)),
+            )
+            .collect()
+    }
+
+    fn datalog() -> Option<Datalog<Self>> {
+        use MutConstraints::*;
+        Some(Datalog {
+            rules: vec![
+                // alias(R, P) <-- alias(Q, P), alias(R, Q).
+                |a, b| match (a, b) {
+                    (Alias(q, p), Alias(r, q2)) if q == q2 => Some(Alias(*r, *p)),
+                    _ => None,
+                },
+                // mut(P) <-- alias(Q, P), mut(Q).
+                |a, b| match (a, b) {
+                    (Alias(q, p), Mut(q2)) if q == q2 => Some(Mut(*p)),
+                    _ => None,
+                },
+            ],
+            retract: |_, _| false,
+        })
+    }
+
+    /// Writes, mutable borrows and calls to the methods of the standard
+    /// library taking `&mut self`, along with the aliases they go through
+    fn collect<'a>(fun: &rem_utils::annotation::Annotated<'a, &'a syn::ItemFn>) -> Vec<Self> {
+        MutConstraints::collect_with(fun, &MutMethods::std())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn l(n: usize) -> Label {
+        Label::of_raw(n)
+    }
+
+    fn set<C: LocalConstraint + Eq + std::hash::Hash>(constraints: Vec<C>) -> HashSet<C> {
+        constraints.into_iter().collect()
+    }
+
+    fn alias_cases() -> Vec<Vec<AliasConstraints>> {
+        use AliasConstraints::*;
+        vec![
+            vec![Ref(l(1)), Assign(l(2), l(1)), Assign(l(3), l(4))],
+            vec![Alias(l(1), l(2)), Alias(l(2), l(3)), Alias(l(3), l(4))],
+            vec![
+                Ref(l(5)),
+                Assign(l(5), l(6)),
+                Alias(l(6), l(7)),
+                Alias(l(1), l(2)),
+                Alias(l(1), l(2)),
+            ],
+        ]
+    }
+
+    fn array_cases() -> Vec<Vec<ArrayConstraints>> {
+        use ArrayConstraints::*;
+        vec![
+            vec![Deref(l(2), l(3)), Offset(l(1), l(2))],
+            vec![Malloc(l(1)), ShouldIndex(l(1), l(2))],
+            vec![Malloc(l(1)), ShouldIndex(l(1), l(2)), Deref(l(3), l(4))],
+        ]
+    }
+
+    #[test]
+    fn test_alias_solve() {
+        use AliasConstraints::*;
+        let [refs, chain, mixed] = &alias_cases()[..] else {
+            unreachable!()
+        };
+        assert_eq!(
+            set(AliasConstraints::solve(refs)),
+            set(vec![Ref(l(1)), Alias(l(2), l(1)), Assign(l(3), l(4))])
+        );
+        assert_eq!(
+            set(AliasConstraints::solve(chain)),
+            set(vec![
+                Alias(l(1), l(2)),
+                Alias(l(2), l(3)),
+                Alias(l(3), l(4)),
+                Alias(l(1), l(3)),
+                Alias(l(2), l(4)),
+                Alias(l(1), l(4)),
+            ])
+        );
+        assert_eq!(
+            set(AliasConstraints::solve(mixed)),
+            set(vec![
+                Ref(l(5)),
+                Alias(l(5), l(6)),
+                Alias(l(6), l(7)),
+                Alias(l(5), l(7)),
+                Alias(l(1), l(2)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_array_solve() {
+        use ArrayConstraints::*;
+        assert_eq!(
+            ArrayConstraints::solve(&[Deref(l(2), l(3)), Offset(l(1), l(2))]),
+            vec![Index(l(1), l(3))]
+        );
+        // unified labels stand for the smallest of them
+        assert_eq!(
+            set(ArrayConstraints::solve(&[
+                Ref(l(1), l(4)),
+                Deref(l(1), l(2)),
+                Index(l(5), l(2)),
+            ])),
+            set(vec![Index(l(5), l(2))])
+        );
+        assert_eq!(
+            set(ArrayConstraints::solve(&[
+                Offset(l(1), l(2)),
+                Offset(l(2), l(3)),
+                Index(l(1), l(4)),
+                ShouldIndex(l(1), l(6)),
+            ])),
+            set(vec![Offset(l(1), l(2)), Index(l(1), l(4))])
+        );
+        assert_eq!(
+            set(ArrayConstraints::solve(&[
+                Malloc(l(1)),
+                ShouldIndex(l(1), l(2))
+            ])),
+            set(vec![Malloc(l(1)), ShouldIndex(l(1), l(2)), Vec(l(1))])
+        );
+    }
+
+    #[test]
+    fn test_array_collect() {
+        let fun: syn::ItemFn = syn::parse_str(
+            "
+fn f(n: usize, b: *mut i32) {
+    let p = malloc(n) as *mut i32;
+    let y = p[0];
+    let a = b.offset(1);
+    let x = *a;
+}",
+        )
+        .unwrap();
+        let annotated = rem_utils::annotation::annotate_ast(&fun);
+        let solved = set(ArrayConstraints::solve(&ArrayConstraints::collect(
+            &annotated,
+        )));
+        assert_eq!(solved.len(), 4);
+        assert!(solved.iter().any(|c| matches!(c, ArrayConstraints::Vec(_))));
+        assert!(solved
+            .iter()
+            .any(|c| matches!(c, ArrayConstraints::Index(_, _))));
+    }
+
+    fn mut_cases() -> Vec<Vec<MutConstraints>> {
+        use MutConstraints::*;
+        vec![
+            vec![Alias(l(2), l(1)), Mut(l(2))],
+            vec![Alias(l(3), l(2)), Alias(l(2), l(1)), Mut(l(3)), Mut(l(3))],
+            vec![Alias(l(2), l(1)), Mut(l(1))],
+        ]
+    }
+
+    #[test]
+    fn test_mut_solve() {
+        use MutConstraints::*;
+        let [write, chain, referent] = &mut_cases()[..] else {
+            unreachable!()
+        };
+        assert_eq!(
+            set(MutConstraints::solve(write)),
+            set(vec![Alias(l(2), l(1)), Mut(l(2)), Mut(l(1))])
+        );
+        assert_eq!(
+            set(MutConstraints::solve(chain)),
+            set(vec![
+                Alias(l(3), l(2)),
+                Alias(l(2), l(1)),
+                Alias(l(3), l(1)),
+                Mut(l(3)),
+                Mut(l(2)),
+                Mut(l(1)),
+            ])
+        );
+        // writing the referent does not write the reference
+        assert_eq!(set(MutConstraints::solve(referent)), set(referent.clone()));
+        for case in mut_cases() {
+            assert_eq!(
+                set(MutConstraints::solve(&case)),
+                set(MutConstraints::datalog().unwrap().run(&case))
+            );
+        }
+    }
+
+    #[test]
+    fn test_mut_collect() {
+        let fun: syn::ItemFn = syn::parse_str(
+            "
+fn f(mut a: Vec<i32>, b: Vec<i32>, mut c: i32, z: &i32, mut w: i32) {
+    let r = &mut a;
+    let q = r;
+    q.push(1);
+    let s = &b;
+    let n = s.len();
+    let mut y = 0;
+    let mut p = &mut c;
+    p = &mut y;
+    *p += n;
+    w = *z + 1;
+}",
+        )
+        .unwrap();
+        let annotated = rem_utils::annotation::annotate_ast(&fun);
+        let (_, _, bindings) = &annotated;
+        let mut written: Vec<String> = MutConstraints::solve(&MutConstraints::collect(&annotated))
+            .into_iter()
+            .filter_map(|constraint| match constraint {
+                MutConstraints::Mut(l) => Some(bindings[&l].to_string()),
+                _ => None,
+            })
+            .collect();
+        written.sort();
+        assert_eq!(written, vec!["a", "c", "p", "q", "r", "w", "y"]);
+    }
+
+    #[test]
+    fn test_native_solve_matches_swipl() {
+        if !crate::chr::has_swipl() {
+            eprintln!("swipl not found, skipping");
+            return;
+        }
+        for case in alias_cases() {
+            assert_eq!(
+                set(AliasConstraints::solve(&case)),
+                set(crate::chr::chr_solve(&case).unwrap())
+            );
+        }
+        for case in array_cases() {
+            assert_eq!(
+                set(ArrayConstraints::solve(&case)),
+                set(crate::chr::chr_solve(&case).unwrap())
+            );
+        }
+        for case in mut_cases() {
+            assert_eq!(
+                set(MutConstraints::solve(&case)),
+                set(crate::chr::chr_solve(&case).unwrap())
+            );
+        }
+    }
+}
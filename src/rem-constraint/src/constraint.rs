@@ -1,23 +1,41 @@
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::hash::Hash;
 
 use rem_utils::annotation::Annotated;
+use rem_utils::error::Error;
+
+use crate::datalog::Datalog;
+use crate::solver::{ConstraintSolver, NativeSolver};
 
 /// Abstract encoding of a Local Constraint
-pub trait LocalConstraint: Any + Display + Clone {
+pub trait LocalConstraint: Any + Display + Clone + Eq + Hash {
     /// static CHR rules for the constraint system
     const CHR_RULES: &'static str;
 
     /// parse a single constraint rule
     fn parse(s: &str) -> nom::IResult<&str, Self>;
 
+    /// Solve constraints with the rules of `CHR_RULES`, natively: the result
+    /// is what SWI-Prolog leaves in the store, up to duplicates
+    fn solve(constraints: &[Self]) -> Vec<Self>;
+
+    /// The rules of `CHR_RULES` as Datalog, if they can be
+    fn datalog() -> Option<Datalog<Self>> {
+        None
+    }
+
     /// Collect CHR rules from a function definition
     fn collect<'a>(fun: &Annotated<'a, &'a syn::ItemFn>) -> Vec<Self>;
 }
 
-trait LocalConstraintSystem {
-    fn analyze<'a>(&mut self, fun: &Annotated<'a, &'a syn::ItemFn>);
+trait LocalConstraintSystem<S> {
+    fn analyze<'a>(
+        &mut self,
+        fun: &Annotated<'a, &'a syn::ItemFn>,
+        solver: &S,
+    ) -> Result<(), Error>;
     fn constraints(&self) -> Vec<Box<dyn Any>>;
 }
 
@@ -33,14 +51,21 @@ impl<C: LocalConstraint> Default for ConstraintSystem<C> {
     }
 }
 
-impl<C: LocalConstraint + 'static> LocalConstraintSystem for ConstraintSystem<C> {
-    fn analyze<'a>(&mut self, fun: &Annotated<'a, &'a syn::ItemFn>) {
+impl<C: LocalConstraint + 'static, S: ConstraintSolver> LocalConstraintSystem<S>
+    for ConstraintSystem<C>
+{
+    fn analyze<'a>(
+        &mut self,
+        fun: &Annotated<'a, &'a syn::ItemFn>,
+        solver: &S,
+    ) -> Result<(), Error> {
         self.constraints = C::collect(fun);
         // println!("collected");
         // for x in &self.constraints {
         //     println!("collected constraints: {}", x);
         // }
-        self.constraints = crate::chr::chr_solve(&self.constraints);
+        self.constraints = solver.solve(&self.constraints)?;
+        Ok(())
     }
 
     fn constraints(&self) -> Vec<Box<dyn Any>> {
@@ -56,20 +81,20 @@ impl<C: LocalConstraint + 'static> LocalConstraintSystem for ConstraintSystem<C>
     }
 }
 
-pub struct ConstraintManager {
+pub struct ConstraintManager<S: ConstraintSolver = NativeSolver> {
     /// mapping of type ids to a name + constraint system
-    constraint_systems: HashMap<TypeId, (&'static str, Box<dyn LocalConstraintSystem>)>,
+    constraint_systems: HashMap<TypeId, (&'static str, Box<dyn LocalConstraintSystem<S>>)>,
+    /// the backend solving the constraints of every system
+    solver: S,
 }
 
 impl Default for ConstraintManager {
     fn default() -> Self {
-        ConstraintManager {
-            constraint_systems: HashMap::new(),
-        }
+        ConstraintManager::with_solver(NativeSolver)
     }
 }
 
-impl Display for ConstraintManager {
+impl<S: ConstraintSolver> Display for ConstraintManager<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "ConstraintManager(")?;
         for (_, (name, _)) in self.constraint_systems.iter() {
@@ -80,7 +105,14 @@ impl Display for ConstraintManager {
     }
 }
 
-impl ConstraintManager {
+impl<S: ConstraintSolver> ConstraintManager<S> {
+    pub fn with_solver(solver: S) -> Self {
+        ConstraintManager {
+            constraint_systems: HashMap::new(),
+            solver,
+        }
+    }
+
     pub fn add_constraint<C: LocalConstraint>(&mut self) {
         let id = TypeId::of::<C>();
         let lcs = ConstraintSystem::<C>::default();
@@ -102,9 +134,12 @@ impl ConstraintManager {
         }
     }
 
-    pub fn analyze<'a>(&mut self, fun: &Annotated<'a, &'a syn::ItemFn>) {
+    /// Collects and solves the constraints of every system, failing as soon
+    /// as the solver fails on one
+    pub fn analyze<'a>(&mut self, fun: &Annotated<'a, &'a syn::ItemFn>) -> Result<(), Error> {
         for (_k, (_, v)) in self.constraint_systems.iter_mut() {
-            v.analyze(fun)
+            v.analyze(fun, &self.solver)?
         }
+        Ok(())
     }
 }
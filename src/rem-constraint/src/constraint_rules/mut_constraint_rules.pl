@@ -1,4 +1,12 @@
 :- use_module(library(chr)).
-:- chr_constraint mut/1.
+:- chr_constraint mut/1, alias/2.
 
-/* Empty set of rules for mutability pass */
\ No newline at end of file
+/* set semantics for mutability */
+mut(A) \ mut(A) <=> true.
+alias(A,B) \ alias(A,B) <=> true.
+
+/* propagating the aliasing */
+alias(Q, P), alias(R, Q) ==> alias(R, P).
+
+/* if Q refers to what P refers to, then writing through Q writes P */
+alias(Q, P), mut(Q) ==> mut(P).
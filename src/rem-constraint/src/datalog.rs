@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// CHR rules of a constraint system written as Datalog: what propagates is
+/// derived to a fixpoint, and what is simplified away is retracted at the
+/// end, e.g. `ref(P) \ assign(Q, P) <=> alias(Q, P)` derives `alias(Q, P)`
+/// from `ref(P), assign(Q, P)` and retracts `assign(Q, P)`.
+///
+/// This is no CHR engine. A simplified constraint still takes part in the
+/// derivations until the end, so the result is that of the CHR rules only if
+/// no rule but the one simplifying it uses it, as for `assign` in the alias
+/// rules. Simplification rules that do not merely retract facts, e.g. that
+/// unify labels, cannot be written at all.
+pub struct Datalog<C> {
+    /// `head <-- a, b` rules, as what they derive from the pair `a, b`
+    pub rules: Vec<fn(&C, &C) -> Option<C>>,
+    /// whether a fact is left out of the result, given all the facts
+    pub retract: fn(&C, &HashSet<C>) -> bool,
+}
+
+impl<C: Clone + Eq + Hash> Datalog<C> {
+    /// Semi-naive evaluation: each round only joins the facts derived by the
+    /// round before with all the facts.
+    pub fn run(&self, facts: &[C]) -> Vec<C> {
+        let mut all: Vec<C> = vec![];
+        let mut known: HashSet<C> = HashSet::new();
+        let mut delta: Vec<C> = facts
+            .iter()
+            .filter(|fact| known.insert((*fact).clone()))
+            .cloned()
+            .collect();
+        while !delta.is_empty() {
+            all.extend(delta.iter().cloned());
+            let mut derived = vec![];
+            for rule in self.rules.iter() {
+                for new in delta.iter() {
+                    for fact in all.iter() {
+                        derived.extend(rule(new, fact));
+                        derived.extend(rule(fact, new));
+                    }
+                }
+            }
+            delta = derived
+                .into_iter()
+                .filter(|fact| known.insert(fact.clone()))
+                .collect();
+        }
+        all.into_iter()
+            .filter(|fact| !(self.retract)(fact, &known))
+            .collect()
+    }
+}
@@ -1,9 +1,13 @@
 #![feature(box_patterns)]
 #![feature(box_into_inner)]
 
-mod chr;
+pub mod chr;
 pub mod common;
 mod constraint;
+pub mod datalog;
+mod native;
+mod solver;
 
 pub use constraint::ConstraintManager;
 pub use constraint::LocalConstraint;
+pub use solver::{ConstraintSolver, DatalogSolver, NativeSolver, SwiplSolver};
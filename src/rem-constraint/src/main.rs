@@ -31,7 +31,7 @@ fn main() {
     //cs.add_constraint::<ArrayConstraint>();
     cs.add_constraint::<AliasConstraints>();
 
-    cs.analyze(&annot_ast);
+    cs.analyze(&annot_ast).unwrap();
     let constraints = cs.get_constraints::<AliasConstraints>();
     let constraints: Vec<AliasConstraints> = constraints.into_iter().unique().collect();
 
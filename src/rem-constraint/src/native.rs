@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use rem_utils::labelling::Label;
+
+/// Unification of labels, standing in for `A = B` in the bodies of CHR rules:
+/// labels unified together all stand for the smallest of them.
+#[derive(Debug, Default)]
+pub struct Unifier {
+    parent: HashMap<Label, Label>,
+}
+
+impl Unifier {
+    pub fn find(&self, label: Label) -> Label {
+        let mut label = label;
+        while let Some(parent) = self.parent.get(&label) {
+            label = *parent
+        }
+        label
+    }
+
+    pub fn unify(&mut self, a: Label, b: Label) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent.insert(a.max(b), a.min(b));
+        }
+    }
+}
+
+/// The first two distinct constraints of the store, in order, matching the
+/// heads of a rule, e.g. `deref(OffsetPtr, Res), offset(Ptr, OffsetPtr)`.
+pub fn find_pair<C>(store: &[C], heads: impl Fn(&C, &C) -> bool) -> Option<(usize, usize)> {
+    (0..store.len())
+        .cartesian_product(0..store.len())
+        .find(|(i, j)| i != j && heads(&store[*i], &store[*j]))
+}
+
+/// Remove the constraints at `i` and `j` from the store.
+pub fn remove_pair<C>(store: &mut Vec<C>, (i, j): (usize, usize)) {
+    store.remove(i.max(j));
+    store.remove(i.min(j));
+}
@@ -0,0 +1,107 @@
+use rem_utils::error::Error;
+
+use crate::constraint::LocalConstraint;
+
+/// Backend solving the constraints a `ConstraintManager` collects, the same
+/// for every constraint system it runs. Each backend solves them its own way
+/// or fails, so that backends can be compared.
+pub trait ConstraintSolver {
+    fn solve<C: LocalConstraint>(&self, constraints: &[C]) -> Result<Vec<C>, Error>;
+}
+
+/// Runs `C::CHR_RULES` with SWI-Prolog. Fails if it is not installed or its
+/// answer cannot be parsed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SwiplSolver;
+
+impl ConstraintSolver for SwiplSolver {
+    fn solve<C: LocalConstraint>(&self, constraints: &[C]) -> Result<Vec<C>, Error> {
+        crate::chr::chr_solve(&constraints.to_vec())
+    }
+}
+
+/// Runs the Rust implementation of the rules, `C::solve`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeSolver;
+
+impl ConstraintSolver for NativeSolver {
+    fn solve<C: LocalConstraint>(&self, constraints: &[C]) -> Result<Vec<C>, Error> {
+        Ok(C::solve(constraints))
+    }
+}
+
+/// Runs the rules as Datalog, `C::datalog`, to a fixpoint (see `Datalog` for
+/// the rules it can stand for). Fails on constraint systems whose rules are
+/// not Datalog, e.g. because they unify labels.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DatalogSolver;
+
+impl ConstraintSolver for DatalogSolver {
+    fn solve<C: LocalConstraint>(&self, constraints: &[C]) -> Result<Vec<C>, Error> {
+        match C::datalog() {
+            Some(program) => Ok(program.run(constraints)),
+            None => Err(Error::Other(format!(
+                "no datalog rules for {}",
+                std::any::type_name::<C>()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rem_utils::labelling::Label;
+
+    use super::*;
+    use crate::common::{AliasConstraints, ArrayConstraints};
+    use crate::ConstraintManager;
+
+    fn aliases<S: ConstraintSolver>(solver: S) -> Result<HashSet<AliasConstraints>, Error> {
+        let fun: syn::ItemFn = syn::parse_str(
+            "
+fn f() {
+    let x = 1;
+    let x_ref = &x;
+    let m = x_ref;
+    let n = m;
+    let mut z: &i32;
+    {
+        let y = 2;
+        z = &y;
+        z = n;
+    }
+}",
+        )
+        .unwrap();
+        let annotated = rem_utils::annotation::annotate_ast(&fun);
+        let mut cs = ConstraintManager::with_solver(solver);
+        cs.add_constraint::<AliasConstraints>();
+        cs.analyze(&annotated)?;
+        Ok(cs
+            .get_constraints::<AliasConstraints>()
+            .into_iter()
+            .collect())
+    }
+
+    #[test]
+    fn test_solvers_agree() {
+        let native = aliases(NativeSolver).unwrap();
+        assert!(native
+            .iter()
+            .any(|c| matches!(c, AliasConstraints::Alias(_, _))));
+        assert_eq!(native, aliases(DatalogSolver).unwrap());
+        if !crate::chr::has_swipl() {
+            eprintln!("swipl not found, skipping");
+            return;
+        }
+        assert_eq!(native, aliases(SwiplSolver).unwrap());
+    }
+
+    #[test]
+    fn test_datalog_solver_fails_without_rules() {
+        let constraints = [ArrayConstraints::Malloc(Label::of_raw(0))];
+        assert!(DatalogSolver.solve(&constraints).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+async fn fetch(x: i32) -> Option<i32> {
+    if x > 0 {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+async fn new_foo(x: i32) -> Option<i32> {
+    let y = bar(x).await;
+    Some(y + 1)
+}
+
+async fn bar(x: i32) -> i32 {
+    let y = fetch(x).await?;
+    y * 2
+}
+
+fn main() {
+    let _ = new_foo(3);
+}
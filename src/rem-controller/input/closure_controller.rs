@@ -0,0 +1,18 @@
+fn new_foo() -> i32 {
+    let f = |x: i32| -> i32 {
+        let y = bar(x);
+        y * 2
+    };
+    f(3)
+}
+
+fn bar(x: i32) -> i32 {
+    if x > 5 {
+        return 0;
+    }
+    x + 1
+}
+
+fn main() {
+    new_foo();
+}
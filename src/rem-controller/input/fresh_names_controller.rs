@@ -0,0 +1,21 @@
+enum RetBar {
+    Unrelated,
+}
+
+fn new_foo(x1: i32) -> i32 {
+    let x = x1;
+    let result = bar(x);
+    let _ = RetBar::Unrelated;
+    result
+}
+
+fn bar(x: i32) -> i32 {
+    if x < 0 {
+        return -1;
+    }
+    x * 2
+}
+
+fn main() {
+    new_foo(3);
+}
@@ -0,0 +1,23 @@
+fn new_foo(grid: &[Vec<i32>]) -> i32 {
+    let mut total = 0;
+    'rows: for row in grid {
+        for &cell in row {
+            bar(cell, &mut total);
+        }
+    }
+    total
+}
+
+fn bar(cell: i32, total: &mut i32) {
+    if cell < 0 {
+        continue 'rows;
+    }
+    if cell == 0 {
+        break 'rows;
+    }
+    *total += cell;
+}
+
+fn main() {
+    new_foo(&[vec![1, -1, 2], vec![3, 0, 4]]);
+}
@@ -0,0 +1,27 @@
+struct Counter {
+    count: i32,
+}
+
+impl Counter {
+    fn new_foo(&mut self, xs: &[i32]) -> Option<i32> {
+        for &x in xs {
+            self.bar(x);
+        }
+        Some(self.count)
+    }
+
+    fn bar(&mut self, x: i32) {
+        if x < 0 {
+            return None;
+        }
+        if x == 0 {
+            break;
+        }
+        self.count += x;
+    }
+}
+
+fn main() {
+    let mut c = Counter { count: 0 };
+    c.new_foo(&[1, 2, 0, 3]);
+}
@@ -0,0 +1,24 @@
+mod shapes {
+    pub struct Square {
+        pub side: i32,
+    }
+
+    impl Square {
+        pub fn new_foo(&self) -> Option<i32> {
+            let area = Self::bar(self.side);
+            Some(area)
+        }
+
+        fn bar(side: i32) -> i32 {
+            if side < 0 {
+                return None;
+            }
+            side * side
+        }
+    }
+}
+
+fn main() {
+    let s = shapes::Square { side: 2 };
+    s.new_foo();
+}
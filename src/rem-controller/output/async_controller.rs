@@ -0,0 +1,33 @@
+async fn fetch(x: i32) -> Option<i32> {
+    if x > 0 {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+async fn new_foo(x: i32) -> Option<i32> {
+    let y = match bar(x).await {
+        RetBar::Ok(x1) => x1,
+        RetBar::Return(x1) => return x1,
+    };
+    Some(y + 1)
+}
+
+async fn bar(x: i32) -> RetBar<i32, Option<i32>> {
+    let y = match fetch(x).await {
+        Some(x1) => x1,
+        None => return RetBar::Return(None),
+    };
+    let result = y * 2;
+    RetBar::Ok(result)
+}
+
+enum RetBar<A, B> {
+    Ok(A),
+    Return(B),
+}
+
+fn main() {
+    let _ = new_foo(3);
+}
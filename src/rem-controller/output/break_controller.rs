@@ -3,20 +3,23 @@ fn new_foo() -> i32 {
     let mut y = 11;
     while y > 1 {
         y = match bar(x) {
-            RetBar::Ok(x) => x,
+            RetBar::Ok(x1) => x1,
             RetBar::Break => break,
         };
     }
     y
 }
+
 fn bar(x: i32) -> RetBar<i32> {
     let result = if x == 5 { return RetBar::Break } else { x - 1 };
     RetBar::Ok(result)
 }
-fn main() {
-    new_foo();
-}
+
 enum RetBar<A> {
     Ok(A),
     Break,
 }
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,27 @@
+fn new_foo() -> i32 {
+    let f = |x: i32| -> i32 {
+        let y = match bar(x) {
+            RetBar::Ok(x1) => x1,
+            RetBar::Return(x1) => return x1,
+        };
+        y * 2
+    };
+    f(3)
+}
+
+fn bar(x: i32) -> RetBar<i32, i32> {
+    if x > 5 {
+        return RetBar::Return(0);
+    }
+    let result = x + 1;
+    RetBar::Ok(result)
+}
+
+enum RetBar<A, B> {
+    Ok(A),
+    Return(B),
+}
+
+fn main() {
+    new_foo();
+}
@@ -0,0 +1,30 @@
+enum RetBar {
+    Unrelated,
+}
+
+fn new_foo(x1: i32) -> i32 {
+    let x = x1;
+    let result = match bar(x) {
+        RetBar1::Ok(x2) => x2,
+        RetBar1::Return(x2) => return x2,
+    };
+    let _ = RetBar::Unrelated;
+    result
+}
+
+fn bar(x: i32) -> RetBar1<i32, i32> {
+    if x < 0 {
+        return RetBar1::Return(-1);
+    }
+    let result1 = x * 2;
+    RetBar1::Ok(result1)
+}
+
+enum RetBar1<A, B> {
+    Ok(A),
+    Return(B),
+}
+
+fn main() {
+    new_foo(3);
+}
@@ -7,14 +7,16 @@ fn foo() -> i32 {
     };
     y
 }
+
 fn new_foo() -> i32 {
     let x = 1;
     let y = match bar(x) {
-        RetBar::Ok(x) => x,
-        RetBar::Return(x) => return x,
+        RetBar::Ok(x1) => x1,
+        RetBar::Return(x1) => return x1,
     };
     y
 }
+
 fn bar(x: i32) -> RetBar<i32, i32> {
     let result = if x < 2 {
         5
@@ -23,11 +25,13 @@ fn bar(x: i32) -> RetBar<i32, i32> {
     };
     RetBar::Ok(result)
 }
-fn main() {
-    foo();
-    new_foo();
-}
+
 enum RetBar<A, B> {
     Ok(A),
     Return(B),
 }
+
+fn main() {
+    foo();
+    new_foo();
+}
@@ -0,0 +1,34 @@
+fn new_foo(grid: &[Vec<i32>]) -> i32 {
+    let mut total = 0;
+    'rows: for row in grid {
+        for &cell in row {
+            match bar(cell, &mut total) {
+                RetBar::Ok(x) => x,
+                RetBar::ContinueRows => continue 'rows,
+                RetBar::BreakRows => break 'rows,
+            };
+        }
+    }
+    total
+}
+
+fn bar(cell: i32, total: &mut i32) -> RetBar<()> {
+    if cell < 0 {
+        return RetBar::ContinueRows;
+    }
+    if cell == 0 {
+        return RetBar::BreakRows;
+    }
+    *total += cell;
+    RetBar::Ok(())
+}
+
+enum RetBar<A> {
+    Ok(A),
+    ContinueRows,
+    BreakRows,
+}
+
+fn main() {
+    new_foo(&[vec![1, -1, 2], vec![3, 0, 4]]);
+}
@@ -4,13 +4,14 @@ fn new_foo() -> i32 {
     loop {
         x -= 1;
         match bar(y) {
-            RetBar::Ok(x) => x,
-            RetBar::Break => break,
+            RetBar::Ok(x1) => x1,
             RetBar::Continue => continue,
+            RetBar::Break => break,
         };
     }
     x
 }
+
 fn bar(y: i32) -> RetBar<()> {
     let result = if y == 5 {
         return RetBar::Continue;
@@ -19,11 +20,13 @@ fn bar(y: i32) -> RetBar<()> {
     };
     RetBar::Ok(result)
 }
-fn main() {
-    new_foo();
-}
+
 enum RetBar<A> {
     Ok(A),
-    Break,
     Continue,
+    Break,
+}
+
+fn main() {
+    new_foo();
 }
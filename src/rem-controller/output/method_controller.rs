@@ -0,0 +1,38 @@
+struct Counter {
+    count: i32,
+}
+
+impl Counter {
+    fn new_foo(&mut self, xs: &[i32]) -> Option<i32> {
+        for &x in xs {
+            match self.bar(x) {
+                RetBar::Ok(x1) => x1,
+                RetBar::Return(x1) => return x1,
+                RetBar::Break => break,
+            };
+        }
+        Some(self.count)
+    }
+
+    fn bar(&mut self, x: i32) -> RetBar<(), Option<i32>> {
+        if x < 0 {
+            return RetBar::Return(None);
+        }
+        if x == 0 {
+            return RetBar::Break;
+        }
+        self.count += x;
+        RetBar::Ok(())
+    }
+}
+
+enum RetBar<A, B> {
+    Ok(A),
+    Return(B),
+    Break,
+}
+
+fn main() {
+    let mut c = Counter { count: 0 };
+    c.new_foo(&[1, 2, 0, 3]);
+}
@@ -0,0 +1,33 @@
+mod shapes {
+    pub struct Square {
+        pub side: i32,
+    }
+
+    impl Square {
+        pub fn new_foo(&self) -> Option<i32> {
+            let area = match Self::bar(self.side) {
+                RetBar::Ok(x) => x,
+                RetBar::Return(x) => return x,
+            };
+            Some(area)
+        }
+
+        fn bar(side: i32) -> RetBar<i32, Option<i32>> {
+            if side < 0 {
+                return RetBar::Return(None);
+            }
+            let result = side * side;
+            RetBar::Ok(result)
+        }
+    }
+
+    enum RetBar<A, B> {
+        Ok(A),
+        Return(B),
+    }
+}
+
+fn main() {
+    let s = shapes::Square { side: 2 };
+    s.new_foo();
+}
@@ -1,32 +1,36 @@
 struct A {
     x: Result<(), String>,
 }
+
 fn new_foo() -> Result<(), String> {
     let x: Result<(), String> = Ok(());
     match bar(x) {
-        RetBar::Ok(x) => x,
-        RetBar::Return(x) => return x,
+        RetBar::Ok(x1) => x1,
+        RetBar::Return(x1) => return x1,
     };
     Ok(())
 }
+
 fn bar(x: Result<(), String>) -> RetBar<(), Result<(), String>> {
     let _y = match x {
-        Ok(x) => x,
-        Err(e) => return RetBar::Return(Err(e)),
+        Ok(x1) => x1,
+        Err(e) => return RetBar::Return(Err(From::from(e))),
     };
     let a = A { x: Ok(()) };
     match match a.x {
-        Ok(x) => x,
-        Err(e) => return RetBar::Return(Err(e)),
+        Ok(x1) => x1,
+        Err(e) => return RetBar::Return(Err(From::from(e))),
     } {
         () => (),
     };
     RetBar::Ok(())
 }
-fn main() {
-    new_foo().unwrap();
-}
+
 enum RetBar<A, B> {
     Ok(A),
     Return(B),
 }
+
+fn main() {
+    new_foo().unwrap();
+}
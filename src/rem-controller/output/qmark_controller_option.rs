@@ -1,31 +1,35 @@
 struct A {
     x: Option<()>,
 }
+
 fn new_foo() -> Option<()> {
     let x = Some(());
     match bar(x) {
-        RetBar::Ok(x) => x,
-        RetBar::Return(x) => return x,
+        RetBar::Ok(x1) => x1,
+        RetBar::Return(x1) => return x1,
     }
 }
+
 fn bar(x: Option<()>) -> RetBar<Option<()>, Option<()>> {
     let _y = match x {
-        Some(x) => x,
+        Some(x1) => x1,
         None => return RetBar::Return(None),
     };
     let a = A { x: None };
     let result = match match a.x {
-        Some(x) => x,
+        Some(x1) => x1,
         None => return RetBar::Return(None),
     } {
         () => Some(()),
     };
     RetBar::Ok(result)
 }
-fn main() {
-    new_foo().unwrap();
-}
+
 enum RetBar<A, B> {
     Ok(A),
     Return(B),
 }
+
+fn main() {
+    new_foo().unwrap();
+}
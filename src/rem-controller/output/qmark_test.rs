@@ -20,12 +20,13 @@ impl ObjectId {
                 }
             }) {
                 Ok(x) => x,
-                Err(e) => return RetBar::Return(Err(e)),
+                Err(e) => return RetBar::Return(Err(From::from(e))),
             },
         ));
         RetBar::Ok(result)
     }
 }
+
 enum RetBar<A, B> {
     Ok(A),
     Return(B),
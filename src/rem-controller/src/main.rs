@@ -1,7 +1,7 @@
-mod non_local_controller;
 use clap::{Parser, Subcommand};
 use colored::Colorize;
-use rem_utils::compile_file;
+use rem_controller::non_local_controller;
+use rem_utils::{compile_file, format_source};
 use std::process::exit;
 use std::time::SystemTime;
 use std::{env, fs};
@@ -36,54 +36,75 @@ fn main() {
             caller_fn_name,
             callee_fn_name,
         } => {
-            if non_local_controller::make_controls(
+            match non_local_controller::inner_make_controls(
                 file_name.as_str(),
                 new_file_name.as_str(),
                 callee_fn_name.as_str(),
                 caller_fn_name.as_str(),
             ) {
-                exit(0)
-            } else {
-                exit(1)
+                Ok(_) => exit(0),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1)
+                }
             }
         }
     }
 }
 
+/// Fixtures using crates rustc is not given, only compared with their output
+const UNCOMPILED: &[&str] = &["qmark_test.rs"];
+
 fn test() {
+    let out_dir = env::temp_dir().join("rem-controller");
+    fs::create_dir_all(&out_dir).unwrap();
+    let mut failed = vec![];
     for file in fs::read_dir("./input").unwrap() {
         let test_name = file.unwrap().file_name().to_owned();
         if test_name.to_str().unwrap() == "if_return_unit_controller.rs" {
             continue;
         }
-        if !test_name.to_str().unwrap().contains("qmark_test") {
-            continue;
-        }
         let file_name = format!("./input/{}", test_name.to_str().unwrap());
-        let new_file_name = format!("./output/{}", test_name.to_str().unwrap());
+        let expected_file_name = format!("./output/{}", test_name.to_str().unwrap());
+        let new_file_name = out_dir.join(&test_name);
+        let new_file_name = new_file_name.to_str().unwrap();
         let callee_fn_name = "bar";
         let caller_fn_name = "new_foo";
         let now = SystemTime::now();
-        let success = non_local_controller::make_controls(
+        let success = non_local_controller::inner_make_controls(
             file_name.as_str(),
-            new_file_name.as_str(),
+            new_file_name,
             callee_fn_name,
             caller_fn_name,
-        );
+        )
+        .is_ok();
         let time_elapsed = now.elapsed().unwrap();
-        let args = vec![];
-        let mut compile_cmd = compile_file(new_file_name.as_str(), &args);
-        let out = compile_cmd.output().unwrap();
+        // the controller keeps the layout of the input, the outputs are formatted
+        let same = success
+            && format_source(&fs::read_to_string(new_file_name).unwrap())
+                == fs::read_to_string(&expected_file_name).unwrap();
+        let compiles = UNCOMPILED.contains(&test_name.to_str().unwrap()) || {
+            let args = vec!["--edition", "2021", "--out-dir", out_dir.to_str().unwrap()];
+            let mut compile_cmd = compile_file(new_file_name, &args);
+            compile_cmd.output().unwrap().status.success()
+        };
         println!(
             "{}: {} in {:#?}",
-            (if out.status.success() && success {
+            (if same && compiles {
                 format!("PASSED").green()
             } else {
+                failed.push(test_name.clone());
                 format!("FAILED").red()
             }),
             test_name.to_str().unwrap(),
             time_elapsed
         );
+        if success && !same {
+            println!("output differs from {}", expected_file_name);
+        }
         println!("------------------------------------------------------------------\n");
     }
+    if !failed.is_empty() {
+        exit(1)
+    }
 }
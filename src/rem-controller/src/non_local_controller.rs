@@ -1,16 +1,208 @@
-use std::fs;
-
 use convert_case::{Case, Casing};
 use log::debug;
-use proc_macro2::{Ident, Span};
+use proc_macro2::{Ident, Span, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
-use rem_utils::{format_source, FindCallee};
-use syn::visit_mut::VisitMut;
-use syn::{Block, Expr, ExprCall, ExprMatch, ExprMethodCall, ExprReturn, ExprTry, ImplItemMethod, Item, ItemFn, ItemImpl, ItemMod, ItemTrait, ReturnType, Signature, Stmt, TraitItemMethod, Type};
+use rem_utils::error::Error;
+use rem_utils::filesystem::{FileSystem, RealFileSystem};
+use rem_utils::resolve::{resolve_fn, CallSites, FnId};
+use rem_utils::typ::{alias_context, ProgramTypeContext, RustType};
+use rem_utils::{edit::splice, format_source, FindCallee};
+use std::collections::HashSet;
+use syn::spanned::Spanned;
 use syn::token::Brace;
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
+use syn::{
+    Block, Expr, ExprAsync, ExprCall, ExprClosure, ExprMatch, ExprMethodCall, ExprReturn, ExprTry,
+    ExprTryBlock, ImplItem, ImplItemMethod, Item, ItemEnum, ItemFn, ItemImpl, ItemMod, ItemTrait,
+    Label, Lifetime, Local, Macro, Pat, ReturnType, Signature, Stmt, TraitItemMethod, Type,
+};
 
 const ENUM_NAME: &str = "Ret";
 
+const SHARED_ENUM_NAME: &str = "RemControl";
+
+/// Where the call sites and callees refer to the shared enum, defined once at
+/// the crate root.
+const SHARED_ENUM_PATH: &str = "crate::RemControl";
+
+/// A callee only leaving its caller some of the ways sets the payloads of the
+/// others to `Infallible`, so their call sites need no arm of their own.
+const SHARED_ENUM: &str =
+    "pub enum RemControl<A, R, B, C> {\nOk(A),\nReturn(R),\nBreak(B),\nContinue(C),\n}";
+
+/// What the callee returns for its call sites to know how it exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ControlEnum {
+    /// A new `Ret<Callee>` enum for each extraction
+    #[default]
+    Dedicated,
+    /// `std::ops::ControlFlow` when the callee leaves the caller one way
+    /// only, the shared enum otherwise
+    ControlFlow,
+    /// A single generic `RemControl` enum for the whole crate, added to the
+    /// crate root if not there yet
+    Shared,
+}
+
+/// The enum the callee returns in this extraction. Labels and `break`
+/// values always need a dedicated one.
+#[derive(Debug)]
+enum Target {
+    Dedicated(String),
+    ControlFlow,
+    /// The ways out of the caller the callee takes
+    Shared {
+        has_return: bool,
+        has_break: bool,
+        has_continue: bool,
+    },
+}
+
+impl Target {
+    /// The expression or pattern standing for the variant of the dedicated
+    /// enum named `variant`.
+    fn variant(&self, variant: &str, payload: Option<&str>) -> String {
+        let (path, payload) = match self {
+            Target::Dedicated(name) => (format!("{}::{}", name, variant), payload),
+            // `Break` and `Continue` carry `()` there
+            Target::Shared { .. } => (
+                format!("{}::{}", SHARED_ENUM_PATH, variant),
+                payload.or(Some("()")),
+            ),
+            // the callee leaves the caller one way only, all of them `Break`
+            Target::ControlFlow if variant == "Ok" => {
+                ("core::ops::ControlFlow::Continue".to_string(), payload)
+            }
+            Target::ControlFlow => (
+                "core::ops::ControlFlow::Break".to_string(),
+                payload.or(Some("()")),
+            ),
+        };
+        match payload {
+            Some(payload) => format!("{}({})", path, payload),
+            None => path,
+        }
+    }
+
+    /// The return type of the callee, `payloads` being what the variants
+    /// after `Ok` carry.
+    fn ty(&self, callee_rety: &Type, payloads: &[Type]) -> Type {
+        let unit = Type::Verbatim(quote! {()});
+        let payload = payloads.first().unwrap_or(&unit);
+        match self {
+            Target::Dedicated(name) => {
+                let ident = Ident::new(name, Span::call_site());
+                Type::Verbatim(quote! {#ident<#callee_rety #(,#payloads)*>})
+            }
+            Target::Shared {
+                has_return,
+                has_break,
+                has_continue,
+            } => {
+                let name = Ident::new(SHARED_ENUM_NAME, Span::call_site());
+                let path = quote! {crate::#name};
+                let never = Type::Verbatim(quote! {core::convert::Infallible});
+                let or_never = |used: bool, ty: &Type| match used {
+                    true => ty.clone(),
+                    false => never.clone(),
+                };
+                let (ret, brk, cont) = (
+                    or_never(*has_return, payload),
+                    or_never(*has_break, &unit),
+                    or_never(*has_continue, &unit),
+                );
+                Type::Verbatim(quote! {#path<#callee_rety, #ret, #brk, #cont>})
+            }
+            Target::ControlFlow => {
+                Type::Verbatim(quote! {core::ops::ControlFlow<#payload, #callee_rety>})
+            }
+        }
+    }
+
+    /// The arms of the call site `match` for the variants of the shared enum
+    /// the callee never returns, their payload being `Infallible`.
+    fn unused_arms(&self, x: &str) -> String {
+        match self {
+            Target::Shared {
+                has_return,
+                has_break,
+                has_continue,
+            } => [
+                ("Return", has_return),
+                ("Break", has_break),
+                ("Continue", has_continue),
+            ]
+            .iter()
+            .filter(|(_, used)| !**used)
+            .map(|(variant, _)| format!("{} => match {x} {{}},\n", self.variant(variant, Some(x))))
+            .collect(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Parse code the controller put together, which embeds pieces of the input
+/// as text.
+fn parse_generated<T: syn::parse::Parse>(code: &str) -> Result<T, Error> {
+    syn::parse_str(code)
+        .map_err(|e| Error::Other(format!("could not parse generated `{}`: {}", code, e)))
+}
+
+/// Whether `items` define or import an item named `name`.
+fn declares(items: &[Item], name: &str) -> bool {
+    fn imports(tree: &syn::UseTree, name: &str) -> bool {
+        match tree {
+            syn::UseTree::Path(p) => imports(&p.tree, name),
+            syn::UseTree::Name(n) => n.ident == name,
+            syn::UseTree::Rename(r) => r.rename == name,
+            syn::UseTree::Group(g) => g.items.iter().any(|tree| imports(tree, name)),
+            syn::UseTree::Glob(_) => false,
+        }
+    }
+    items.iter().any(|item| match item {
+        Item::Enum(e) => e.ident == name,
+        Item::Use(u) => imports(&u.tree, name),
+        _ => false,
+    })
+}
+
+/// Put the enum right after the item defining the callee, in the same module
+/// and as visible as the callee. Returns whether the callee was found.
+fn place_enum(items: &mut Vec<Item>, callee: &FnId, enum_item: &ItemEnum) -> bool {
+    for idx in 0..items.len() {
+        let vis = match &items[idx] {
+            item if !callee.is_defined_in(std::slice::from_ref(item)) => None,
+            Item::Fn(f) => Some(f.vis.clone()),
+            Item::Impl(i) => i.items.iter().find_map(|item| match item {
+                ImplItem::Method(m) if callee.is(&m.sig.ident) => Some(m.vis.clone()),
+                _ => None,
+            }),
+            // trait items are as visible as the trait
+            Item::Trait(t) => Some(t.vis.clone()),
+            _ => None,
+        };
+        if let Some(vis) = vis {
+            let enum_item = ItemEnum {
+                vis,
+                ..enum_item.clone()
+            };
+            items.insert(idx + 1, Item::Enum(enum_item));
+            return true;
+        }
+        if let Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = &mut items[idx]
+        {
+            if place_enum(items, callee, enum_item) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 fn make_pascal_case(s: &str) -> String {
     let result = s.to_case(Case::Pascal);
     match result.strip_suffix("ExtractThis") {
@@ -19,74 +211,287 @@ fn make_pascal_case(s: &str) -> String {
     }
 }
 
-struct CheckCalleeWithinLoopHelper<'a> {
-    callee_fn_name: &'a str,
-    callee_in_loop: bool,
+struct IdentCollector {
+    idents: HashSet<String>,
+}
+
+impl IdentCollector {
+    fn visit_tokens(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(i) => {
+                    self.idents.insert(i.to_string());
+                }
+                TokenTree::Group(g) => self.visit_tokens(g.stream()),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for IdentCollector {
+    fn visit_ident(&mut self, i: &'ast Ident) {
+        self.idents.insert(i.to_string());
+    }
+
+    fn visit_macro(&mut self, i: &'ast Macro) {
+        self.visit_tokens(i.tokens.clone());
+        syn::visit::visit_macro(self, i);
+    }
+}
+
+fn collect_idents(file: &syn::File) -> HashSet<String> {
+    let mut collector = IdentCollector {
+        idents: HashSet::new(),
+    };
+    collector.visit_file(file);
+    collector.idents
+}
+
+struct TypeCollector {
+    types: HashSet<String>,
+    glob: bool,
+}
+
+impl<'ast> Visit<'ast> for TypeCollector {
+    fn visit_item(&mut self, i: &'ast Item) {
+        let ident = match i {
+            Item::Enum(e) => Some(&e.ident),
+            Item::Struct(s) => Some(&s.ident),
+            Item::Union(u) => Some(&u.ident),
+            Item::Type(t) => Some(&t.ident),
+            Item::Trait(t) => Some(&t.ident),
+            Item::TraitAlias(t) => Some(&t.ident),
+            _ => None,
+        };
+        if let Some(ident) = ident {
+            self.types.insert(ident.to_string());
+        }
+        syn::visit::visit_item(self, i);
+    }
+
+    fn visit_use_tree(&mut self, i: &'ast syn::UseTree) {
+        match i {
+            syn::UseTree::Name(n) => {
+                self.types.insert(n.ident.to_string());
+            }
+            syn::UseTree::Rename(r) => {
+                self.types.insert(r.rename.to_string());
+            }
+            syn::UseTree::Glob(_) => self.glob = true,
+            _ => (),
+        }
+        syn::visit::visit_use_tree(self, i);
+    }
+
+    fn visit_type_param(&mut self, i: &'ast syn::TypeParam) {
+        self.types.insert(i.ident.to_string());
+        syn::visit::visit_type_param(self, i);
+    }
+}
+
+/// The types a file declares or imports by name, `None` if it also imports
+/// some with a glob.
+fn collect_types(file: &syn::File) -> Option<HashSet<String>> {
+    let mut collector = TypeCollector {
+        types: HashSet::new(),
+        glob: false,
+    };
+    collector.visit_file(file);
+    (!collector.glob).then_some(collector.types)
+}
+
+/// The names the controller binds in the code it generates, none of them an
+/// identifier of the file already.
+#[derive(Debug)]
+struct Names {
+    /// of the `Ret` enum
+    ret: String,
+    /// of what the callee or a `?` gives back
+    value: String,
+    /// of what a `?` returns early with
+    residual: String,
+    /// of the tail of the callee
+    result: String,
+}
+
+impl Names {
+    fn new(
+        mut idents: HashSet<String>,
+        types: Option<HashSet<String>>,
+        callee_fn_name: &str,
+    ) -> Self {
+        let ret = format!("{}{}", ENUM_NAME, make_pascal_case(callee_fn_name));
+        // a file referring to the enum without defining it refers to the one
+        // an earlier run of the controller made, which is defined again
+        if types.is_some_and(|types| !types.contains(&ret)) {
+            idents.remove(&ret);
+        }
+        let mut fresh = |name: String| {
+            let mut fresh = name.clone();
+            let mut n = 0;
+            while idents.contains(&fresh) {
+                n += 1;
+                fresh = format!("{}{}", name, n);
+            }
+            idents.insert(fresh.clone());
+            fresh
+        };
+        Names {
+            ret: fresh(ret),
+            value: fresh("x".to_string()),
+            residual: fresh("e".to_string()),
+            result: fresh("result".to_string()),
+        }
+    }
+}
+
+fn label_name(label: &Option<Label>) -> Option<String> {
+    label.as_ref().map(|l| l.name.ident.to_string())
+}
+
+/// A loop of the caller around the call to the callee.
+#[derive(Debug, Clone)]
+struct CallerLoop {
+    label: Option<String>,
+    /// the type of the value a `loop` breaks with, if it is annotated
+    ty: Option<Type>,
+}
+
+/// What the call to the callee is in: the function, closure or async block
+/// whose body its `return`, `?`, `break` and `continue` would leave.
+#[derive(Debug, Clone)]
+struct CallContext {
+    /// `None` for closures and async blocks whose return type is inferred
+    rety: Option<ReturnType>,
+    span: Span,
+    /// innermost last
+    loops: Vec<CallerLoop>,
+}
+
+struct CheckCalleeContext<'a> {
+    callee: &'a CallSites,
+    context: CallContext,
+    found: Option<CallContext>,
+    // the type of the expression being visited, if known, for the `loop`s
+    // whose value it is
+    expected: Option<Type>,
+}
+
+impl CheckCalleeContext<'_> {
+    fn visit_context_mut<F: FnOnce(&mut Self)>(&mut self, context: CallContext, visit: F) {
+        let outer = std::mem::replace(&mut self.context, context);
+        visit(self);
+        self.context = outer;
+    }
+
+    fn visit_loop_mut<F: FnOnce(&mut Self)>(&mut self, label: Option<String>, visit: F) {
+        let ty = self.expected.take();
+        self.context.loops.push(CallerLoop { label, ty });
+        visit(self);
+        self.context.loops.pop();
+    }
 }
 
-impl VisitMut for CheckCalleeWithinLoopHelper<'_> {
+impl VisitMut for CheckCalleeContext<'_> {
     fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
-        let id = i.func.as_ref().into_token_stream().to_string();
-        match id.contains(self.callee_fn_name) {
-            true => self.callee_in_loop = true,
+        match self.found.is_none() && self.callee.is_call(&i.func) {
+            true => self.found = Some(self.context.clone()),
             false => syn::visit_mut::visit_expr_call_mut(self, i),
         }
     }
 
     fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let callee = i.clone().method.into_token_stream().to_string();
-        match callee.contains(self.callee_fn_name) {
-            true => self.callee_in_loop = true,
+        match self.found.is_none() && self.callee.is_method_call(i) {
+            true => self.found = Some(self.context.clone()),
             false => syn::visit_mut::visit_expr_method_call_mut(self, i),
         }
     }
-}
 
-struct CheckCalleeWithinLoop<'a> {
-    callee_fn_name: &'a str,
-    callee_in_loop: bool,
-}
-
-impl VisitMut for CheckCalleeWithinLoop<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
-        let mut helper = CheckCalleeWithinLoopHelper {
-            callee_fn_name: self.callee_fn_name,
-            callee_in_loop: self.callee_in_loop,
-        };
         match i {
+            Expr::Loop(l) => {
+                let label = label_name(&l.label);
+                self.visit_loop_mut(label, |v| syn::visit_mut::visit_expr_loop_mut(v, l))
+            }
             Expr::ForLoop(l) => {
-                helper.visit_expr_for_loop_mut(l);
-                if helper.callee_in_loop {
-                    self.callee_in_loop = true
-                };
+                let label = label_name(&l.label);
+                self.visit_loop_mut(label, |v| syn::visit_mut::visit_expr_for_loop_mut(v, l))
             }
-            Expr::Loop(l) => {
-                helper.visit_expr_loop_mut(l);
-                if helper.callee_in_loop {
-                    self.callee_in_loop = true
+            Expr::While(l) => {
+                let label = label_name(&l.label);
+                self.visit_loop_mut(label, |v| syn::visit_mut::visit_expr_while_mut(v, l))
+            }
+            // the type of a block is the type of its tail
+            Expr::Block(_) => syn::visit_mut::visit_expr_mut(self, i),
+            Expr::Closure(c) => {
+                let context = CallContext {
+                    rety: match &c.output {
+                        ReturnType::Default => None,
+                        rety => Some(rety.clone()),
+                    },
+                    span: c.span(),
+                    loops: vec![],
                 };
+                self.visit_context_mut(context, |v| {
+                    v.expected = match &c.output {
+                        ReturnType::Default => None,
+                        ReturnType::Type(_, t) => Some(t.as_ref().clone()),
+                    };
+                    v.visit_expr_mut(&mut c.body)
+                })
             }
-            Expr::While(l) => {
-                helper.visit_expr_while_mut(l);
-                if helper.callee_in_loop {
-                    self.callee_in_loop = true
+            Expr::Async(a) => {
+                let context = CallContext {
+                    rety: None,
+                    span: a.span(),
+                    loops: vec![],
                 };
+                self.expected = None;
+                self.visit_context_mut(context, |v| syn::visit_mut::visit_expr_async_mut(v, a))
+            }
+            _ => {
+                self.expected = None;
+                syn::visit_mut::visit_expr_mut(self, i)
             }
+        }
+    }
 
-            _ => syn::visit_mut::visit_expr_mut(self, i),
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        let expected = self.expected.take();
+        let tail = i.stmts.len().wrapping_sub(1);
+        for (idx, stmt) in i.stmts.iter_mut().enumerate() {
+            if idx == tail {
+                if let Stmt::Expr(_) = stmt {
+                    self.expected = expected.clone();
+                }
+            }
+            self.visit_stmt_mut(stmt);
+        }
+    }
+
+    fn visit_local_mut(&mut self, i: &mut Local) {
+        if let Some((_, init)) = &mut i.init {
+            self.expected = match &i.pat {
+                Pat::Type(t) => Some(t.ty.as_ref().clone()),
+                _ => None,
+            };
+            self.visit_expr_mut(init);
         }
     }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // a nested function is a caller of its own
+    }
 }
 
 struct CallerVisitor<'a> {
     found: bool,
-    caller_fn_name: &'a str,
+    caller: &'a FnId,
     callee_finder: &'a mut FindCallee<'a>,
-    callee_fn_name: &'a str,
-    callee_in_loop: bool,
     // very simplified handling: if caller has loop and callee has break/continue but no loop
     // assume it's control flow for caller otherwise, keep the same (assume control for callee loop)
-    caller_rety: &'a mut ReturnType,
+    context: Option<CallContext>,
 }
 
 impl VisitMut for CallerVisitor<'_> {
@@ -95,15 +500,15 @@ impl VisitMut for CallerVisitor<'_> {
             return;
         }
         debug!("finding caller in impl...");
-        let id = i.sig.ident.clone().to_string();
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
-                debug!("found same id: {}...", id);
+                debug!("found same id: {}...", i.sig.ident);
                 self.callee_finder.visit_impl_item_method_mut(i);
                 debug!(
                     "found callee: {}? {}...",
-                    self.callee_finder.callee_fn_name, self.callee_finder.found
+                    self.callee_finder.callee.name(),
+                    self.callee_finder.found
                 );
                 if !self.callee_finder.found {
                     return;
@@ -119,8 +524,7 @@ impl VisitMut for CallerVisitor<'_> {
             return;
         }
 
-        let id = i.sig.ident.clone().to_string();
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 self.callee_finder.visit_item_fn_mut(i);
@@ -130,6 +534,7 @@ impl VisitMut for CallerVisitor<'_> {
                 self.caller_visitor(&mut i.sig, &mut i.block)
             }
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
@@ -137,8 +542,7 @@ impl VisitMut for CallerVisitor<'_> {
             return;
         }
 
-        let id = i.sig.ident.clone().to_string();
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 self.callee_finder.visit_trait_item_method_mut(i);
@@ -158,59 +562,93 @@ impl VisitMut for CallerVisitor<'_> {
 impl CallerVisitor<'_> {
     fn caller_visitor(&mut self, sig: &mut Signature, block: &mut Block) {
         self.found = true;
-        *self.caller_rety = sig.output.clone();
-        let mut helper = CheckCalleeWithinLoop {
-            callee_fn_name: self.callee_fn_name,
-            callee_in_loop: false,
+        let mut helper = CheckCalleeContext {
+            callee: self.callee_finder.callee,
+            context: CallContext {
+                rety: Some(sig.output.clone()),
+                span: sig.span(),
+                loops: vec![],
+            },
+            found: None,
+            expected: match &sig.output {
+                ReturnType::Default => None,
+                ReturnType::Type(_, t) => Some(t.as_ref().clone()),
+            },
         };
         helper.visit_block_mut(block);
-        self.callee_in_loop = helper.callee_in_loop;
+        self.context = Some(helper.found.unwrap_or(helper.context));
     }
 }
 
 enum RetTyQMark {
     QMarkOption,
     QMarkResult,
+    QMarkControlFlow,
 }
 
-struct CalleeDeSugarQMark {
+/// How `?` returns from a function returning `ty`, by the type constructor
+/// `ty` is an alias of.
+fn rety_qmark(ty: &Type, aliases: &ProgramTypeContext) -> Result<RetTyQMark, Error> {
+    let mut head = RustType::head(ty);
+    if let Some(head) = head.as_mut() {
+        head.resolve(aliases);
+    }
+    match head {
+        Some(RustType::Applied(id, _)) if id == "Option" => Ok(RetTyQMark::QMarkOption),
+        Some(RustType::Applied(id, _)) | Some(RustType::CAlias(id)) if id == "Result" => {
+            Ok(RetTyQMark::QMarkResult)
+        }
+        Some(RustType::Applied(id, _)) if id == "ControlFlow" => Ok(RetTyQMark::QMarkControlFlow),
+        // e.g. `Poll<Result<T, E>>`, where `?` may be on a `Result` or a `Poll`
+        _ => Err(Error::unsupported(
+            "`?` in a function returning neither a `Result`, an `Option` nor a `ControlFlow`",
+            ty.span(),
+        )),
+    }
+}
+
+struct CalleeDeSugarQMark<'a> {
     has_desugared: bool,
     rety_qmark: RetTyQMark,
+    names: &'a Names,
+    error: Option<Error>,
 }
 
-impl VisitMut for CalleeDeSugarQMark {
+impl VisitMut for CalleeDeSugarQMark<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         match i {
             Expr::Try(ExprTry { expr, .. }) => {
                 let inner = expr.as_mut().clone();
-                match self.rety_qmark {
-                    RetTyQMark::QMarkOption => {
-                        *i = syn::parse_str(
-                            format!(
-                                "match {} {{ Some(x) => x, None => return None }}",
-                                inner.into_token_stream().to_string()
-                            )
-                            .as_str(),
-                        )
-                        .unwrap();
-                    }
-                    RetTyQMark::QMarkResult => {
-                        *i = syn::parse_str(
-                            format!(
-                                "match {} {{ Ok(x) => x, Err(e) => return Err(e) }}",
-                                inner.into_token_stream().to_string()
-                            )
-                            .as_str(),
-                        )
-                        .unwrap();
-                    }
+                let (x, e) = (&self.names.value, &self.names.residual);
+                let inner = inner.into_token_stream().to_string();
+                let desugared = match self.rety_qmark {
+                    RetTyQMark::QMarkOption => format!(
+                        "match {} {{ Some({x}) => {x}, None => return None }}",
+                        inner
+                    ),
+                    RetTyQMark::QMarkResult => format!(
+                        "match {} {{ Ok({x}) => {x}, Err({e}) => return Err(From::from({e})) }}",
+                        inner
+                    ),
+                    RetTyQMark::QMarkControlFlow => format!(
+                        "match {} {{ core::ops::ControlFlow::Continue({x}) => {x}, core::ops::ControlFlow::Break({e}) => return core::ops::ControlFlow::Break({e}) }}",
+                        inner
+                    ),
+                };
+                match parse_generated(&desugared) {
+                    Ok(desugared) => *i = desugared,
+                    Err(err) => self.error = Some(err),
                 }
                 self.has_desugared = true;
             }
+            // the `?` in these do not leave the callee
+            Expr::Closure(_) | Expr::Async(_) | Expr::TryBlock(_) => return,
             _ => (),
         }
         syn::visit_mut::visit_expr_mut(self, i);
     }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
 }
 
 struct CalleeCheckReturn {
@@ -222,45 +660,179 @@ impl VisitMut for CalleeCheckReturn {
         debug!("has return?{:?}", _e);
         self.has_return = true
     }
+
+    // returning from a closure, async block or nested function does not
+    // return from the callee
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+struct CalleeCheckQMark {
+    has_qmark: bool,
+}
+
+impl VisitMut for CalleeCheckQMark {
+    fn visit_expr_try_mut(&mut self, _: &mut ExprTry) {
+        self.has_qmark = true
+    }
+
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_expr_try_block_mut(&mut self, _: &mut ExprTryBlock) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// A `break` or `continue` of the callee out to a loop of the caller, one
+/// variant of the `Ret` enum per kind and label.
+#[derive(Debug, Clone)]
+struct LoopExit {
+    is_break: bool,
+    label: Option<String>,
+    /// where it breaks with a value, if it does
+    value: Option<Span>,
+}
+
+impl LoopExit {
+    fn new(is_break: bool, label: &Option<Lifetime>, value: Option<Span>) -> Self {
+        LoopExit {
+            is_break,
+            label: label.as_ref().map(|l| l.ident.to_string()),
+            value,
+        }
+    }
+
+    fn variant(&self) -> String {
+        format!(
+            "{}{}",
+            if self.is_break { "Break" } else { "Continue" },
+            self.label
+                .as_ref()
+                .map_or(String::new(), |l| make_pascal_case(l))
+        )
+    }
+
+    /// The arm of the call site `match` doing the same in the caller.
+    fn arm(&self, target: &Target, x: &str) -> String {
+        let jump = format!(
+            "{}{}",
+            if self.is_break { "break" } else { "continue" },
+            self.label
+                .as_ref()
+                .map_or(String::new(), |l| format!(" '{}", l))
+        );
+        match self.value {
+            Some(_) => format!(
+                "{} => {} {},\n",
+                target.variant(&self.variant(), Some(x)),
+                jump,
+                x
+            ),
+            None => format!("{} => {},\n", target.variant(&self.variant(), None), jump),
+        }
+    }
+}
+
+/// The loops and labelled blocks of the callee around the expression being
+/// visited: a `break` or `continue` to one of them stays in the callee.
+#[derive(Clone, Default)]
+struct CalleeLoops {
+    labels: Vec<String>,
+    depth: usize,
+}
+
+impl CalleeLoops {
+    fn enter(&self, e: &Expr) -> Option<CalleeLoops> {
+        let (label, is_loop) = match e {
+            Expr::ForLoop(l) => (&l.label, true),
+            Expr::Loop(l) => (&l.label, true),
+            Expr::While(l) => (&l.label, true),
+            Expr::Block(b) if b.label.is_some() => (&b.label, false),
+            _ => return None,
+        };
+        let mut inner = self.clone();
+        inner.labels.extend(label_name(label));
+        inner.depth += is_loop as usize;
+        Some(inner)
+    }
+
+    fn leaves(&self, label: &Option<Lifetime>) -> bool {
+        match label {
+            None => self.depth == 0,
+            Some(l) => !self.labels.contains(&l.ident.to_string()),
+        }
+    }
 }
 
 struct CalleeCheckLoops {
-    has_break: bool,
-    has_continue: bool,
+    loops: CalleeLoops,
+    exits: Vec<LoopExit>,
+}
+
+impl CalleeCheckLoops {
+    fn check_exit(&mut self, is_break: bool, label: &Option<Lifetime>, value: Option<Span>) {
+        if !self.loops.leaves(label) {
+            return;
+        }
+        let exit = LoopExit::new(is_break, label, value);
+        match self
+            .exits
+            .iter_mut()
+            .find(|e| e.is_break == exit.is_break && e.label == exit.label)
+        {
+            Some(e) => e.value = e.value.or(exit.value),
+            None => self.exits.push(exit),
+        }
+    }
 }
 
 impl VisitMut for CalleeCheckLoops {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         match i {
-            Expr::Break(_) => self.has_break = true,
-            Expr::Continue(_) => self.has_continue = true,
+            Expr::Break(e) => self.check_exit(true, &e.label, e.expr.as_ref().map(|v| v.span())),
+            Expr::Continue(e) => self.check_exit(false, &e.label, None),
 
-            // don't check for loop control within callee loops
-            Expr::ForLoop(_) => (),
-            Expr::Loop(_) => (),
-            Expr::While(_) => (),
+            // closures and async blocks cannot break out
+            Expr::Closure(_) => return,
+            Expr::Async(_) => return,
 
-            _ => syn::visit_mut::visit_expr_mut(self, i),
+            _ => (),
+        }
+        match self.loops.enter(i) {
+            Some(loops) => {
+                let outer = std::mem::replace(&mut self.loops, loops);
+                syn::visit_mut::visit_expr_mut(self, i);
+                self.loops = outer;
+            }
+            None => syn::visit_mut::visit_expr_mut(self, i),
         }
     }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
 }
 
 #[derive(Debug)]
 struct CalleeCheckNCF<'a> {
     found: bool,
-    callee_fn_name: &'a str,
-    caller_rety: ReturnType,
+    callee: &'a FnId,
+    caller_rety: Option<ReturnType>,
+    aliases: &'a ProgramTypeContext,
+    names: &'a Names,
+    error: Option<Error>,
     within_caller_loop: bool,
-    has_break: bool,
-    has_continue: bool,
+    exits: Vec<LoopExit>,
     has_return: bool,
     num_inputs: usize,
 }
 
 impl VisitMut for CalleeCheckNCF<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.callee_check_ncf(i.sig.clone(), &mut i.block),
         }
@@ -268,16 +840,15 @@ impl VisitMut for CalleeCheckNCF<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.callee_check_ncf(i.sig.clone(), &mut i.block),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
@@ -295,25 +866,22 @@ impl CalleeCheckNCF<'_> {
         self.found = true;
 
         match &self.caller_rety {
-            ReturnType::Default => {}
-            ReturnType::Type(_, ty) => {
+            // whatever it is, `?` leaves the closure or async block
+            None => {
+                let mut check_qmark = CalleeCheckQMark { has_qmark: false };
+                check_qmark.visit_block_mut(block);
+                self.has_return = check_qmark.has_qmark || self.has_return;
+            }
+            Some(ReturnType::Default) => {}
+            Some(ReturnType::Type(_, ty)) => {
+                let mut check_qmark = CalleeCheckQMark { has_qmark: false };
+                check_qmark.visit_block_mut(block);
                 let mut rety = None;
-                if ty
-                    .as_ref()
-                    .clone()
-                    .into_token_stream()
-                    .to_string()
-                    .starts_with("Result")
-                {
-                    rety = Some(RetTyQMark::QMarkResult)
-                } else if ty
-                    .as_ref()
-                    .clone()
-                    .into_token_stream()
-                    .to_string()
-                    .starts_with("Option")
-                {
-                    rety = Some(RetTyQMark::QMarkOption)
+                if check_qmark.has_qmark {
+                    match rety_qmark(ty, self.aliases) {
+                        Ok(rety_qmark) => rety = Some(rety_qmark),
+                        Err(e) => self.error = Some(e),
+                    }
                 }
 
                 match rety {
@@ -323,8 +891,13 @@ impl CalleeCheckNCF<'_> {
                         let mut desugar_qmark = CalleeDeSugarQMark {
                             has_desugared: false,
                             rety_qmark,
+                            names: self.names,
+                            error: None,
                         };
                         desugar_qmark.visit_block_mut(block);
+                        if let Some(e) = desugar_qmark.error {
+                            self.error = Some(e);
+                        }
                         debug!("desugaring...{}", desugar_qmark.has_desugared);
                         self.has_return = desugar_qmark.has_desugared || self.has_return;
                     }
@@ -338,8 +911,8 @@ impl CalleeCheckNCF<'_> {
         };
 
         let mut check_loops = CalleeCheckLoops {
-            has_break: self.has_break,
-            has_continue: self.has_continue,
+            loops: CalleeLoops::default(),
+            exits: self.exits.clone(),
         };
         block.stmts.iter_mut().for_each(|stmt| {
             if !self.has_return {
@@ -350,18 +923,19 @@ impl CalleeCheckNCF<'_> {
             }
         });
         self.has_return = check_return.has_return;
-        self.has_break = check_loops.has_break;
-        self.has_continue = check_loops.has_continue;
+        self.exits = check_loops.exits;
     }
 }
 
-struct MakeLastReturnBlkVisitor {}
+struct MakeLastReturnBlkVisitor<'a> {
+    result: &'a str,
+}
 
-impl VisitMut for MakeLastReturnBlkVisitor {
+impl VisitMut for MakeLastReturnBlkVisitor<'_> {
     fn visit_stmt_mut(&mut self, i: &mut Stmt) {
         match i {
             Stmt::Expr(e) => {
-                let re = quote!(result);
+                let re = Ident::new(self.result, Span::call_site());
                 let e = e.clone();
                 *i = syn::parse_quote! {let #re = #e;}
             }
@@ -371,8 +945,9 @@ impl VisitMut for MakeLastReturnBlkVisitor {
 }
 
 struct MakeBrkAndContVisitor<'a> {
-    callee_fn_name: &'a str,
-    success: bool,
+    target: &'a Target,
+    loops: CalleeLoops,
+    error: Option<Error>,
 }
 
 impl VisitMut for MakeBrkAndContVisitor<'_> {
@@ -382,43 +957,53 @@ impl VisitMut for MakeBrkAndContVisitor<'_> {
         //     i.clone().into_token_stream().to_string()
         // );
         match i {
-            Expr::Break(e) => {
-                match &e.expr {
-                    None => {}
-                    Some(_) => self.success = false,
-                }
-                let new_e_str = format!(
-                    "return {}{}::Break",
-                    ENUM_NAME,
-                    make_pascal_case(self.callee_fn_name)
-                );
-                let new_e: Expr = syn::parse_str(new_e_str.as_str()).unwrap();
-                *i = new_e
-            }
-            Expr::Continue(_) => {
-                let new_e_str = format!(
-                    "return {}{}::Continue",
-                    ENUM_NAME,
-                    make_pascal_case(self.callee_fn_name)
-                );
-                let new_e: Expr = syn::parse_str(new_e_str.as_str()).unwrap();
-                *i = new_e
+            Expr::Closure(_) | Expr::Async(_) => return,
+            _ => (),
+        }
+        match self.loops.enter(i) {
+            Some(loops) => {
+                let outer = std::mem::replace(&mut self.loops, loops);
+                syn::visit_mut::visit_expr_mut(self, i);
+                self.loops = outer;
             }
-            _ => syn::visit_mut::visit_expr_mut(self, i),
+            None => syn::visit_mut::visit_expr_mut(self, i),
+        }
+        let new_e_str = match i {
+            Expr::Break(e) if self.loops.leaves(&e.label) => {
+                let exit = LoopExit::new(true, &e.label, None);
+                let value = e.expr.as_ref().map(|v| v.into_token_stream().to_string());
+                format!(
+                    "return {}",
+                    self.target.variant(&exit.variant(), value.as_deref())
+                )
+            }
+            Expr::Continue(e) if self.loops.leaves(&e.label) => {
+                let exit = LoopExit::new(false, &e.label, None);
+                format!("return {}", self.target.variant(&exit.variant(), None))
+            }
+            _ => return,
+        };
+        match parse_generated(&new_e_str) {
+            Ok(new_e) => *i = new_e,
+            Err(e) => self.error = Some(e),
         }
     }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
 }
 
 struct MakeBrkAndCont<'a> {
-    callee_fn_name: &'a str,
-    success: bool,
+    callee: &'a FnId,
+    names: &'a Names,
+    target: &'a Target,
+    payloads: &'a [Type],
     already_did_return: bool,
+    error: Option<Error>,
 }
 
 impl VisitMut for MakeBrkAndCont<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.make_brk_and_cont(&mut i.sig, &mut i.block),
         }
@@ -426,17 +1011,16 @@ impl VisitMut for MakeBrkAndCont<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.make_brk_and_cont(&mut i.sig, &mut i.block),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
@@ -452,35 +1036,42 @@ impl VisitMut for MakeBrkAndCont<'_> {
 impl MakeBrkAndCont<'_> {
     fn make_brk_and_cont(&mut self, sig: &mut Signature, block: &mut Block) {
         let mut helper = MakeBrkAndContVisitor {
-            callee_fn_name: self.callee_fn_name,
-            success: self.success,
+            target: self.target,
+            loops: CalleeLoops::default(),
+            error: None,
         };
         helper.visit_block_mut(block);
-        self.success = helper.success;
+        if let Some(e) = helper.error {
+            self.error = Some(e);
+        }
         if !self.already_did_return {
-            let ident_str = format!("{}{}", ENUM_NAME, make_pascal_case(self.callee_fn_name));
-            let ident = Ident::new(ident_str.as_str(), Span::call_site());
             let callee_rety = match sig.output.clone() {
                 ReturnType::Default => Type::Verbatim(quote! {()}),
                 ReturnType::Type(_, t) => t.as_ref().clone(),
             };
-            let ty: Type = Type::Verbatim(quote! {#ident<#callee_rety>});
+            let ty = self.target.ty(&callee_rety, self.payloads);
             sig.output = ReturnType::Type(syn::parse_quote! {->}, Box::new(ty));
 
-            let ok = quote!(Ok);
             match block.stmts.last_mut() {
                 None => {}
                 Some(s) => match s {
                     Stmt::Expr(_) => {
-                        let mut helper = MakeLastReturnBlkVisitor {};
+                        let mut helper = MakeLastReturnBlkVisitor {
+                            result: &self.names.result,
+                        };
                         helper.visit_stmt_mut(s);
-                        let re = quote!(result);
-                        let ret_stmt_expr: Expr = syn::parse_quote! {#ident::#ok(#re)};
-                        block.stmts.push(Stmt::Expr(ret_stmt_expr))
+                        let ok = self.target.variant("Ok", Some(&self.names.result));
+                        match parse_generated(&ok) {
+                            Ok(ret_stmt_expr) => block.stmts.push(Stmt::Expr(ret_stmt_expr)),
+                            Err(e) => self.error = Some(e),
+                        }
                     }
                     _ => {
-                        let ret_stmt_expr: Expr = syn::parse_quote! {#ident::#ok(())};
-                        block.stmts.push(Stmt::Expr(ret_stmt_expr))
+                        let ok = self.target.variant("Ok", Some("()"));
+                        match parse_generated(&ok) {
+                            Ok(ret_stmt_expr) => block.stmts.push(Stmt::Expr(ret_stmt_expr)),
+                            Err(e) => self.error = Some(e),
+                        }
                     }
                 },
             }
@@ -489,14 +1080,17 @@ impl MakeBrkAndCont<'_> {
 }
 
 struct MakeReturn<'a> {
-    callee_fn_name: &'a str,
-    caller_rety: &'a Type,
+    callee: &'a FnId,
+    names: &'a Names,
+    target: &'a Target,
+    // what the variants after `Ok` carry, the caller's return type first
+    payloads: &'a [Type],
+    error: Option<Error>,
 }
 
 impl VisitMut for MakeReturn<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.make_return(&mut i.sig, &mut i.block),
         }
@@ -504,9 +1098,8 @@ impl VisitMut for MakeReturn<'_> {
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 let _ = i
@@ -519,42 +1112,45 @@ impl VisitMut for MakeReturn<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => self.make_return(&mut i.sig, &mut i.block),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 }
 
 impl MakeReturn<'_> {
     fn make_return(&mut self, sig: &mut Signature, block: &mut Block) {
-        let ident_str = format!("{}{}", ENUM_NAME, make_pascal_case(self.callee_fn_name));
-        let ident = Ident::new(ident_str.as_str(), Span::call_site());
-        let caller_rety = self.caller_rety.clone();
         let callee_rety = match sig.output.clone() {
             ReturnType::Default => Type::Verbatim(quote! {()}),
             ReturnType::Type(_, t) => t.as_ref().clone(),
         };
-        let ty: Type = Type::Verbatim(quote! {#ident<#callee_rety,#caller_rety>});
+        let ty = self.target.ty(&callee_rety, self.payloads);
         sig.output = ReturnType::Type(syn::parse_quote! {->}, Box::new(ty));
 
-        let ok = quote!(Ok);
         match block.stmts.last_mut() {
             None => {}
             Some(s) => {
                 // println!("last stmt: {}", s.into_token_stream().to_string());
                 match s {
                     Stmt::Expr(_) => {
-                        let mut helper = MakeLastReturnBlkVisitor {};
+                        let mut helper = MakeLastReturnBlkVisitor {
+                            result: &self.names.result,
+                        };
                         helper.visit_stmt_mut(s);
-                        let re = quote!(result);
-                        let ret_stmt_expr: Expr = syn::parse_quote! {#ident::#ok(#re)};
-                        block.stmts.push(Stmt::Expr(ret_stmt_expr))
+                        let ok = self.target.variant("Ok", Some(&self.names.result));
+                        match parse_generated(&ok) {
+                            Ok(ret_stmt_expr) => block.stmts.push(Stmt::Expr(ret_stmt_expr)),
+                            Err(e) => self.error = Some(e),
+                        }
                     }
                     _ => {
-                        let ret_stmt_expr: Expr = syn::parse_quote! {#ident::#ok(())};
-                        block.stmts.push(Stmt::Expr(ret_stmt_expr))
+                        let ok = self.target.variant("Ok", Some("()"));
+                        match parse_generated(&ok) {
+                            Ok(ret_stmt_expr) => block.stmts.push(Stmt::Expr(ret_stmt_expr)),
+                            Err(e) => self.error = Some(e),
+                        }
                     }
                 }
             }
@@ -563,7 +1159,8 @@ impl MakeReturn<'_> {
 }
 
 struct MakeCallerReturnHelper<'a> {
-    callee_fn_name: &'a str,
+    target: &'a Target,
+    error: Option<Error>,
 }
 impl VisitMut for MakeCallerReturnHelper<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
@@ -572,64 +1169,77 @@ impl VisitMut for MakeCallerReturnHelper<'_> {
     }
 
     fn visit_expr_return_mut(&mut self, i: &mut ExprReturn) {
-        let ident_str = format!("{}{}", ENUM_NAME, make_pascal_case(self.callee_fn_name));
-        let ident = Ident::new(ident_str.as_str(), Span::call_site());
-        let return_t = quote! {Return};
-        match i.expr.clone() {
-            None => {
-                let rety: Expr = syn::parse_quote! {#ident::#return_t(())};
-                i.expr = Some(Box::new(rety))
-            }
-            Some(e) => {
-                let e = e.as_ref().clone();
-                let rety: Expr = syn::parse_quote! {#ident::#return_t(#e)};
-                i.expr = Some(Box::new(rety));
-            }
+        let e = match i.expr.clone() {
+            None => "()".to_string(),
+            Some(e) => e.into_token_stream().to_string(),
+        };
+        let rety = self.target.variant("Return", Some(&e));
+        match parse_generated(&rety) {
+            Ok(rety) => i.expr = Some(Box::new(rety)),
+            Err(e) => self.error = Some(e),
         }
     }
+
+    fn visit_expr_closure_mut(&mut self, _: &mut ExprClosure) {}
+
+    fn visit_expr_async_mut(&mut self, _: &mut ExprAsync) {}
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
 }
 
 struct MakeCallerReturn<'a> {
-    callee_fn_name: &'a str,
+    callee: &'a FnId,
+    target: &'a Target,
+    error: Option<Error>,
 }
 
 impl VisitMut for MakeCallerReturn<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             true => {
                 debug!("found callee: {:?}", i);
                 let mut helper = MakeCallerReturnHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    target: self.target,
+                    error: None,
                 };
-                helper.visit_impl_item_method_mut(i)
+                helper.visit_impl_item_method_mut(i);
+                if let Some(e) = helper.error {
+                    self.error = Some(e);
+                }
             }
             false => {}
         }
         syn::visit_mut::visit_impl_item_method_mut(self, i);
     }
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             false => (),
             true => {
                 debug!("found callee: {:?}", i);
                 let mut helper = MakeCallerReturnHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    target: self.target,
+                    error: None,
                 };
-                helper.visit_item_fn_mut(i)
+                helper.visit_item_fn_mut(i);
+                if let Some(e) = helper.error {
+                    self.error = Some(e);
+                }
             }
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
-        match id.contains(self.callee_fn_name) {
+        match self.callee.is(&i.sig.ident) {
             true => {
                 debug!("found callee: {:?}", i);
                 let mut helper = MakeCallerReturnHelper {
-                    callee_fn_name: self.callee_fn_name,
+                    target: self.target,
+                    error: None,
                 };
                 helper.visit_trait_item_method_mut(i);
+                if let Some(e) = helper.error {
+                    self.error = Some(e);
+                }
             }
             false => {}
         }
@@ -637,64 +1247,72 @@ impl VisitMut for MakeCallerReturn<'_> {
     }
 }
 
+fn is_call_to(e: &Expr, callee: &CallSites) -> bool {
+    match e {
+        // `bar(..)`, `Self::bar(..)` or `Type::bar(..)`
+        Expr::Call(c) => callee.is_call(&c.func),
+        Expr::MethodCall(m) => callee.is_method_call(m),
+        Expr::Await(a) => is_call_to(&a.base, callee),
+        _ => false,
+    }
+}
+
 struct MatchCallSiteHelper<'a> {
-    callee_fn_name: &'a str,
+    callee: &'a CallSites,
+    names: &'a Names,
+    target: &'a Target,
     has_return: bool,
-    has_continue: bool,
-    has_break: bool,
+    exits: &'a [LoopExit],
+    error: Option<Error>,
 }
 
 impl VisitMut for MatchCallSiteHelper<'_> {
     fn visit_expr_mut(&mut self, i: &mut Expr) {
         // println!("visit expr: {}", i.into_token_stream().to_string());
         match i {
-            Expr::Call(c) => {
-                let id = c.func.clone().as_ref().into_token_stream().to_string();
-                match id.contains(self.callee_fn_name) {
+            // an async callee is matched on once awaited
+            Expr::Call(_) | Expr::MethodCall(_) | Expr::Await(_) => {
+                match is_call_to(i, self.callee) {
                     true => {
                         let e = i.clone().into_token_stream().to_string();
-                        let enum_name_fn = make_pascal_case(self.callee_fn_name);
+                        let (target, x) = (self.target, &self.names.value);
                         let match_str = format!(
                             "match {} {{\n{} {} {} {}\n}}",
                             e,
-                            format!("{}{}::Ok(x) => x,\n", ENUM_NAME, enum_name_fn),
+                            format!("{} => {x},\n", target.variant("Ok", Some(x))),
                             if self.has_return {
-                                format!("{}{}::Return(x) => return x,\n", ENUM_NAME, enum_name_fn)
-                            } else {
-                                "".to_string()
-                            },
-                            if self.has_break {
-                                format!("{}{}::Break => break,\n", ENUM_NAME, enum_name_fn)
-                            } else {
-                                "".to_string()
-                            },
-                            if self.has_continue {
-                                format!("{}{}::Continue => continue,", ENUM_NAME, enum_name_fn)
+                                format!("{} => return {x},\n", target.variant("Return", Some(x)))
                             } else {
                                 "".to_string()
                             },
+                            self.exits
+                                .iter()
+                                .map(|exit| exit.arm(target, x))
+                                .collect::<String>(),
+                            target.unused_arms(x),
                         );
-                        let match_expr: ExprMatch = syn::parse_str(match_str.as_str()).unwrap();
-                        *i = Expr::Match(match_expr)
+                        match parse_generated::<ExprMatch>(&match_str) {
+                            Ok(match_expr) => *i = Expr::Match(match_expr),
+                            Err(e) => self.error = Some(e),
+                        }
                     }
                     false => syn::visit_mut::visit_expr_mut(self, i),
                 }
             }
-            // NEED TO FIX TO INCLUDE CHECK FOR OTHER CALL SITE SUCH AS self. and Self::
             _ => syn::visit_mut::visit_expr_mut(self, i),
         }
     }
 }
 
 struct MatchCallSite<'a> {
-    caller_fn_name: &'a str,
+    caller: &'a FnId,
     callee_finder: &'a mut FindCallee<'a>,
-    callee_fn_name: &'a str,
+    callee: &'a CallSites,
+    names: &'a Names,
+    target: &'a Target,
     has_return: bool,
-    has_continue: bool,
-    has_break: bool,
-    enum_str: String,
-    added_enum: bool,
+    exits: &'a [LoopExit],
+    error: Option<Error>,
 }
 
 impl VisitMut for MatchCallSite<'_> {
@@ -703,8 +1321,7 @@ impl VisitMut for MatchCallSite<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 self.callee_finder.visit_impl_item_method_mut(i);
@@ -722,8 +1339,7 @@ impl VisitMut for MatchCallSite<'_> {
             return;
         }
 
-        let id = i.sig.ident.to_string();
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             true => {
                 self.callee_finder.visit_item_fn_mut(i);
                 if !self.callee_finder.found {
@@ -736,32 +1352,13 @@ impl VisitMut for MatchCallSite<'_> {
         syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
-
-    fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
-        if i.clone()
-            .into_token_stream()
-            .to_string()
-            .contains(self.callee_fn_name)
-        {
-            match i.content.as_mut() {
-                None => {}
-                Some((_, items)) => {
-                    items.push(syn::parse_str(self.enum_str.as_str()).unwrap());
-                    self.added_enum = true;
-                }
-            }
-        }
-        syn::visit_mut::visit_item_mod_mut(self, i);
-    }
-
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
         if self.callee_finder.found {
             return;
         }
 
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id.contains(self.caller_fn_name) {
+        match self.caller.is(&i.sig.ident) {
             false => (),
             true => {
                 self.callee_finder.visit_trait_item_method_mut(i);
@@ -769,7 +1366,6 @@ impl VisitMut for MatchCallSite<'_> {
                     return;
                 }
                 let _ = i
-                    .clone()
                     .default
                     .as_mut()
                     .and_then(|block| Some(self.match_callsite(block)));
@@ -782,180 +1378,375 @@ impl VisitMut for MatchCallSite<'_> {
 impl MatchCallSite<'_> {
     fn match_callsite(&mut self, block: &mut Block) {
         let mut helper = MatchCallSiteHelper {
-            callee_fn_name: self.callee_fn_name,
+            callee: self.callee,
+            names: self.names,
+            target: self.target,
             has_return: self.has_return,
-            has_continue: self.has_continue,
-            has_break: self.has_break,
+            exits: self.exits,
+            error: None,
         };
         helper.visit_block_mut(block);
+        if let Some(e) = helper.error {
+            self.error = Some(e);
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct NonLocalControlFlowResult {
-    pub success: bool,
     pub has_return: bool,
     pub has_continue: bool,
     pub has_break: bool,
     pub num_inputs: usize,
+    /// Whether the callee returns the shared enum, which `add_shared_enum`
+    /// then has to define at the crate root.
+    pub shared_enum: bool,
 }
 
-pub fn inner_make_controls(
-    file_name: &str,
-    new_file_name: &str,
-    callee_fn_name: &str,
+/// Find what the call to the callee is in and how the callee leaves it,
+/// desugaring the `?`s of the callee on the way. What the callee does that
+/// the controller cannot handle is left in the `error` of the visitor.
+fn check_callee<'a>(
+    file: &mut syn::File,
     caller_fn_name: &str,
-) -> NonLocalControlFlowResult {
-    let mut success = true;
-    debug!("debugging controller...");
-    let file_content: String = fs::read_to_string(&file_name).unwrap().parse().unwrap();
-
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| {
-            let s = format!("THERE IS AN ERROR HERE NOT PARSED: {:?}", e);
-            println!("errored: {}", &s);
-            s
-        })
-        .unwrap();
-
-    let mut caller_rety = ReturnType::Default;
+    caller: &FnId,
+    callee: &'a FnId,
+    call_sites: &CallSites,
+    aliases: &'a ProgramTypeContext,
+    names: &'a Names,
+) -> Result<(CallContext, CalleeCheckNCF<'a>), Error> {
     let mut caller_visitor = CallerVisitor {
         found: false,
-        caller_fn_name,
+        caller,
         callee_finder: &mut FindCallee {
             found: false,
-            callee_fn_name,
+            callee: call_sites,
         },
-        callee_fn_name,
-        callee_in_loop: false,
-        caller_rety: &mut caller_rety,
+        context: None,
+    };
+    caller_visitor.visit_file_mut(file);
+    let context = match caller_visitor.context {
+        Some(context) if caller_visitor.found => context,
+        _ => {
+            debug!("did not find caller");
+            return Err(Error::CallerNotFound(caller_fn_name.to_string()));
+        }
     };
-    caller_visitor.visit_file_mut(&mut file);
-    if !caller_visitor.found {
-        debug!("did not find caller");
-        return NonLocalControlFlowResult {
-            success: false,
-            has_return: false,
-            has_continue: false,
-            has_break: false,
-            num_inputs: 0,
-        };
-    }
 
     let mut callee_visitor = CalleeCheckNCF {
         found: false,
-        callee_fn_name,
-        caller_rety: caller_visitor.caller_rety.clone(),
-        within_caller_loop: caller_visitor.callee_in_loop,
-        has_break: false,
-        has_continue: false,
+        callee,
+        caller_rety: context.rety.clone(),
+        aliases,
+        names,
+        error: None,
+        within_caller_loop: !context.loops.is_empty(),
+        exits: vec![],
         has_return: false,
         num_inputs: 0,
     };
-    callee_visitor.visit_file_mut(&mut file);
+    callee_visitor.visit_file_mut(file);
 
     if !callee_visitor.found {
         debug!("did not find callee");
-        return NonLocalControlFlowResult {
-            success: false,
-            has_return: false,
-            has_continue: false,
-            has_break: false,
-            num_inputs: 0,
-        };
+        return Err(Error::CalleeNotFound(callee.name.clone()));
+    }
+    Ok((context, callee_visitor))
+}
+
+/// Make the non-local control flow of the callee explicit, in place on a
+/// parsed file, returning it through the enum `control` asks for.
+pub fn make_controls_file(
+    file: &mut syn::File,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+    control: ControlEnum,
+) -> Result<NonLocalControlFlowResult, Error> {
+    debug!("debugging controller...");
+    let caller = resolve_fn(file, caller_fn_name)?
+        .ok_or_else(|| Error::CallerNotFound(caller_fn_name.to_string()))?;
+    let callee = resolve_fn(file, callee_fn_name)?
+        .ok_or_else(|| Error::CalleeNotFound(callee_fn_name.to_string()))?;
+    let call_sites = CallSites::resolve(file, &caller, &callee);
+    let callee_fn_name = callee.name.as_str();
+    let aliases = alias_context(file);
+    let idents = collect_idents(file);
+    let names = Names::new(idents, collect_types(file), callee_fn_name);
+    let (context, mut callee_visitor) = check_callee(
+        file,
+        caller_fn_name,
+        &caller,
+        &callee,
+        &call_sites,
+        &aliases,
+        &names,
+    )?;
+    if let Some(e) = callee_visitor.error.take() {
+        return Err(e);
     }
 
     debug!("callee_visitor: {:?}", callee_visitor);
-    if callee_visitor.has_return || callee_visitor.has_continue || callee_visitor.has_break {
-        let caller_rety = match caller_visitor.caller_rety {
-            ReturnType::Default => Type::Verbatim(quote! {()}),
-            ReturnType::Type(_, t) => t.as_ref().clone(),
+    let has_break = callee_visitor.exits.iter().any(|exit| exit.is_break);
+    let has_continue = callee_visitor.exits.iter().any(|exit| !exit.is_break);
+    let mut shared_enum = false;
+    if callee_visitor.has_return || !callee_visitor.exits.is_empty() {
+        let caller_rety = match context.rety {
+            Some(ReturnType::Default) => Type::Verbatim(quote! {()}),
+            Some(ReturnType::Type(_, t)) => t.as_ref().clone(),
+            None if callee_visitor.has_return => {
+                return Err(Error::unsupported(
+                    "`return` or `?` in a closure or async block without a return type",
+                    context.span,
+                ))
+            }
+            None => Type::Verbatim(quote! {()}),
         };
+        // the values of `break`s go to the caller's loops they break out of
+        let mut payloads = vec![];
+        if callee_visitor.has_return {
+            payloads.push(caller_rety);
+        }
+        for exit in callee_visitor.exits.iter() {
+            if let Some(span) = exit.value {
+                let target = context.loops.iter().rev().find(|l| match &exit.label {
+                    None => true,
+                    label => &l.label == label,
+                });
+                match target.and_then(|l| l.ty.clone()) {
+                    Some(ty) => payloads.push(ty),
+                    None => {
+                        return Err(Error::unsupported(
+                            "`break` with a value out of a `loop` whose type is not annotated",
+                            span,
+                        ))
+                    }
+                }
+            }
+        }
+        let plain = callee_visitor
+            .exits
+            .iter()
+            .all(|exit| exit.label.is_none() && exit.value.is_none());
+        let ways_out = callee_visitor.exits.len() + callee_visitor.has_return as usize;
+        let target = match control {
+            ControlEnum::ControlFlow if plain && ways_out == 1 => Target::ControlFlow,
+            ControlEnum::ControlFlow | ControlEnum::Shared if plain => Target::Shared {
+                has_return: callee_visitor.has_return,
+                has_break,
+                has_continue,
+            },
+            _ => Target::Dedicated(names.ret.clone()),
+        };
+        debug!("returning through {:?}", target);
+        shared_enum = matches!(target, Target::Shared { .. });
         let mut already_did_return = false;
 
         if callee_visitor.has_return {
             debug!("has return!");
             let mut make_ret = MakeReturn {
-                callee_fn_name,
-                caller_rety: &caller_rety,
+                callee: &callee,
+                names: &names,
+                target: &target,
+                payloads: &payloads,
+                error: None,
             };
-            make_ret.visit_file_mut(&mut file);
+            make_ret.visit_file_mut(file);
+            if let Some(e) = make_ret.error {
+                return Err(e);
+            }
 
-            let mut make_caller_ret = MakeCallerReturn { callee_fn_name };
-            make_caller_ret.visit_file_mut(&mut file);
+            let mut make_caller_ret = MakeCallerReturn {
+                callee: &callee,
+                target: &target,
+                error: None,
+            };
+            make_caller_ret.visit_file_mut(file);
+            if let Some(e) = make_caller_ret.error {
+                return Err(e);
+            }
             already_did_return = true;
         }
 
-        if callee_visitor.has_break || callee_visitor.has_continue {
-            debug!("has break {} or cont {}",callee_visitor.has_break, callee_visitor.has_continue);
+        if !callee_visitor.exits.is_empty() {
+            debug!("has loop exits {:?}", callee_visitor.exits);
             let mut make_brk_and_cont = MakeBrkAndCont {
-                callee_fn_name,
-                success,
+                callee: &callee,
+                names: &names,
+                target: &target,
+                payloads: &payloads,
                 already_did_return,
+                error: None,
             };
-            make_brk_and_cont.visit_file_mut(&mut file);
-            success = make_brk_and_cont.success
-        }
-
-        let ident_str = format!("{}{}", ENUM_NAME, make_pascal_case(callee_fn_name));
-        let enum_str = format!(
-            "enum {}<A{}> \n{{Ok(A),\n{}{}{}}}",
-            ident_str,
-            if callee_visitor.has_return { ", B" } else { "" },
-            if callee_visitor.has_return {
-                "Return(B),\n"
-            } else {
-                ""
-            },
-            if callee_visitor.has_break {
-                "Break,\n"
-            } else {
-                ""
-            },
-            if callee_visitor.has_continue {
-                "Continue,\n"
-            } else {
-                ""
-            },
-        );
+            make_brk_and_cont.visit_file_mut(file);
+            if let Some(e) = make_brk_and_cont.error {
+                return Err(e);
+            }
+        }
+
+        let ident_str = names.ret.as_str();
+        // one type parameter per variant carrying something
+        let mut params = vec!["A".to_string()];
+        let mut variants = vec!["Ok(A)".to_string()];
+        if callee_visitor.has_return {
+            params.push("B".to_string());
+            variants.push("Return(B)".to_string());
+        }
+        for exit in callee_visitor.exits.iter() {
+            match exit.value {
+                Some(_) => {
+                    let param = ((b'A' + params.len() as u8) as char).to_string();
+                    variants.push(format!("{}({})", exit.variant(), param));
+                    params.push(param);
+                }
+                None => variants.push(exit.variant()),
+            }
+        }
+        let enum_str = match target {
+            Target::Dedicated(_) => Some(format!(
+                "enum {}<{}> \n{{{},\n}}",
+                ident_str,
+                params.join(", "),
+                variants.join(",\n"),
+            )),
+            _ => None,
+        };
 
         let mut caller_matcher = MatchCallSite {
-            caller_fn_name,
+            caller: &caller,
             callee_finder: &mut FindCallee {
                 found: false,
-                callee_fn_name,
+                callee: &call_sites,
             },
-            callee_fn_name,
+            callee: &call_sites,
+            names: &names,
+            target: &target,
             has_return: callee_visitor.has_return,
-            has_continue: callee_visitor.has_continue,
-            has_break: callee_visitor.has_break,
-            enum_str: enum_str.clone(),
-            added_enum: false,
+            exits: &callee_visitor.exits,
+            error: None,
         };
-        caller_matcher.visit_file_mut(&mut file);
+        caller_matcher.visit_file_mut(file);
+        if let Some(e) = caller_matcher.error {
+            return Err(e);
+        }
 
-        if !caller_matcher.added_enum {
-            file.items.push(syn::parse_str(enum_str.as_str()).unwrap());
+        if let Some(enum_str) = enum_str {
+            let enum_item: ItemEnum = parse_generated(&enum_str)?;
+            // the callee may be nested in a function
+            if !place_enum(&mut file.items, &callee, &enum_item) {
+                file.items.push(Item::Enum(enum_item));
+            }
         }
     }
-    let file = file.into_token_stream().to_string();
-    fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
-    NonLocalControlFlowResult {
-        success,
+    Ok(NonLocalControlFlowResult {
+        has_return: callee_visitor.has_return,
+        has_continue,
+        has_break,
+        num_inputs: callee_visitor.num_inputs,
+        shared_enum,
+    })
+}
+
+/// How the callee leaves the caller, as `make_controls_file` reports it,
+/// without making it explicit. This still answers when the controller gives
+/// up on the callee.
+pub fn check_controls_source(
+    source: &str,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+) -> Result<NonLocalControlFlowResult, Error> {
+    let mut file = syn::parse_str::<syn::File>(source)?;
+    let caller = resolve_fn(&file, caller_fn_name)?
+        .ok_or_else(|| Error::CallerNotFound(caller_fn_name.to_string()))?;
+    let callee = resolve_fn(&file, callee_fn_name)?
+        .ok_or_else(|| Error::CalleeNotFound(callee_fn_name.to_string()))?;
+    let call_sites = CallSites::resolve(&file, &caller, &callee);
+    let aliases = alias_context(&file);
+    let idents = collect_idents(&file);
+    let names = Names::new(idents, collect_types(&file), callee.name.as_str());
+    let (_, callee_visitor) = check_callee(
+        &mut file,
+        caller_fn_name,
+        &caller,
+        &callee,
+        &call_sites,
+        &aliases,
+        &names,
+    )?;
+    Ok(NonLocalControlFlowResult {
         has_return: callee_visitor.has_return,
-        has_continue: callee_visitor.has_continue,
-        has_break: callee_visitor.has_break,
+        has_continue: callee_visitor.exits.iter().any(|exit| !exit.is_break),
+        has_break: callee_visitor.exits.iter().any(|exit| exit.is_break),
         num_inputs: callee_visitor.num_inputs,
+        shared_enum: false,
+    })
+}
+
+/// Define the shared enum at the end of `root`, the source of the crate root,
+/// unless it already defines or imports an item of that name.
+pub fn add_shared_enum(root: &str) -> Result<String, Error> {
+    let file = syn::parse_str::<syn::File>(root)?;
+    if declares(&file.items, SHARED_ENUM_NAME) {
+        return Ok(root.to_string());
     }
+    let enum_item: ItemEnum = syn::parse_str(SHARED_ENUM)?;
+    Ok(format!(
+        "{}\n\n{}",
+        root.trim_end(),
+        format_source(&enum_item.into_token_stream().to_string())
+    ))
+}
+
+/// Same as `make_controls_file` on a source string.
+pub fn make_controls_source(
+    source: &str,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+    control: ControlEnum,
+) -> Result<(String, NonLocalControlFlowResult), Error> {
+    let mut file = syn::parse_str::<syn::File>(source)?;
+    let res = make_controls_file(&mut file, callee_fn_name, caller_fn_name, control)?;
+    let file = file.into_token_stream().to_string();
+    Ok((splice(source, &format_source(&file)), res))
+}
+
+/// Same as `inner_make_controls` on any file system, e.g. a
+/// `SymbolicFileSystem` holding the sources in memory.
+pub fn make_controls_fs<S: FileSystem>(
+    fs: &S,
+    file_name: &str,
+    new_file_name: &str,
+    callee_fn_name: &str,
+    caller_fn_name: &str,
+    control: ControlEnum,
+) -> Result<NonLocalControlFlowResult, Error>
+where
+    Error: From<S::FSError>,
+{
+    let file_content = fs.read(file_name)?;
+    let (new_file_content, res) = make_controls_source(
+        file_content.as_str(),
+        callee_fn_name,
+        caller_fn_name,
+        control,
+    )?;
+    fs.write(new_file_name, new_file_content)?;
+    Ok(res)
 }
 
-pub fn make_controls(
+pub fn inner_make_controls(
     file_name: &str,
     new_file_name: &str,
     callee_fn_name: &str,
     caller_fn_name: &str,
-) -> bool {
-    let res = inner_make_controls(file_name, new_file_name, callee_fn_name, caller_fn_name);
+) -> Result<NonLocalControlFlowResult, Error> {
+    let res = make_controls_fs(
+        &RealFileSystem,
+        file_name,
+        new_file_name,
+        callee_fn_name,
+        caller_fn_name,
+        ControlEnum::Dedicated,
+    );
     debug!("result: {:?}", res);
-    res.success
+    res
 }
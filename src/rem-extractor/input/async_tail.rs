@@ -0,0 +1,11 @@
+// extract new_foo 7:5 8:10
+async fn fetch(n: i32) -> i32 {
+    n + 1
+}
+
+async fn new_foo(n: i32) -> i32 {
+    let m = fetch(n).await;
+    m * 2
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+// extract new_foo 7:5 11:37
+fn new_foo(n: u64) {
+    let name: &str = "rem";
+    let scale = 2.5;
+    let flags = vec![true, false];
+    println!("{}", name);
+    let doubled = n * 2;
+    let ratio = scale as f32;
+    let label = name.to_owned();
+    let parts = (doubled, flags.len());
+    let pairs = vec![(1u8, 'a'); 2];
+    println!("{} {} {:?} {:?}", ratio, label, parts, pairs);
+}
+
+fn main() {
+    new_foo(1);
+}
@@ -0,0 +1,12 @@
+// extract new_foo 4:9 5:20
+fn new_foo(n: i32) -> i32 {
+    let total = {
+        let doubled = n * 2;
+        doubled + 1
+    };
+    total
+}
+
+fn main() {
+    new_foo(1);
+}
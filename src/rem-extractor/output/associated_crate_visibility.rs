@@ -0,0 +1,25 @@
+// extract new_foo 12:9 13:44 --visibility crate
+#[derive(Debug)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    const ORIGIN: Point = Point { x: 0, y: 0 };
+
+    pub fn new_foo(x: i32) -> Point {
+        let p = Self::bar(x);
+        p
+    }
+
+    pub(crate) fn bar(x: i32) -> Point {
+        let shifted: i32 = Self::ORIGIN.x + x;
+        let p = Point { x: shifted, y: 1 };
+        p
+    }
+}
+
+fn main() {
+    println!("{:?}", Point::new_foo(1));
+}
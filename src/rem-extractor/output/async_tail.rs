@@ -0,0 +1,15 @@
+// extract new_foo 7:5 8:10
+async fn fetch(n: i32) -> i32 {
+    n + 1
+}
+
+async fn new_foo(n: i32) -> i32 {
+    bar(n).await
+}
+
+async fn bar(n: i32) -> i32 {
+    let m = fetch(n).await;
+    m * 2
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+// extract new_foo 6:9 9:10 --placement free --visibility caller
+mod shapes {
+    pub struct Square(pub f64);
+
+    pub(crate) fn new_foo(squares: &Vec<Square>) -> f64 {
+        let mut area = bar(squares);
+        area
+    }
+
+    pub(crate) fn bar(squares: &Vec<Square>) -> f64 {
+        let mut area = 0.0;
+        for s in squares.iter() {
+            area += s.0 * s.0;
+        }
+        area
+    }
+}
+
+fn main() {
+    println!("{}", shapes::new_foo(&vec![shapes::Square(2.0)]));
+}
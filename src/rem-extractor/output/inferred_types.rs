@@ -0,0 +1,27 @@
+// extract new_foo 7:5 11:37
+fn new_foo(n: u64) {
+    let name: &str = "rem";
+    let scale = 2.5;
+    let flags = vec![true, false];
+    println!("{}", name);
+    let (ratio, label, parts, pairs) = bar(n, name, scale, flags);
+    println!("{} {} {:?} {:?}", ratio, label, parts, pairs);
+}
+
+fn bar(
+    n: u64,
+    name: &str,
+    scale: f64,
+    flags: Vec<bool>,
+) -> (f32, String, (u64, usize), Vec<(u8, char)>) {
+    let doubled = n * 2;
+    let ratio = scale as f32;
+    let label = name.to_owned();
+    let parts = (doubled, flags.len());
+    let pairs = vec![(1u8, 'a'); 2];
+    (ratio, label, parts, pairs)
+}
+
+fn main() {
+    new_foo(1);
+}
@@ -0,0 +1,24 @@
+// extract new_foo 10:9 11:46 --visibility caller
+struct Counter {
+    count: u32,
+    step: u32,
+}
+
+impl Counter {
+    pub fn new_foo(&mut self, times: u32) -> u32 {
+        let before: u32 = self.count;
+        let after = self.bar(times, before);
+        after
+    }
+
+    pub fn bar(&mut self, times: u32, before: u32) -> u32 {
+        self.count += self.step * times;
+        let after: u32 = self.count - before;
+        after
+    }
+}
+
+fn main() {
+    let mut c = Counter { count: 0, step: 2 };
+    c.new_foo(3);
+}
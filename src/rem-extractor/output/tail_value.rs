@@ -0,0 +1,14 @@
+// extract new_foo 4:9 5:20
+fn new_foo(n: i32) -> i32 {
+    let total = { bar(n) };
+    total
+}
+
+fn bar(n: i32) -> i32 {
+    let doubled = n * 2;
+    doubled + 1
+}
+
+fn main() {
+    new_foo(1);
+}
@@ -0,0 +1,1742 @@
+use log::debug;
+use once_cell::sync::Lazy;
+use proc_macro2::{LineColumn, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
+use syn::{
+    BinOp, Block, Expr, FnArg, GenericParam, Generics, ImplItem, ImplItemMethod, Item, ItemFn,
+    ItemImpl, ItemTrait, Lit, Local, Macro, Pat, ReturnType, Signature, Stmt, Token, TraitItem,
+    TraitItemMethod, Type, UnOp, Visibility, WherePredicate,
+};
+
+use rem_utils::error::Error;
+use rem_utils::resolve::{resolve_fn, FnId, FnSpec};
+use rem_utils::{edit::splice, format_source};
+
+/// A position in a source file, either a byte offset or a 1-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Offset(usize),
+    LineColumn(usize, usize),
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((line, column)) => {
+                let line = line.parse::<usize>().map_err(|e| format!("{}: {}", s, e))?;
+                let column = column
+                    .parse::<usize>()
+                    .map_err(|e| format!("{}: {}", s, e))?;
+                if line == 0 || column == 0 {
+                    return Err(format!("{}: lines and columns start at 1", s));
+                }
+                Ok(Position::LineColumn(line, column))
+            }
+            None => s
+                .parse::<usize>()
+                .map(Position::Offset)
+                .map_err(|e| format!("{}: {}", s, e)),
+        }
+    }
+}
+
+impl Position {
+    /// Convert to the convention of `proc_macro2` spans: 1-based lines and
+    /// 0-based columns counted in chars.
+    pub fn to_line_column(&self, src: &str) -> LineColumn {
+        match self {
+            Position::LineColumn(line, column) => LineColumn {
+                line: *line,
+                column: column - 1,
+            },
+            Position::Offset(offset) => {
+                let mut line = 1;
+                let mut column = 0;
+                for (i, c) in src.char_indices() {
+                    if i >= *offset {
+                        break;
+                    }
+                    if c == '\n' {
+                        line += 1;
+                        column = 0;
+                    } else {
+                        column += 1;
+                    }
+                }
+                LineColumn { line, column }
+            }
+        }
+    }
+}
+
+type Pos = (usize, usize);
+
+fn pos(lc: LineColumn) -> Pos {
+    (lc.line, lc.column)
+}
+
+fn start_of(span: Span) -> Pos {
+    pos(span.start())
+}
+
+fn end_of(span: Span) -> Pos {
+    pos(span.end())
+}
+
+/// Names bound by a pattern, together with their type if the pattern carries one.
+fn pat_bindings(pat: &Pat, ty: Option<&Type>, out: &mut Vec<(syn::Ident, Option<Type>, bool)>) {
+    match pat {
+        Pat::Ident(id) => {
+            out.push((id.ident.clone(), ty.cloned(), id.mutability.is_some()));
+            if let Some((_, sub)) = &id.subpat {
+                pat_bindings(sub.as_ref(), None, out)
+            }
+        }
+        Pat::Type(t) => pat_bindings(t.pat.as_ref(), Some(t.ty.as_ref()), out),
+        Pat::Tuple(t) => match ty {
+            Some(Type::Tuple(tys)) if tys.elems.len() == t.elems.len() => t
+                .elems
+                .iter()
+                .zip(tys.elems.iter())
+                .for_each(|(p, ty)| pat_bindings(p, Some(ty), out)),
+            _ => t.elems.iter().for_each(|p| pat_bindings(p, None, out)),
+        },
+        Pat::TupleStruct(t) => t.pat.elems.iter().for_each(|p| pat_bindings(p, None, out)),
+        Pat::Struct(s) => s
+            .fields
+            .iter()
+            .for_each(|f| pat_bindings(f.pat.as_ref(), None, out)),
+        Pat::Reference(r) => pat_bindings(r.pat.as_ref(), None, out),
+        Pat::Slice(s) => s.elems.iter().for_each(|p| pat_bindings(p, None, out)),
+        Pat::Or(o) => {
+            if let Some(p) = o.cases.first() {
+                pat_bindings(p, None, out)
+            }
+        }
+        Pat::Box(b) => pat_bindings(b.pat.as_ref(), None, out),
+        _ => (),
+    }
+}
+
+fn pat_names(pat: &Pat) -> Vec<String> {
+    let mut bindings = vec![];
+    pat_bindings(pat, None, &mut bindings);
+    bindings
+        .into_iter()
+        .map(|(id, _, _)| id.to_string())
+        .collect()
+}
+
+/// What is known about the types of the variables in scope.
+struct TypeEnv {
+    vars: HashMap<String, Option<Type>>,
+    /// Non-generic structs and enums declared in the file.
+    local_types: HashSet<String>,
+}
+
+fn strip_reference(ty: Type) -> Type {
+    match ty {
+        Type::Reference(r) => *r.elem,
+        ty => ty,
+    }
+}
+
+fn infer_type(e: &Expr, env: &TypeEnv) -> Option<Type> {
+    match e {
+        Expr::Lit(l) => match &l.lit {
+            Lit::Int(i) => match i.suffix() {
+                "" => Some(syn::parse_quote! {i32}),
+                suffix => syn::parse_str(suffix).ok(),
+            },
+            Lit::Float(f) => match f.suffix() {
+                "" => Some(syn::parse_quote! {f64}),
+                suffix => syn::parse_str(suffix).ok(),
+            },
+            Lit::Bool(_) => Some(syn::parse_quote! {bool}),
+            Lit::Char(_) => Some(syn::parse_quote! {char}),
+            Lit::Str(_) => Some(syn::parse_quote! {&str}),
+            _ => None,
+        },
+        Expr::Path(p) if p.qself.is_none() && p.path.segments.len() == 1 => env
+            .vars
+            .get(&p.path.segments[0].ident.to_string())
+            .cloned()
+            .flatten(),
+        Expr::Reference(r) => {
+            let elem = infer_type(r.expr.as_ref(), env)?;
+            match r.mutability {
+                Some(_) => Some(syn::parse_quote! {&mut #elem}),
+                None => Some(syn::parse_quote! {&#elem}),
+            }
+        }
+        Expr::Paren(p) => infer_type(p.expr.as_ref(), env),
+        Expr::Group(g) => infer_type(g.expr.as_ref(), env),
+        Expr::Cast(c) => Some(c.ty.as_ref().clone()),
+        Expr::Binary(b) => match b.op {
+            BinOp::Eq(_)
+            | BinOp::Ne(_)
+            | BinOp::Lt(_)
+            | BinOp::Le(_)
+            | BinOp::Gt(_)
+            | BinOp::Ge(_)
+            | BinOp::And(_)
+            | BinOp::Or(_) => Some(syn::parse_quote! {bool}),
+            BinOp::Add(_)
+            | BinOp::Sub(_)
+            | BinOp::Mul(_)
+            | BinOp::Div(_)
+            | BinOp::Rem(_)
+            | BinOp::BitXor(_)
+            | BinOp::BitAnd(_)
+            | BinOp::BitOr(_)
+            | BinOp::Shl(_)
+            | BinOp::Shr(_) => infer_type(b.left.as_ref(), env).map(strip_reference),
+            _ => None,
+        },
+        Expr::Unary(u) => match u.op {
+            UnOp::Deref(_) => infer_type(u.expr.as_ref(), env).map(strip_reference),
+            _ => infer_type(u.expr.as_ref(), env),
+        },
+        Expr::Tuple(t) => {
+            let elems = t
+                .elems
+                .iter()
+                .map(|e| infer_type(e, env))
+                .collect::<Option<Vec<Type>>>()?;
+            Some(syn::parse_quote! {(#(#elems),*)})
+        }
+        Expr::Array(a) => {
+            let elem = infer_type(a.elems.first()?, env)?;
+            let len = a.elems.len();
+            Some(syn::parse_quote! {[#elem; #len]})
+        }
+        Expr::Struct(s) => match s.path.get_ident() {
+            Some(id) if env.local_types.contains(&id.to_string()) => Some(syn::parse_quote! {#id}),
+            _ => None,
+        },
+        Expr::Call(c) => match c.func.as_ref() {
+            Expr::Path(p) if p.qself.is_none() => {
+                let segments = p
+                    .path
+                    .segments
+                    .iter()
+                    .map(|s| s.ident.to_string())
+                    .collect::<Vec<String>>();
+                match segments.iter().map(|s| s.as_str()).collect::<Vec<&str>>()[..] {
+                    [ty] if env.local_types.contains(ty) => syn::parse_str(ty).ok(),
+                    ["String", "from"] | ["String", "new"] => Some(syn::parse_quote! {String}),
+                    ["Some"] => {
+                        let elem = infer_type(c.args.first()?, env)?;
+                        Some(syn::parse_quote! {Option<#elem>})
+                    }
+                    ["Box", "new"] => {
+                        let elem = infer_type(c.args.first()?, env)?;
+                        Some(syn::parse_quote! {Box<#elem>})
+                    }
+                    [ty, "new"] | [ty, "default"] if env.local_types.contains(ty) => {
+                        syn::parse_str(ty).ok()
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        Expr::MethodCall(m) => match m.method.to_string().as_str() {
+            "to_string" => Some(syn::parse_quote! {String}),
+            "to_owned" => match infer_type(m.receiver.as_ref(), env).map(strip_reference) {
+                Some(Type::Path(p)) if p.path.is_ident("str") => Some(syn::parse_quote! {String}),
+                ty => ty,
+            },
+            "clone" => infer_type(m.receiver.as_ref(), env).map(strip_reference),
+            "len" | "count" => Some(syn::parse_quote! {usize}),
+            "is_empty" | "contains" | "starts_with" | "ends_with" => Some(syn::parse_quote! {bool}),
+            _ => None,
+        },
+        Expr::Macro(m) => infer_macro_type(&m.mac, env),
+        _ => None,
+    }
+}
+
+fn infer_macro_type(mac: &Macro, env: &TypeEnv) -> Option<Type> {
+    let name = mac.path.segments.last()?.ident.to_string();
+    match name.as_str() {
+        "format" => Some(syn::parse_quote! {String}),
+        "vec" => {
+            let first = mac
+                .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+                .ok()
+                .and_then(|elems| elems.first().cloned())
+                .or_else(|| {
+                    // vec![elem; n]
+                    mac.parse_body_with(|input: ParseStream| {
+                        let elem: Expr = input.parse()?;
+                        let _: Token![;] = input.parse()?;
+                        let _: Expr = input.parse()?;
+                        Ok(elem)
+                    })
+                    .ok()
+                })?;
+            let elem = infer_type(&first, env)?;
+            Some(syn::parse_quote! {Vec<#elem>})
+        }
+        _ => None,
+    }
+}
+
+struct LocalTypeCollector {
+    local_types: HashSet<String>,
+    /// the traits of the file and their visibility
+    traits: HashMap<String, Visibility>,
+    /// how many impls of each trait the file has
+    trait_impls: HashMap<String, usize>,
+}
+
+impl<'ast> Visit<'ast> for LocalTypeCollector {
+    fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
+        if i.generics.params.is_empty() {
+            self.local_types.insert(i.ident.to_string());
+        }
+    }
+
+    fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
+        if i.generics.params.is_empty() {
+            self.local_types.insert(i.ident.to_string());
+        }
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.traits.insert(i.ident.to_string(), i.vis.clone());
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        if let Some((_, path, _)) = &i.trait_ {
+            if let Some(segment) = path.segments.last() {
+                *self
+                    .trait_impls
+                    .entry(segment.ident.to_string())
+                    .or_default() += 1;
+            }
+        }
+        syn::visit::visit_item_impl(self, i);
+    }
+}
+
+/// Collects the variables that are in scope at the start of the selection.
+struct ScopeCollector {
+    start: Pos,
+    end: Pos,
+    env: TypeEnv,
+    /// in order of declaration
+    bindings: Vec<String>,
+}
+
+impl ScopeCollector {
+    fn encloses(&self, span: Span) -> bool {
+        start_of(span) <= self.start && self.end <= end_of(span)
+    }
+
+    fn bind(&mut self, pat: &Pat, ty: Option<&Type>) {
+        let mut bindings = vec![];
+        pat_bindings(pat, ty, &mut bindings);
+        for (id, ty, _) in bindings {
+            let name = id.to_string();
+            self.bindings.retain(|n| n != &name);
+            self.bindings.push(name.clone());
+            self.env.vars.insert(name, ty);
+        }
+    }
+
+    fn bind_local(&mut self, local: &Local) {
+        match &local.pat {
+            Pat::Type(t) => self.bind(t.pat.as_ref(), Some(t.ty.as_ref())),
+            pat => {
+                let ty = local
+                    .init
+                    .as_ref()
+                    .and_then(|(_, init)| infer_type(init.as_ref(), &self.env));
+                self.bind(pat, ty.as_ref())
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ScopeCollector {
+    fn visit_block(&mut self, i: &'ast Block) {
+        if !self.encloses(i.span()) {
+            return;
+        }
+        for stmt in &i.stmts {
+            if start_of(stmt.span()) >= self.start {
+                break;
+            }
+            match stmt {
+                Stmt::Local(local) if end_of(local.span()) <= self.start => self.bind_local(local),
+                _ => (),
+            }
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_item(&mut self, _: &'ast Item) {
+        // nested items cannot see the locals of the caller
+    }
+
+    fn visit_expr_for_loop(&mut self, i: &'ast syn::ExprForLoop) {
+        if self.encloses(i.body.span()) {
+            // only ranges tell us what they iterate over
+            let ty = match i.expr.as_ref() {
+                Expr::Range(r) => r
+                    .from
+                    .as_ref()
+                    .or(r.to.as_ref())
+                    .and_then(|e| infer_type(e.as_ref(), &self.env)),
+                _ => None,
+            };
+            self.bind(&i.pat, ty.as_ref());
+        }
+        syn::visit::visit_expr_for_loop(self, i);
+    }
+
+    fn visit_expr_closure(&mut self, i: &'ast syn::ExprClosure) {
+        if self.encloses(i.body.span()) {
+            i.inputs.iter().for_each(|pat| self.bind(pat, None));
+        }
+        syn::visit::visit_expr_closure(self, i);
+    }
+
+    fn visit_arm(&mut self, i: &'ast syn::Arm) {
+        if self.encloses(i.body.span()) {
+            self.bind(&i.pat, None);
+        }
+        syn::visit::visit_arm(self, i);
+    }
+
+    fn visit_expr_if(&mut self, i: &'ast syn::ExprIf) {
+        if let Expr::Let(l) = i.cond.as_ref() {
+            if self.encloses(i.then_branch.span()) {
+                self.bind(&l.pat, None);
+            }
+        }
+        syn::visit::visit_expr_if(self, i);
+    }
+
+    fn visit_expr_while(&mut self, i: &'ast syn::ExprWhile) {
+        if let Expr::Let(l) = i.cond.as_ref() {
+            if self.encloses(i.body.span()) {
+                self.bind(&l.pat, None);
+            }
+        }
+        syn::visit::visit_expr_while(self, i);
+    }
+}
+
+/// Finds the `.await`s of some statements, but not those of the async blocks
+/// and closures in them.
+struct AwaitFinder {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for AwaitFinder {
+    fn visit_expr_await(&mut self, _: &'ast syn::ExprAwait) {
+        self.found = true
+    }
+
+    fn visit_expr_async(&mut self, _: &'ast syn::ExprAsync) {}
+
+    fn visit_expr_closure(&mut self, _: &'ast syn::ExprClosure) {}
+
+    fn visit_item(&mut self, _: &'ast Item) {}
+}
+
+/// An inline format argument, e.g. `{x}` or `{x:?}`.
+static FORMAT_ARG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{(?P<name>[A-Za-z_][A-Za-z0-9_]*)(:[^}]*)?\}").unwrap());
+
+/// Collects the variables used but not bound by a sequence of statements.
+struct UseCollector<'a> {
+    candidates: &'a HashSet<String>,
+    scopes: Vec<HashSet<String>>,
+    free: HashSet<String>,
+    uses_self: bool,
+    uses_self_type: bool,
+}
+
+impl<'a> UseCollector<'a> {
+    fn new(candidates: &'a HashSet<String>) -> Self {
+        UseCollector {
+            candidates,
+            scopes: vec![HashSet::new()],
+            free: HashSet::new(),
+            uses_self: false,
+            uses_self_type: false,
+        }
+    }
+
+    fn use_name(&mut self, name: String) {
+        if name == "self" {
+            self.uses_self = true;
+        } else if self.candidates.contains(&name)
+            && !self.scopes.iter().any(|scope| scope.contains(&name))
+        {
+            self.free.insert(name);
+        }
+    }
+
+    fn bind(&mut self, pat: &Pat) {
+        let names = pat_names(pat);
+        self.scopes.last_mut().unwrap().extend(names);
+    }
+
+    fn visit_tokens(&mut self, tokens: TokenStream) {
+        let mut after_dot = false;
+        for token in tokens {
+            match token {
+                TokenTree::Ident(id) => {
+                    if !after_dot {
+                        self.use_name(id.to_string())
+                    }
+                    after_dot = false
+                }
+                TokenTree::Group(g) => {
+                    self.visit_tokens(g.stream());
+                    after_dot = false
+                }
+                TokenTree::Punct(p) => after_dot = p.as_char() == '.',
+                TokenTree::Literal(l) => {
+                    // inline format arguments, e.g. println!("{x}")
+                    for captured in FORMAT_ARG.captures_iter(l.to_string().as_str()) {
+                        self.use_name(captured["name"].to_string())
+                    }
+                    after_dot = false
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UseCollector<'_> {
+    fn visit_block(&mut self, i: &'ast Block) {
+        self.scopes.push(HashSet::new());
+        syn::visit::visit_block(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_local(&mut self, i: &'ast Local) {
+        if let Some((_, init)) = &i.init {
+            self.visit_expr(init.as_ref());
+        }
+        self.bind(&i.pat);
+    }
+
+    fn visit_expr_path(&mut self, i: &'ast syn::ExprPath) {
+        if i.qself.is_none() && i.path.segments.len() == 1 {
+            self.use_name(i.path.segments[0].ident.to_string());
+        }
+        syn::visit::visit_expr_path(self, i);
+    }
+
+    fn visit_path(&mut self, i: &'ast syn::Path) {
+        if let Some(first) = i.segments.first() {
+            if first.ident == "Self" {
+                self.uses_self_type = true;
+            }
+        }
+        syn::visit::visit_path(self, i);
+    }
+
+    fn visit_macro(&mut self, i: &'ast Macro) {
+        self.visit_path(&i.path);
+        self.visit_tokens(i.tokens.clone());
+    }
+
+    fn visit_item(&mut self, _: &'ast Item) {}
+
+    fn visit_expr_for_loop(&mut self, i: &'ast syn::ExprForLoop) {
+        self.visit_expr(i.expr.as_ref());
+        self.scopes.push(HashSet::new());
+        self.bind(&i.pat);
+        self.visit_block(&i.body);
+        self.scopes.pop();
+    }
+
+    fn visit_expr_closure(&mut self, i: &'ast syn::ExprClosure) {
+        self.scopes.push(HashSet::new());
+        i.inputs.iter().for_each(|pat| self.bind(pat));
+        self.visit_expr(i.body.as_ref());
+        self.scopes.pop();
+    }
+
+    fn visit_arm(&mut self, i: &'ast syn::Arm) {
+        self.scopes.push(HashSet::new());
+        self.bind(&i.pat);
+        if let Some((_, guard)) = &i.guard {
+            self.visit_expr(guard.as_ref());
+        }
+        self.visit_expr(i.body.as_ref());
+        self.scopes.pop();
+    }
+
+    fn visit_expr_if(&mut self, i: &'ast syn::ExprIf) {
+        match i.cond.as_ref() {
+            Expr::Let(l) => {
+                self.visit_expr(l.expr.as_ref());
+                self.scopes.push(HashSet::new());
+                self.bind(&l.pat);
+                self.visit_block(&i.then_branch);
+                self.scopes.pop();
+            }
+            cond => {
+                self.visit_expr(cond);
+                self.visit_block(&i.then_branch);
+            }
+        }
+        if let Some((_, else_branch)) = &i.else_branch {
+            self.visit_expr(else_branch.as_ref());
+        }
+    }
+
+    fn visit_expr_while(&mut self, i: &'ast syn::ExprWhile) {
+        match i.cond.as_ref() {
+            Expr::Let(l) => {
+                self.visit_expr(l.expr.as_ref());
+                self.scopes.push(HashSet::new());
+                self.bind(&l.pat);
+                self.visit_block(&i.body);
+                self.scopes.pop();
+            }
+            cond => {
+                self.visit_expr(cond);
+                self.visit_block(&i.body);
+            }
+        }
+    }
+}
+
+/// Where the callee goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Placement {
+    /// In the `impl` or trait of the caller if it needs `self` or `Self`, a
+    /// free function next to the caller otherwise
+    #[default]
+    Auto,
+    /// A free function next to the caller
+    Free,
+    /// A method taking the receiver of the caller, next to it
+    Method,
+    /// An associated function without receiver, next to the caller
+    Associated,
+    /// A default method of the trait the caller is in or implements
+    TraitDefault,
+    /// A required method of the trait the caller is in or implements, defined
+    /// in each impl of it in the file, so only when the caller's is the one
+    /// impl or the caller is a default method of the trait
+    TraitRequired,
+}
+
+impl Placement {
+    fn is_trait(&self) -> bool {
+        matches!(self, Placement::TraitDefault | Placement::TraitRequired)
+    }
+}
+
+/// The visibility of the callee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CalleeVisibility {
+    /// Private to the module, or to the trait
+    #[default]
+    Private,
+    /// `pub(crate)`
+    Crate,
+    /// The same as the caller
+    Caller,
+}
+
+/// How the callee is laid out in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CalleeStyle {
+    pub placement: Placement,
+    pub visibility: CalleeVisibility,
+}
+
+/// The statements cut out of the caller.
+struct Cut {
+    inputs: Vec<(syn::Ident, Type)>,
+    outputs: Vec<(syn::Ident, Type, bool)>,
+    /// the selection ends with the value of its block
+    tail: Option<ReturnType>,
+    stmts: Vec<Stmt>,
+    /// the selection awaits, so the callee is an `async fn`
+    is_async: bool,
+    /// how the callee is called: `Free`, `Method` or `Associated`
+    placement: Placement,
+}
+
+/// Finds the block whose statements are selected, cuts them out and puts the
+/// call to the callee in their place.
+struct BlockCutter<'a> {
+    start: Pos,
+    end: Pos,
+    depth: usize,
+    env: &'a TypeEnv,
+    bindings: &'a Vec<String>,
+    caller_output: &'a ReturnType,
+    in_impl: bool,
+    has_receiver: bool,
+    placement: Placement,
+    callee_fn_name: &'a str,
+    cut: Option<Result<Cut, String>>,
+}
+
+impl BlockCutter<'_> {
+    fn cut_stmts(&mut self, block: &mut Block, from: usize, to: usize) -> Result<Cut, String> {
+        let selected = &block.stmts[from..to];
+        let following = &block.stmts[to..];
+        let has_tail = following.is_empty()
+            && match selected.last() {
+                Some(Stmt::Expr(_)) => true,
+                _ => false,
+            };
+
+        let candidates: HashSet<String> = self.bindings.iter().cloned().collect();
+        let mut uses = UseCollector::new(&candidates);
+        selected.iter().for_each(|stmt| uses.visit_stmt(stmt));
+        let declared: HashSet<String> = uses.scopes[0].clone();
+
+        let mut inputs = vec![];
+        for name in self.bindings {
+            if !uses.free.contains(name) {
+                continue;
+            }
+            match self.env.vars.get(name).cloned().flatten() {
+                Some(ty) => inputs.push((format_ident!("{}", name), ty)),
+                None => {
+                    return Err(format!(
+                        "could not infer the type of `{}`, please annotate its declaration",
+                        name
+                    ))
+                }
+            }
+        }
+
+        let mut used_after = UseCollector::new(&declared);
+        following
+            .iter()
+            .for_each(|stmt| used_after.visit_stmt(stmt));
+        let mut env = TypeEnv {
+            vars: self.env.vars.clone(),
+            local_types: self.env.local_types.clone(),
+        };
+        let mut outputs = vec![];
+        for stmt in selected {
+            let local = match stmt {
+                Stmt::Local(local) => local,
+                _ => continue,
+            };
+            let mut bindings = vec![];
+            match &local.pat {
+                Pat::Type(t) => pat_bindings(t.pat.as_ref(), Some(t.ty.as_ref()), &mut bindings),
+                pat => {
+                    let ty = local
+                        .init
+                        .as_ref()
+                        .and_then(|(_, init)| infer_type(init.as_ref(), &env));
+                    pat_bindings(pat, ty.as_ref(), &mut bindings)
+                }
+            }
+            for (id, ty, mutable) in bindings {
+                let name = id.to_string();
+                env.vars.insert(name.clone(), ty.clone());
+                outputs.retain(|(out, _, _): &(syn::Ident, Type, bool)| out != &id);
+                if !used_after.free.contains(&name) {
+                    continue;
+                }
+                match ty {
+                    Some(ty) => outputs.push((id, ty, mutable)),
+                    None => {
+                        return Err(format!(
+                            "could not infer the type of `{}`, please annotate its declaration",
+                            name
+                        ))
+                    }
+                }
+            }
+        }
+
+        let tail = match has_tail {
+            false => None,
+            true if !outputs.is_empty() => {
+                return Err(
+                    "the selection both declares variables used after it and ends with a value"
+                        .to_string(),
+                )
+            }
+            true if self.depth == 0 => Some(self.caller_output.clone()),
+            true => match selected.last() {
+                Some(Stmt::Expr(e)) => match infer_type(e, &env) {
+                    Some(ty) => Some(ReturnType::Type(Default::default(), Box::new(ty))),
+                    None => {
+                        return Err(
+                            "could not infer the type of the value of the selection".to_string()
+                        )
+                    }
+                },
+                _ => None,
+            },
+        };
+
+        let uses_self_type = uses.uses_self_type
+            || inputs
+                .iter()
+                .map(|(_, ty)| ty.to_token_stream())
+                .chain(outputs.iter().map(|(_, ty, _)| ty.to_token_stream()))
+                .chain(tail.iter().map(|ty| ty.to_token_stream()))
+                .any(|tokens| mentions(tokens).contains("Self"));
+
+        let mut awaits = AwaitFinder { found: false };
+        selected.iter().for_each(|stmt| awaits.visit_stmt(stmt));
+
+        // how the callee is called, whatever it ends up in
+        let placement = match self.placement {
+            Placement::Auto if uses.uses_self => Placement::Method,
+            Placement::Auto if self.in_impl && uses_self_type => Placement::Associated,
+            Placement::Auto => Placement::Free,
+            Placement::Free if uses.uses_self || uses_self_type => {
+                return Err(
+                    "the selection uses `self` or `Self`, the callee cannot be a free function"
+                        .to_string(),
+                )
+            }
+            Placement::Free => Placement::Free,
+            _ if !self.in_impl => return Err(
+                "the caller is not in an `impl` or trait, the callee can only be a free function"
+                    .to_string(),
+            ),
+            Placement::Method if !self.has_receiver => {
+                return Err("the caller has no receiver to pass on to a method".to_string())
+            }
+            Placement::Associated if uses.uses_self => {
+                return Err("the selection uses `self`, the callee must be a method".to_string())
+            }
+            Placement::TraitDefault | Placement::TraitRequired if uses.uses_self => {
+                Placement::Method
+            }
+            Placement::TraitDefault | Placement::TraitRequired => Placement::Associated,
+            placement => placement,
+        };
+
+        let callee = format_ident!("{}", self.callee_fn_name);
+        let args = inputs.iter().map(|(id, _)| id);
+        let mut call: Expr = match placement {
+            Placement::Method => syn::parse_quote! {self.#callee(#(#args),*)},
+            Placement::Associated => syn::parse_quote! {Self::#callee(#(#args),*)},
+            _ => syn::parse_quote! {#callee(#(#args),*)},
+        };
+        if awaits.found {
+            call = syn::parse_quote! {#call.await};
+        }
+        let call_stmt: Stmt = match (&tail, &outputs[..]) {
+            (Some(_), _) => Stmt::Expr(call),
+            (None, []) => syn::parse_quote! {#call;},
+            (None, [(id, _, mutable)]) => match mutable {
+                true => syn::parse_quote! {let mut #id = #call;},
+                false => syn::parse_quote! {let #id = #call;},
+            },
+            (None, outputs) => {
+                let pats = outputs.iter().map(|(id, _, mutable)| match mutable {
+                    true => quote! {mut #id},
+                    false => quote! {#id},
+                });
+                syn::parse_quote! {let (#(#pats),*) = #call;}
+            }
+        };
+
+        let stmts = block
+            .stmts
+            .splice(from..to, vec![call_stmt])
+            .collect::<Vec<Stmt>>();
+        Ok(Cut {
+            inputs,
+            outputs,
+            tail,
+            stmts,
+            is_async: awaits.found,
+            placement,
+        })
+    }
+}
+
+impl VisitMut for BlockCutter<'_> {
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        if self.cut.is_some() {
+            return;
+        }
+        let span = i.span();
+        if !(start_of(span) <= self.start && self.end <= end_of(span)) {
+            return;
+        }
+        let mut selected = None;
+        let mut partial = false;
+        for (idx, stmt) in i.stmts.iter().enumerate() {
+            let (start, end) = (start_of(stmt.span()), end_of(stmt.span()));
+            if self.start <= start && end <= self.end {
+                selected = match selected {
+                    None => Some((idx, idx + 1)),
+                    Some((from, _)) => Some((from, idx + 1)),
+                };
+            } else if start < self.end && self.start < end {
+                partial = true;
+            }
+        }
+        match (selected, partial) {
+            (Some((from, to)), false) => self.cut = Some(self.cut_stmts(i, from, to)),
+            (Some(_), true) => {
+                self.cut = Some(Err(
+                    "the selection does not cover whole statements".to_string()
+                ))
+            }
+            (None, _) => {
+                self.depth += 1;
+                syn::visit_mut::visit_block_mut(self, i);
+                self.depth -= 1;
+            }
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+}
+
+/// Identifiers and lifetime names occurring in some tokens.
+fn mentions(tokens: TokenStream) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(id) => {
+                names.insert(id.to_string());
+            }
+            TokenTree::Group(g) => names.extend(mentions(g.stream())),
+            _ => (),
+        }
+    }
+    names
+}
+
+/// Keep the generic parameters (and their where clauses) of the caller that
+/// the callee signature refers to.
+fn callee_generics(caller: &Generics, used: &HashSet<String>) -> Generics {
+    let param_name = |p: &GenericParam| match p {
+        GenericParam::Type(t) => t.ident.to_string(),
+        GenericParam::Lifetime(l) => l.lifetime.ident.to_string(),
+        GenericParam::Const(c) => c.ident.to_string(),
+    };
+    let all: HashSet<String> = caller.params.iter().map(param_name).collect();
+    let mut generics = caller.clone();
+    generics.params = caller
+        .params
+        .iter()
+        .filter(|p| used.contains(&param_name(p)))
+        .cloned()
+        .collect();
+    let kept: HashSet<String> = generics.params.iter().map(param_name).collect();
+    if let Some(where_clause) = &mut generics.where_clause {
+        where_clause.predicates = where_clause
+            .predicates
+            .iter()
+            .filter(|pred: &&WherePredicate| {
+                let names = mentions(pred.to_token_stream());
+                let params = names.intersection(&all).collect::<Vec<&String>>();
+                !params.is_empty() && params.iter().all(|p| kept.contains(*p))
+            })
+            .cloned()
+            .collect();
+        if where_clause.predicates.is_empty() {
+            generics.where_clause = None;
+        }
+    }
+    if generics.params.is_empty() {
+        generics.lt_token = None;
+        generics.gt_token = None;
+    }
+    generics
+}
+
+struct CallerCutter<'a> {
+    caller: &'a FnSpec,
+    callee_fn_name: &'a str,
+    style: CalleeStyle,
+    start: Pos,
+    end: Pos,
+    local_types: HashSet<String>,
+    traits: HashMap<String, Visibility>,
+    trait_impls: HashMap<String, usize>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    /// the trait the callee is to be moved to
+    trait_name: Option<String>,
+    result: Option<Result<(), String>>,
+}
+
+impl CallerCutter<'_> {
+    fn is_caller(&self, sig: &Signature, span: Span) -> bool {
+        sig.ident == self.caller.name
+            && self
+                .caller
+                .line
+                .map_or(true, |line| line == sig.ident.span().start().line)
+            && self.encloses(span)
+    }
+
+    fn cut_fn(
+        &mut self,
+        vis: &Visibility,
+        sig: &Signature,
+        block: &mut Block,
+        in_impl: bool,
+    ) -> Result<(ItemFn, Placement), String> {
+        let mut scope = ScopeCollector {
+            start: self.start,
+            end: self.end,
+            env: TypeEnv {
+                vars: HashMap::new(),
+                local_types: self.local_types.clone(),
+            },
+            bindings: vec![],
+        };
+        for arg in &sig.inputs {
+            if let FnArg::Typed(t) = arg {
+                scope.bind(t.pat.as_ref(), Some(t.ty.as_ref()));
+            }
+        }
+        scope.visit_block(block);
+        debug!("in scope: {:?}", scope.bindings);
+
+        let mut cutter = BlockCutter {
+            start: self.start,
+            end: self.end,
+            depth: 0,
+            env: &scope.env,
+            bindings: &scope.bindings,
+            caller_output: &sig.output,
+            in_impl,
+            has_receiver: sig.receiver().is_some(),
+            placement: self.style.placement,
+            callee_fn_name: self.callee_fn_name,
+            cut: None,
+        };
+        cutter.visit_block_mut(block);
+        let cut = match cutter.cut {
+            Some(cut) => cut?,
+            None => return Err("the selection does not cover any statement".to_string()),
+        };
+        self.inputs = cut.inputs.iter().map(|(id, _)| id.to_string()).collect();
+        self.outputs = cut
+            .outputs
+            .iter()
+            .map(|(id, _, _)| id.to_string())
+            .collect();
+
+        let output = match (&cut.tail, &cut.outputs[..]) {
+            (Some(tail), _) => tail.clone(),
+            (None, []) => ReturnType::Default,
+            (None, [(_, ty, _)]) => syn::parse_quote! {-> #ty},
+            (None, outputs) => {
+                let tys = outputs.iter().map(|(_, ty, _)| ty);
+                syn::parse_quote! {-> (#(#tys),*)}
+            }
+        };
+        let mut stmts = cut.stmts;
+        match &cut.outputs[..] {
+            [] => (),
+            [(id, _, _)] => stmts.push(Stmt::Expr(syn::parse_quote! {#id})),
+            outputs => {
+                let ids = outputs.iter().map(|(id, _, _)| id);
+                stmts.push(Stmt::Expr(syn::parse_quote! {(#(#ids),*)}))
+            }
+        }
+
+        let receiver = match cut.placement {
+            Placement::Method => sig.inputs.iter().find(|arg| match arg {
+                FnArg::Receiver(_) => true,
+                _ => false,
+            }),
+            _ => None,
+        };
+        let params = receiver.into_iter().cloned().chain(
+            cut.inputs
+                .iter()
+                .map(|(id, ty)| syn::parse_quote! {#id: #ty}),
+        );
+        let mut used = HashSet::new();
+        cut.inputs
+            .iter()
+            .for_each(|(_, ty)| used.extend(mentions(ty.to_token_stream())));
+        used.extend(mentions(output.to_token_stream()));
+        let generics = callee_generics(&sig.generics, &used);
+        let (generic_params, _, where_clause) = generics.split_for_impl();
+        let callee = format_ident!("{}", self.callee_fn_name);
+        let asyncness = match cut.is_async {
+            true => quote! {async},
+            false => quote! {},
+        };
+        let vis = match self.style.visibility {
+            CalleeVisibility::Private => Visibility::Inherited,
+            CalleeVisibility::Crate => syn::parse_quote! {pub(crate)},
+            CalleeVisibility::Caller => vis.clone(),
+        };
+        let callee_fn: ItemFn = syn::parse_quote! {
+            #vis #asyncness fn #callee #generic_params (#(#params),*) #output #where_clause {
+                #(#stmts)*
+            }
+        };
+        Ok((callee_fn, cut.placement))
+    }
+
+    fn encloses(&self, span: Span) -> bool {
+        start_of(span) <= self.start && self.end <= end_of(span)
+    }
+
+    fn cut_items(&mut self, items: &mut Vec<Item>) {
+        let mut idx = 0;
+        while idx < items.len() && self.result.is_none() {
+            if let Some(item) = self.cut_item(&mut items[idx]) {
+                items.insert(idx + 1, item);
+                self.result = Some(Ok(()));
+            }
+            idx += 1;
+        }
+    }
+
+    /// Cut the selection out of `item` or the functions in it, innermost
+    /// first. Returns the callee when it goes after `item`.
+    fn cut_item(&mut self, item: &mut Item) -> Option<Item> {
+        match item {
+            Item::Fn(f) if self.encloses(f.span()) => {
+                self.visit_block_mut(&mut f.block);
+                if self.result.is_none() && self.is_caller(&f.sig, f.span()) {
+                    match self.cut_fn(&f.vis, &f.sig, &mut f.block, false) {
+                        Ok((callee, _)) => return Some(Item::Fn(callee)),
+                        Err(e) => self.result = Some(Err(e)),
+                    }
+                }
+            }
+            Item::Mod(m) => {
+                if let Some((_, items)) = &mut m.content {
+                    self.cut_items(items)
+                }
+            }
+            Item::Impl(imp) => {
+                for j in 0..imp.items.len() {
+                    let cut = match &mut imp.items[j] {
+                        ImplItem::Method(m) if self.encloses(m.span()) => {
+                            self.visit_block_mut(&mut m.block);
+                            if self.result.is_some() || !self.is_caller(&m.sig, m.span()) {
+                                break;
+                            }
+                            self.cut_fn(&m.vis, &m.sig, &mut m.block, true)
+                        }
+                        _ => continue,
+                    };
+                    let trait_name = imp
+                        .trait_
+                        .as_ref()
+                        .and_then(|(_, path, _)| path.segments.last())
+                        .map(|segment| segment.ident.to_string());
+                    let (callee, placement) = match cut {
+                        Ok(cut) => cut,
+                        Err(e) => {
+                            self.result = Some(Err(e));
+                            break;
+                        }
+                    };
+                    let mut callee = ImplItemMethod {
+                        attrs: callee.attrs,
+                        vis: callee.vis,
+                        defaultness: None,
+                        sig: callee.sig,
+                        block: *callee.block,
+                    };
+                    if self.style.placement.is_trait() {
+                        match trait_name.as_ref().and_then(|name| self.traits.get(name)) {
+                            // trait items are as visible as the trait
+                            Some(vis) => callee.vis = vis.clone(),
+                            None => {
+                                self.result =
+                                    Some(Err("the caller does not implement a trait of this file"
+                                        .to_string()));
+                                break;
+                            }
+                        }
+                        // the other impls would have no body for a required callee
+                        let impls = trait_name
+                            .as_ref()
+                            .and_then(|name| self.trait_impls.get(name));
+                        if self.style.placement == Placement::TraitRequired
+                            && impls.is_some_and(|impls| *impls > 1)
+                        {
+                            self.result = Some(Err("the trait has other impls in this file, \
+                                which cannot define the callee: make it a default method instead"
+                                .to_string()));
+                            break;
+                        }
+                        self.trait_name = trait_name.clone();
+                    }
+                    match (placement, trait_name) {
+                        (Placement::Free, _) => {
+                            return Some(Item::Fn(ItemFn {
+                                attrs: callee.attrs,
+                                vis: callee.vis,
+                                sig: callee.sig,
+                                block: Box::new(callee.block),
+                            }))
+                        }
+                        // there is no room for it in the trait impl, it goes
+                        // in an impl of its own until moved to the trait
+                        (_, Some(_)) => {
+                            return Some(Item::Impl(ItemImpl {
+                                attrs: vec![],
+                                defaultness: None,
+                                unsafety: None,
+                                trait_: None,
+                                items: vec![ImplItem::Method(callee)],
+                                ..imp.clone()
+                            }))
+                        }
+                        (_, None) => {
+                            imp.items.insert(j + 1, ImplItem::Method(callee));
+                            self.result = Some(Ok(()))
+                        }
+                    }
+                    break;
+                }
+            }
+            Item::Trait(t) => {
+                for j in 0..t.items.len() {
+                    let cut = match &mut t.items[j] {
+                        TraitItem::Method(m) if self.encloses(m.span()) => {
+                            let is_caller = self.is_caller(&m.sig, m.span());
+                            match &mut m.default {
+                                Some(block) => {
+                                    self.visit_block_mut(block);
+                                    if self.result.is_some() || !is_caller {
+                                        break;
+                                    }
+                                    // trait items are as visible as the trait
+                                    self.cut_fn(&t.vis, &m.sig, block, true)
+                                }
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    if self.style.placement.is_trait() {
+                        self.trait_name = Some(t.ident.to_string());
+                    }
+                    match cut {
+                        Ok((callee, Placement::Free)) => return Some(Item::Fn(callee)),
+                        Ok((callee, _)) => {
+                            t.items.insert(
+                                j + 1,
+                                TraitItem::Method(TraitItemMethod {
+                                    attrs: callee.attrs,
+                                    sig: callee.sig,
+                                    default: Some(*callee.block),
+                                    semi_token: None,
+                                }),
+                            );
+                            self.result = Some(Ok(()))
+                        }
+                        Err(e) => self.result = Some(Err(e)),
+                    }
+                    break;
+                }
+            }
+            _ => (),
+        }
+        None
+    }
+}
+
+impl VisitMut for CallerCutter<'_> {
+    /// Functions nested in the body of another, the callee going next to them
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        if !self.encloses(i.span()) {
+            return;
+        }
+        let mut idx = 0;
+        while idx < i.stmts.len() && self.result.is_none() {
+            match &mut i.stmts[idx] {
+                Stmt::Item(item) => {
+                    if let Some(callee) = self.cut_item(item) {
+                        i.stmts.insert(idx + 1, Stmt::Item(callee));
+                        self.result = Some(Ok(()));
+                    }
+                }
+                stmt => self.visit_stmt_mut(stmt),
+            }
+            idx += 1;
+        }
+    }
+}
+
+/// Finds the innermost function around the selection.
+struct EnclosingFn {
+    start: Pos,
+    end: Pos,
+    name: Option<String>,
+}
+
+impl EnclosingFn {
+    fn check(&mut self, sig: &Signature, span: Span) {
+        if start_of(span) <= self.start && self.end <= end_of(span) {
+            let line = sig.ident.span().start().line;
+            self.name = Some(format!("{}@{}", sig.ident, line));
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for EnclosingFn {
+    fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+        self.check(&i.sig, i.span());
+        syn::visit::visit_item_fn(self, i);
+    }
+
+    fn visit_impl_item_method(&mut self, i: &'ast ImplItemMethod) {
+        self.check(&i.sig, i.span());
+        syn::visit::visit_impl_item_method(self, i);
+    }
+
+    fn visit_trait_item_method(&mut self, i: &'ast TraitItemMethod) {
+        if i.default.is_some() {
+            self.check(&i.sig, i.span());
+        }
+        syn::visit::visit_trait_item_method(self, i);
+    }
+}
+
+/// The innermost function containing the selection, i.e. the caller to
+/// extract from, as `name@LINE` (see `resolve::FnSpec`) so that it is not
+/// mistaken for a function of the same name elsewhere in the file.
+pub fn enclosing_fn(source: &str, start: Position, end: Position) -> Result<String, Error> {
+    let file = syn::parse_str::<syn::File>(source)?;
+    let mut finder = EnclosingFn {
+        start: pos(start.to_line_column(source)),
+        end: pos(end.to_line_column(source)),
+        name: None,
+    };
+    finder.visit_file(&file);
+    finder.name.ok_or(Error::InvalidSelection(
+        "the selection is not inside a function".to_string(),
+    ))
+}
+
+#[derive(Debug)]
+pub struct ExtractResult {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// The trait to move the callee to once fixed up, see `move_to_trait`.
+    pub trait_name: Option<String>,
+}
+
+/// Same as `inner_extract` on a source string, returning the new source.
+pub fn extract_source(
+    source: &str,
+    caller_fn_name: &str,
+    callee_fn_name: &str,
+    start: Position,
+    end: Position,
+    style: CalleeStyle,
+) -> Result<(String, ExtractResult), Error> {
+    let mut file = syn::parse_str::<syn::File>(source)?;
+
+    let mut local_types = LocalTypeCollector {
+        local_types: HashSet::new(),
+        traits: HashMap::new(),
+        trait_impls: HashMap::new(),
+    };
+    local_types.visit_file(&file);
+
+    let caller = FnSpec::parse(caller_fn_name)?;
+    let callee = FnSpec::parse(callee_fn_name)?;
+    let mut cutter = CallerCutter {
+        caller: &caller,
+        callee_fn_name: callee.name.as_str(),
+        style,
+        start: pos(start.to_line_column(source)),
+        end: pos(end.to_line_column(source)),
+        local_types: local_types.local_types,
+        traits: local_types.traits,
+        trait_impls: local_types.trait_impls,
+        inputs: vec![],
+        outputs: vec![],
+        trait_name: None,
+        result: None,
+    };
+    cutter.cut_items(&mut file.items);
+    match cutter.result {
+        Some(Ok(())) => (),
+        Some(Err(e)) => {
+            debug!("could not extract: {}", e);
+            return Err(Error::InvalidSelection(e));
+        }
+        None => {
+            debug!("did not find caller around the selection");
+            return Err(Error::CallerNotFound(caller_fn_name.to_string()));
+        }
+    }
+
+    let file = file.into_token_stream().to_string();
+    Ok((
+        splice(source, &format_source(&file)),
+        ExtractResult {
+            inputs: cutter.inputs,
+            outputs: cutter.outputs,
+            trait_name: cutter.trait_name,
+        },
+    ))
+}
+
+/// Cut the statements between `start` and `end` out of `caller_fn_name` into a
+/// new function `callee_fn_name`, as an IDE would. The callee takes its inputs
+/// by value; fixing up borrows, lifetimes and non-local control flow is left to
+/// the other stages, which is why the file before the cut is kept at
+/// `pre_extract_file_name`. `style` says where the callee goes and how visible
+/// it is.
+pub fn inner_extract(
+    file_name: &str,
+    new_file_name: &str,
+    pre_extract_file_name: &str,
+    caller_fn_name: &str,
+    callee_fn_name: &str,
+    start: Position,
+    end: Position,
+    style: CalleeStyle,
+) -> Result<ExtractResult, Error> {
+    let file_content = fs::read_to_string(&file_name)?;
+    let (new_file_content, res) = extract_source(
+        file_content.as_str(),
+        caller_fn_name,
+        callee_fn_name,
+        start,
+        end,
+        style,
+    )?;
+    fs::write(pre_extract_file_name.to_string(), &file_content)?;
+    fs::write(new_file_name.to_string(), new_file_content)?;
+    Ok(res)
+}
+
+/// The callee as the other stages left it, and the type of the impl it was in
+/// unless it is a method of the trait already.
+struct Staged {
+    sig: Signature,
+    block: Block,
+    self_ty: Option<Type>,
+}
+
+/// Take the callee out of the impl it was put in, or out of the trait with
+/// `required`, leaving its declaration there.
+fn take_staged(items: &mut Vec<Item>, callee: &FnId, required: bool) -> Option<Staged> {
+    for idx in 0..items.len() {
+        let staged = match &mut items[idx] {
+            Item::Impl(imp) if imp.trait_.is_none() => {
+                let method = imp.items.iter().position(|item| match item {
+                    ImplItem::Method(m) => callee.is(&m.sig.ident),
+                    _ => false,
+                });
+                match method.map(|j| imp.items.remove(j)) {
+                    Some(ImplItem::Method(m)) => Staged {
+                        sig: m.sig,
+                        block: m.block,
+                        self_ty: Some(imp.self_ty.as_ref().clone()),
+                    },
+                    _ => continue,
+                }
+            }
+            Item::Trait(t) => {
+                let method = t.items.iter_mut().find_map(|item| match item {
+                    TraitItem::Method(m) if callee.is(&m.sig.ident) => Some(m),
+                    _ => None,
+                });
+                match method {
+                    Some(m) => {
+                        let block = m.default.clone()?;
+                        if required {
+                            *m = declaration(&m.sig);
+                        }
+                        Staged {
+                            sig: m.sig.clone(),
+                            block,
+                            self_ty: None,
+                        }
+                    }
+                    None => continue,
+                }
+            }
+            Item::Mod(m) => match m.content.as_mut() {
+                Some((_, items)) => match take_staged(items, callee, required) {
+                    Some(staged) => return Some(staged),
+                    None => continue,
+                },
+                None => continue,
+            },
+            _ => continue,
+        };
+        if let Item::Impl(imp) = &items[idx] {
+            if imp.items.is_empty() {
+                items.remove(idx);
+            }
+        }
+        return Some(staged);
+    }
+    None
+}
+
+/// The declaration of a required method, patterns not being allowed there.
+fn declaration(sig: &Signature) -> TraitItemMethod {
+    let mut sig = sig.clone();
+    for arg in sig.inputs.iter_mut() {
+        if let FnArg::Typed(t) = arg {
+            if let Pat::Ident(p) = t.pat.as_mut() {
+                p.mutability = None;
+            }
+        }
+    }
+    TraitItemMethod {
+        attrs: vec![],
+        sig,
+        default: None,
+        semi_token: Some(Default::default()),
+    }
+}
+
+fn find_trait<'f>(items: &'f mut [Item], name: &str) -> Option<&'f mut ItemTrait> {
+    items.iter_mut().find_map(|item| match item {
+        Item::Trait(t) if t.ident == name => Some(t),
+        Item::Mod(m) => m
+            .content
+            .as_mut()
+            .and_then(|(_, items)| find_trait(items, name)),
+        _ => None,
+    })
+}
+
+fn impls_of<'f>(items: &'f mut [Item], name: &str, impls: &mut Vec<&'f mut ItemImpl>) {
+    for item in items.iter_mut() {
+        match item {
+            Item::Impl(imp) => {
+                let implements = imp
+                    .trait_
+                    .as_ref()
+                    .and_then(|(_, path, _)| path.segments.last())
+                    .map_or(false, |segment| segment.ident == name);
+                if implements {
+                    impls.push(imp)
+                }
+            }
+            Item::Mod(m) => {
+                if let Some((_, items)) = m.content.as_mut() {
+                    impls_of(items, name, impls)
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Move the callee, once the other stages are done with it, to the trait
+/// `trait_name` of the file: as a default method, or with `required` as a
+/// method that each impl of the trait in the file defines. The caller's impl
+/// gets the body, and so do the others if the callee came from a default
+/// method; otherwise there must be no other impl, which would have no body
+/// to give it.
+pub fn move_to_trait(
+    source: &str,
+    callee_fn_name: &str,
+    trait_name: &str,
+    required: bool,
+) -> Result<String, Error> {
+    let mut file = syn::parse_str::<syn::File>(source)?;
+    let callee = resolve_fn(&file, callee_fn_name)?
+        .ok_or_else(|| Error::CalleeNotFound(callee_fn_name.to_string()))?;
+    let staged = take_staged(&mut file.items, &callee, required)
+        .ok_or_else(|| Error::CalleeNotFound(callee_fn_name.to_string()))?;
+    if let (true, Some(self_ty)) = (required, &staged.self_ty) {
+        let self_ty = self_ty.to_token_stream().to_string();
+        let mut impls = vec![];
+        impls_of(&mut file.items, trait_name, &mut impls);
+        if let Some(other) = impls
+            .iter()
+            .find(|imp| imp.self_ty.to_token_stream().to_string() != self_ty)
+        {
+            return Err(Error::Other(format!(
+                "`{}` for `{}` cannot define the required method `{}`",
+                trait_name,
+                other.self_ty.to_token_stream(),
+                callee.name
+            )));
+        }
+    }
+    let item_trait = find_trait(&mut file.items, trait_name)
+        .ok_or_else(|| Error::Other(format!("trait `{}` not found", trait_name)))?;
+    match (required, &staged.self_ty) {
+        (false, None) => return Ok(source.to_string()),
+        (false, Some(_)) => item_trait.items.push(TraitItem::Method(TraitItemMethod {
+            attrs: vec![],
+            sig: staged.sig,
+            default: Some(staged.block),
+            semi_token: None,
+        })),
+        (true, self_ty) => {
+            if self_ty.is_some() {
+                item_trait
+                    .items
+                    .push(TraitItem::Method(declaration(&staged.sig)));
+            }
+            let mut impls = vec![];
+            impls_of(&mut file.items, trait_name, &mut impls);
+            for imp in impls {
+                imp.items.push(ImplItem::Method(ImplItemMethod {
+                    attrs: vec![],
+                    vis: Visibility::Inherited,
+                    defaultness: None,
+                    sig: staged.sig.clone(),
+                    block: staged.block.clone(),
+                }));
+            }
+        }
+    }
+    let file = file.into_token_stream().to_string();
+    Ok(splice(source, &format_source(&file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(ty: &str) -> Option<String> {
+        syn::parse_str::<Type>(ty)
+            .ok()
+            .map(|ty| ty.to_token_stream().to_string())
+    }
+
+    fn infer(expr: &str, vars: &[(&str, &str)]) -> Option<String> {
+        let env = TypeEnv {
+            vars: vars
+                .iter()
+                .map(|(name, ty)| (name.to_string(), syn::parse_str(ty).ok()))
+                .collect(),
+            local_types: HashSet::from(["Point".to_string()]),
+        };
+        infer_type(&syn::parse_str(expr).unwrap(), &env).map(|ty| ty.to_token_stream().to_string())
+    }
+
+    #[test]
+    fn test_infer_type_of_literals() {
+        assert_eq!(infer("1", &[]), ty("i32"));
+        assert_eq!(infer("1u8", &[]), ty("u8"));
+        assert_eq!(infer("2.5", &[]), ty("f64"));
+        assert_eq!(infer("'a'", &[]), ty("char"));
+        assert_eq!(infer("\"a\"", &[]), ty("&str"));
+        assert_eq!(infer("(1, true)", &[]), ty("(i32, bool)"));
+        assert_eq!(infer("[1u64, 2]", &[]), ty("[u64; 2usize]"));
+    }
+
+    #[test]
+    fn test_infer_type_of_operations() {
+        let vars = [("x", "&u64"), ("s", "&str"), ("v", "Vec<u8>")];
+        // arithmetic on a reference gives a value
+        assert_eq!(infer("x + 1", &vars), ty("u64"));
+        assert_eq!(infer("*x", &vars), ty("u64"));
+        assert_eq!(infer("x < &2", &vars), ty("bool"));
+        assert_eq!(infer("&mut v", &vars), ty("&mut Vec<u8>"));
+        assert_eq!(infer("x as i64", &vars), ty("i64"));
+        assert_eq!(infer("s.to_owned()", &vars), ty("String"));
+        assert_eq!(infer("v.clone()", &vars), ty("Vec<u8>"));
+        assert_eq!(infer("v.len()", &vars), ty("usize"));
+        assert_eq!(infer("unknown + 1", &vars), None);
+    }
+
+    #[test]
+    fn test_infer_type_of_constructors() {
+        assert_eq!(infer("Point { x: 1, y: 2 }", &[]), ty("Point"));
+        assert_eq!(infer("Point::new()", &[]), ty("Point"));
+        assert_eq!(infer("Other { x: 1 }", &[]), None);
+        assert_eq!(infer("Some(1)", &[]), ty("Option<i32>"));
+        assert_eq!(infer("Box::new('a')", &[]), ty("Box<char>"));
+        assert_eq!(infer("String::from(\"a\")", &[]), ty("String"));
+        assert_eq!(infer("vec![1u8; 3]", &[]), ty("Vec<u8>"));
+        assert_eq!(infer("vec![true, false]", &[]), ty("Vec<bool>"));
+        assert_eq!(infer("format!(\"{}\", 1)", &[]), ty("String"));
+        assert_eq!(infer("foo()", &[]), None);
+    }
+
+    const SHAPES: &str = "trait Area {
+    fn area(&self) -> i32;
+}
+struct Square(i32);
+impl Area for Square {
+    fn area(&self) -> i32 {
+        let side = self.0;
+        side * side
+    }
+}
+";
+
+    const CIRCLE: &str = "struct Circle(i32);
+impl Area for Circle {
+    fn area(&self) -> i32 {
+        3 * self.0 * self.0
+    }
+}
+";
+
+    fn extract_area(source: &str, placement: Placement) -> Result<(String, ExtractResult), Error> {
+        let style = CalleeStyle {
+            placement,
+            ..CalleeStyle::default()
+        };
+        let (start, end) = (Position::LineColumn(7, 9), Position::LineColumn(8, 20));
+        extract_source(source, "area", "bar", start, end, style)
+    }
+
+    #[test]
+    fn test_trait_required_callee_needs_the_only_impl() {
+        let (_, res) = extract_area(SHAPES, Placement::TraitRequired).unwrap();
+        assert_eq!(res.trait_name.as_deref(), Some("Area"));
+        // the other impl would have no body for it
+        let shapes = format!("{}{}", SHAPES, CIRCLE);
+        assert!(matches!(
+            extract_area(&shapes, Placement::TraitRequired),
+            Err(Error::InvalidSelection(_))
+        ));
+        assert!(extract_area(&shapes, Placement::TraitDefault).is_ok());
+    }
+
+    #[test]
+    fn test_move_to_trait_refuses_required_callee_of_other_impls() {
+        let (extracted, _) = extract_area(SHAPES, Placement::TraitRequired).unwrap();
+        let moved = move_to_trait(&extracted, "bar", "Area", true).unwrap();
+        let file = syn::parse_file(&moved).unwrap();
+        let impls = file
+            .items
+            .iter()
+            .filter(|item| matches!(item, Item::Impl(imp) if imp.trait_.is_none()))
+            .count();
+        assert_eq!(impls, 0);
+        assert!(moved.contains("fn bar(&self) -> i32;"));
+
+        let extracted = format!("{}{}", extracted, CIRCLE);
+        assert!(move_to_trait(&extracted, "bar", "Area", true).is_err());
+        assert!(move_to_trait(&extracted, "bar", "Area", false).is_ok());
+    }
+}
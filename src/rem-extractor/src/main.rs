@@ -0,0 +1,152 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use std::process::exit;
+use std::time::SystemTime;
+use std::{env, fs};
+
+use rem_extractor::extract;
+use rem_extractor::extract::{CalleeStyle, CalleeVisibility, Placement, Position};
+
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Cut the statements between START and END out of the caller
+    Run {
+        file_name: String,
+        new_file_name: String,
+        caller_fn_name: String,
+        callee_fn_name: String,
+        /// LINE:COLUMN (1-based) or byte offset
+        start: Position,
+        /// LINE:COLUMN (1-based) or byte offset
+        end: Position,
+        /// Where to keep the file before the cut, defaults to NEW_FILE_NAME_ORIGINAL
+        #[arg(long)]
+        pre_extract_file_name: Option<String>,
+        /// Where the callee goes
+        #[arg(long, value_enum, default_value_t = Placement::Auto)]
+        placement: Placement,
+        /// The visibility of the callee
+        #[arg(long, value_enum, default_value_t = CalleeVisibility::Private)]
+        visibility: CalleeVisibility,
+    },
+    /// Test the extractor on inputs
+    Test {},
+}
+
+fn main() {
+    env::set_var("RUST_LOG", "debug");
+    env_logger::init();
+    let args = Cli::parse();
+    match &args.command {
+        Commands::Test {} => test(),
+        Commands::Run {
+            file_name,
+            new_file_name,
+            caller_fn_name,
+            callee_fn_name,
+            start,
+            end,
+            pre_extract_file_name,
+            placement,
+            visibility,
+        } => {
+            let pre_extract_file_name = match pre_extract_file_name {
+                Some(path) => path.clone(),
+                None => format!("{}_ORIGINAL", new_file_name),
+            };
+            let res = extract::inner_extract(
+                file_name.as_str(),
+                new_file_name.as_str(),
+                pre_extract_file_name.as_str(),
+                caller_fn_name.as_str(),
+                callee_fn_name.as_str(),
+                *start,
+                *end,
+                CalleeStyle {
+                    placement: *placement,
+                    visibility: *visibility,
+                },
+            );
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1)
+                }
+            };
+            println!(
+                "{} takes ({}) and returns ({})",
+                callee_fn_name,
+                res.inputs.join(", "),
+                res.outputs.join(", ")
+            );
+        }
+    }
+}
+
+/// The extraction an input asks for on its first line, e.g.
+/// `// extract new_foo 7:5 11:37 --placement free --visibility crate`.
+fn fixture(source: &str) -> Option<(String, Position, Position, CalleeStyle)> {
+    let mut words = source
+        .lines()
+        .next()?
+        .strip_prefix("// extract ")?
+        .split_whitespace();
+    let caller_fn_name = words.next()?.to_string();
+    let start = words.next()?.parse().ok()?;
+    let end = words.next()?.parse().ok()?;
+    let mut style = CalleeStyle::default();
+    while let (Some(flag), Some(value)) = (words.next(), words.next()) {
+        match flag {
+            "--placement" => style.placement = Placement::from_str(value, true).ok()?,
+            "--visibility" => style.visibility = CalleeVisibility::from_str(value, true).ok()?,
+            _ => return None,
+        }
+    }
+    Some((caller_fn_name, start, end, style))
+}
+
+fn test() {
+    for file in fs::read_dir("./input").unwrap() {
+        let test_name = file.unwrap().file_name().to_owned();
+        let file_name = format!("./input/{}", test_name.to_str().unwrap());
+        let expected_file_name = format!("./output/{}", test_name.to_str().unwrap());
+        let source = fs::read_to_string(&file_name).unwrap();
+        let (caller_fn_name, start, end, style) = fixture(source.as_str()).unwrap();
+        let now = SystemTime::now();
+        let res = extract::extract_source(
+            source.as_str(),
+            caller_fn_name.as_str(),
+            "bar",
+            start,
+            end,
+            style,
+        );
+        let time_elapsed = now.elapsed().unwrap();
+        let expected = fs::read_to_string(&expected_file_name).unwrap_or_default();
+        let success = match &res {
+            Ok((extracted, _)) => extracted == &expected,
+            Err(e) => {
+                eprintln!("{}", e);
+                false
+            }
+        };
+        println!(
+            "{}: {} in {:#?}",
+            (if success {
+                format!("PASSED").green()
+            } else {
+                format!("FAILED").red()
+            }),
+            test_name.to_str().unwrap(),
+            time_elapsed
+        );
+        println!("------------------------------------------------------------------\n");
+    }
+}
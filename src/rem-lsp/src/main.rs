@@ -0,0 +1,17 @@
+use log::info;
+use lsp_server::Connection;
+use std::error::Error;
+
+use rem_lsp::server::{capabilities, main_loop};
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    // stdout is the protocol channel, logs go to stderr
+    env_logger::init();
+    info!("starting rem-lsp");
+    let (connection, io_threads) = Connection::stdio();
+    let params = connection.initialize(serde_json::to_value(capabilities())?)?;
+    main_loop(connection, params)?;
+    io_threads.join()?;
+    info!("shutting down rem-lsp");
+    Ok(())
+}
@@ -0,0 +1,577 @@
+use log::{debug, info};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification,
+};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, CodeActionRequest, CodeActionResolveRequest, ExecuteCommand,
+    Request as LspRequest,
+};
+use lsp_types::{
+    ApplyWorkspaceEditParams, CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand,
+    CodeActionParams, CodeActionProviderCapability, Command, ExecuteCommandOptions,
+    ExecuteCommandParams, InitializeParams, Position as LspPosition, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+
+use rem_cli::pipeline::{run_extraction_source, Extraction};
+use rem_extractor::extract::{enclosing_fn, Position};
+use rem_repairer::repair_lifetime_loosest_bound_first;
+use rem_utils::edit;
+
+pub const EXTRACT_TITLE: &str = "Extract function (REM)";
+/// Runs the extraction for clients that cannot resolve code actions
+pub const EXTRACT_COMMAND: &str = "rem.extract";
+const CALLEE_NAME: &str = "extracted";
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![CodeActionKind::REFACTOR_EXTRACT]),
+            work_done_progress_options: Default::default(),
+            resolve_provider: Some(true),
+        })),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![EXTRACT_COMMAND.to_string()],
+            work_done_progress_options: Default::default(),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Serve requests until the client shuts the server down. The connection is
+/// dropped on return, which lets its IO threads finish.
+pub fn main_loop(
+    connection: Connection,
+    params: serde_json::Value,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let mut server = Server::new(&params);
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                let resp = server.handle_request(req);
+                for req in server.outgoing.drain(..) {
+                    connection.sender.send(Message::Request(req))?;
+                }
+                connection.sender.send(Message::Response(resp))?;
+            }
+            Message::Response(resp) => {
+                if let Some(e) = resp.error {
+                    debug!("client failed {:?}: {}", resp.id, e.message);
+                }
+            }
+            Message::Notification(not) => server.handle_notification(not),
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    /// Contents of the open documents, which may not be saved yet
+    documents: HashMap<Url, String>,
+    /// Whether the client asks for the edit in `codeAction/resolve`. If not,
+    /// the action runs `EXTRACT_COMMAND` instead. Either way the (slow)
+    /// extraction only runs once the user picks the action.
+    lazy_edits: bool,
+    /// Requests to the client, sent before the response to the current request
+    outgoing: Vec<Request>,
+    next_id: i32,
+}
+
+impl Server {
+    fn new(params: &InitializeParams) -> Self {
+        let lazy_edits = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|t| t.code_action.as_ref())
+            .and_then(|c| c.resolve_support.as_ref())
+            .map(|r| r.properties.iter().any(|p| p == "edit"))
+            .unwrap_or(false);
+        Server {
+            documents: HashMap::new(),
+            lazy_edits,
+            outgoing: vec![],
+            next_id: 0,
+        }
+    }
+
+    fn handle_notification(&mut self, not: Notification) {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = not.extract::<<DidOpenTextDocument as LspNotification>::Params>(
+                    DidOpenTextDocument::METHOD,
+                ) {
+                    self.documents
+                        .insert(params.text_document.uri, params.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                if let Ok(mut params) = not
+                    .extract::<<DidChangeTextDocument as LspNotification>::Params>(
+                        DidChangeTextDocument::METHOD,
+                    )
+                {
+                    // full sync: the last change holds the whole document
+                    if let Some(change) = params.content_changes.pop() {
+                        self.documents.insert(params.text_document.uri, change.text);
+                    }
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = not
+                    .extract::<<DidCloseTextDocument as LspNotification>::Params>(
+                        DidCloseTextDocument::METHOD,
+                    )
+                {
+                    self.documents.remove(&params.text_document.uri);
+                }
+            }
+            method => debug!("ignoring notification {}", method),
+        }
+    }
+
+    fn handle_request(&mut self, req: Request) -> Response {
+        let req_id = req.id.clone();
+        match req.method.as_str() {
+            CodeActionRequest::METHOD => {
+                match req.extract::<CodeActionParams>(CodeActionRequest::METHOD) {
+                    Ok((id, params)) => {
+                        let actions = self.code_actions(&params);
+                        Response::new_ok(id, actions)
+                    }
+                    Err(e) => invalid_params(req_id, e),
+                }
+            }
+            CodeActionResolveRequest::METHOD => {
+                match req.extract::<CodeAction>(CodeActionResolveRequest::METHOD) {
+                    Ok((id, mut action)) => match self.resolve(&mut action) {
+                        Ok(()) => Response::new_ok(id, action),
+                        Err(reason) => {
+                            Response::new_err(id, ErrorCode::RequestFailed as i32, reason)
+                        }
+                    },
+                    Err(e) => invalid_params(req_id, e),
+                }
+            }
+            ExecuteCommand::METHOD => {
+                match req.extract::<ExecuteCommandParams>(ExecuteCommand::METHOD) {
+                    Ok((id, params)) if params.command == EXTRACT_COMMAND => {
+                        match self.extraction_edit(params.arguments.into_iter().next()) {
+                            Ok(edit) => {
+                                self.apply_edit(edit);
+                                Response::new_ok(id, serde_json::Value::Null)
+                            }
+                            Err(reason) => {
+                                Response::new_err(id, ErrorCode::RequestFailed as i32, reason)
+                            }
+                        }
+                    }
+                    Ok((id, params)) => Response::new_err(
+                        id,
+                        ErrorCode::InvalidParams as i32,
+                        format!("unknown command {}", params.command),
+                    ),
+                    Err(e) => invalid_params(req_id, e),
+                }
+            }
+            method => Response::new_err(
+                req_id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled method {}", method),
+            ),
+        }
+    }
+
+    fn code_actions(&self, params: &CodeActionParams) -> Vec<CodeActionOrCommand> {
+        if let Some(only) = &params.context.only {
+            if !only.iter().any(|kind| {
+                CodeActionKind::REFACTOR_EXTRACT
+                    .as_str()
+                    .starts_with(kind.as_str())
+            }) {
+                return vec![];
+            }
+        }
+        let uri = &params.text_document.uri;
+        let range = params.range;
+        let source = match self.documents.get(uri) {
+            Some(source) if range.start != range.end => source,
+            _ => return vec![],
+        };
+        let (start, end) = (
+            to_position(source, range.start),
+            to_position(source, range.end),
+        );
+        if let Err(e) = enclosing_fn(source, start, end) {
+            debug!("no extraction at {:?}: {}", range, e);
+            return vec![];
+        }
+
+        let mut action = CodeAction {
+            title: EXTRACT_TITLE.to_string(),
+            kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+            data: serde_json::to_value((uri, range)).ok(),
+            ..Default::default()
+        };
+        if !self.lazy_edits {
+            action.command = Some(Command {
+                title: EXTRACT_TITLE.to_string(),
+                command: EXTRACT_COMMAND.to_string(),
+                arguments: action.data.clone().map(|data| vec![data]),
+            });
+        }
+        vec![CodeActionOrCommand::CodeAction(action)]
+    }
+
+    /// Run the extraction and attach its result to the action as an edit.
+    fn resolve(&self, action: &mut CodeAction) -> Result<(), String> {
+        action.edit = Some(self.extraction_edit(action.data.clone())?);
+        Ok(())
+    }
+
+    /// Ask the client to apply `edit`.
+    fn apply_edit(&mut self, edit: WorkspaceEdit) {
+        self.next_id += 1;
+        let params = ApplyWorkspaceEditParams {
+            label: Some(EXTRACT_TITLE.to_string()),
+            edit,
+        };
+        self.outgoing.push(Request::new(
+            RequestId::from(self.next_id),
+            ApplyWorkspaceEdit::METHOD.to_string(),
+            params,
+        ));
+    }
+
+    /// Run the extraction the `data` of a REM code action stands for.
+    fn extraction_edit(&self, data: Option<serde_json::Value>) -> Result<WorkspaceEdit, String> {
+        let (uri, range): (Url, Range) = data
+            .and_then(|data| serde_json::from_value(data).ok())
+            .ok_or("not a REM code action".to_string())?;
+        let source = self
+            .documents
+            .get(&uri)
+            .ok_or(format!("{} is not open", uri))?;
+        let (start, end) = (
+            to_position(source, range.start),
+            to_position(source, range.end),
+        );
+        let caller_fn_name = enclosing_fn(source, start, end).map_err(|e| e.to_string())?;
+        let callee_fn_name = fresh_name(source, CALLEE_NAME);
+        let path = uri.to_file_path().ok();
+        let file_name = path
+            .as_ref()
+            .and_then(|path| path.to_str())
+            .unwrap_or(uri.as_str());
+        let mut extraction = Extraction::new(file_name, &caller_fn_name, &callee_fn_name);
+        extraction.selection = Some((start, end));
+        extraction.manifest_path = path.as_deref().and_then(find_manifest);
+        info!(
+            "extracting {} from {} in {}",
+            callee_fn_name, caller_fn_name, uri
+        );
+        let (_, result) = run_extraction_source(
+            source,
+            &extraction,
+            &repair_lifetime_loosest_bound_first::Repairer {},
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut changes = HashMap::from([(uri, lsp_edits(source, result.edits))]);
+        // the shared control enum goes at the root of the crate
+        if let Some(root) = result.crate_root {
+            let root_uri = Url::from_file_path(&root.file_name)
+                .map_err(|_| format!("invalid crate root {}", root.file_name))?;
+            changes.insert(root_uri, lsp_edits(&root.source, root.edits));
+        }
+        Ok(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        })
+    }
+}
+
+fn lsp_edits(source: &str, edits: Vec<edit::TextEdit>) -> Vec<TextEdit> {
+    edits
+        .into_iter()
+        .map(|edit| TextEdit {
+            range: Range {
+                start: to_lsp_position(source, edit.start),
+                end: to_lsp_position(source, edit.end),
+            },
+            new_text: edit.text,
+        })
+        .collect()
+}
+
+fn invalid_params<T: std::fmt::Debug>(id: RequestId, e: T) -> Response {
+    Response::new_err(id, ErrorCode::InvalidParams as i32, format!("{:?}", e))
+}
+
+/// LSP positions count columns in UTF-16 code units, the extractor in chars.
+pub fn to_position(source: &str, position: LspPosition) -> Position {
+    let line = source.split('\n').nth(position.line as usize).unwrap_or("");
+    let mut units = 0;
+    let mut chars = 0;
+    for c in line.chars() {
+        if units >= position.character as usize {
+            break;
+        }
+        units += c.len_utf16();
+        chars += 1;
+    }
+    Position::LineColumn(position.line as usize + 1, chars + 1)
+}
+
+/// The LSP position of the byte `offset` of `source`.
+pub fn to_lsp_position(source: &str, offset: usize) -> LspPosition {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    LspPosition::new(
+        before.matches('\n').count() as u32,
+        before[line_start..]
+            .chars()
+            .map(|c| c.len_utf16() as u32)
+            .sum(),
+    )
+}
+
+/// `name`, or `name_1`, `name_2`... if it is already used in `source`.
+fn fresh_name(source: &str, name: &str) -> String {
+    let used = |candidate: &str| {
+        source
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .any(|word| word == candidate)
+    };
+    let mut candidate = name.to_string();
+    let mut n = 0;
+    while used(candidate.as_str()) {
+        n += 1;
+        candidate = format!("{}_{}", name, n);
+    }
+    candidate
+}
+
+/// The manifest of the crate `path` belongs to, if any.
+fn find_manifest(path: &Path) -> Option<String> {
+    path.ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|manifest| manifest.exists())
+        .and_then(|manifest| manifest.to_str().map(|s| s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::notification::{Exit, Initialized};
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{
+        ClientCapabilities, CodeActionCapabilityResolveSupport, CodeActionClientCapabilities,
+        CodeActionContext, DidOpenTextDocumentParams, InitializedParams,
+        TextDocumentClientCapabilities, TextDocumentIdentifier, TextDocumentItem,
+    };
+    use std::thread::JoinHandle;
+
+    const SOURCE: &str = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+
+    fn uri() -> Url {
+        // no manifest above it, so the callee is repaired on its own
+        Url::parse("file:///rem-lsp-test/main.rs").unwrap()
+    }
+
+    /// Start a server on its own thread and initialize it as a client with
+    /// `capabilities`.
+    fn start(capabilities: ClientCapabilities) -> (Connection, JoinHandle<()>) {
+        let (server, client) = Connection::memory();
+        let handle = std::thread::spawn(move || {
+            let params = server
+                .initialize(serde_json::to_value(super::capabilities()).unwrap())
+                .unwrap();
+            main_loop(server, params).unwrap();
+        });
+        let params = InitializeParams {
+            capabilities,
+            ..Default::default()
+        };
+        request::<Initialize>(&client, 0, params);
+        notify::<Initialized>(&client, InitializedParams {});
+        (client, handle)
+    }
+
+    fn notify<N: LspNotification>(client: &Connection, params: N::Params) {
+        let not = Notification::new(N::METHOD.to_string(), params);
+        client.sender.send(not.into()).unwrap();
+    }
+
+    fn send<R: LspRequest>(client: &Connection, id: i32, params: R::Params) {
+        let req = Request::new(id.into(), R::METHOD.to_string(), params);
+        client.sender.send(req.into()).unwrap();
+    }
+
+    fn request<R: LspRequest>(
+        client: &Connection,
+        id: i32,
+        params: R::Params,
+    ) -> serde_json::Value {
+        send::<R>(client, id, params);
+        match client.receiver.recv().unwrap() {
+            Message::Response(Response {
+                result: Some(result),
+                error: None,
+                ..
+            }) => result,
+            msg => panic!("expected a result, got {:?}", msg),
+        }
+    }
+
+    fn shutdown(client: Connection, handle: JoinHandle<()>) {
+        request::<Shutdown>(&client, 99, ());
+        notify::<Exit>(&client, ());
+        handle.join().unwrap();
+    }
+
+    /// Open `SOURCE` and ask for the actions on its `println!` statement.
+    fn code_action(client: &Connection) -> CodeAction {
+        notify::<DidOpenTextDocument>(
+            client,
+            DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri(), "rust".to_string(), 0, SOURCE.into()),
+            },
+        );
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range: Range::new(LspPosition::new(2, 4), LspPosition::new(2, 22)),
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = request::<CodeActionRequest>(client, 1, params);
+        let mut actions: Vec<CodeAction> = serde_json::from_value(actions).unwrap();
+        assert_eq!(actions.len(), 1);
+        let action = actions.remove(0);
+        assert_eq!(action.title, EXTRACT_TITLE);
+        // nothing is extracted until the user picks the action
+        assert!(action.edit.is_none());
+        action
+    }
+
+    fn assert_extracts(edit: WorkspaceEdit) {
+        // `SOURCE` is ASCII, so columns are byte offsets into the line
+        let offset = |position: LspPosition| {
+            SOURCE
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum::<usize>()
+                + position.character as usize
+        };
+        let mut edits = edit.changes.unwrap().remove(&uri()).unwrap();
+        edits.sort_by_key(|e| offset(e.range.start));
+        let mut extracted = SOURCE.to_string();
+        for e in edits.iter().rev() {
+            extracted.replace_range(offset(e.range.start)..offset(e.range.end), &e.new_text);
+        }
+        assert_eq!(
+            extracted,
+            "fn main() {\n    let x = 1;\n    extracted(x);\n}\n\n\
+             fn extracted(x: i32) {\n    println!(\"{}\", x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_through_code_action_resolve() {
+        let (client, handle) = start(ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                code_action: Some(CodeActionClientCapabilities {
+                    resolve_support: Some(CodeActionCapabilityResolveSupport {
+                        properties: vec!["edit".to_string()],
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let action = code_action(&client);
+        assert!(action.command.is_none());
+        let resolved = request::<CodeActionResolveRequest>(&client, 2, action);
+        let resolved: CodeAction = serde_json::from_value(resolved).unwrap();
+        assert_extracts(resolved.edit.unwrap());
+        shutdown(client, handle);
+    }
+
+    #[test]
+    fn test_extract_through_execute_command() {
+        let (client, handle) = start(ClientCapabilities::default());
+        let command = code_action(&client).command.unwrap();
+        assert_eq!(command.command, EXTRACT_COMMAND);
+        let params = ExecuteCommandParams {
+            command: command.command,
+            arguments: command.arguments.unwrap(),
+            work_done_progress_params: Default::default(),
+        };
+        send::<ExecuteCommand>(&client, 2, params);
+        let apply = match client.receiver.recv().unwrap() {
+            Message::Request(req) => req,
+            msg => panic!("expected workspace/applyEdit, got {:?}", msg),
+        };
+        let (id, params) = apply
+            .extract::<ApplyWorkspaceEditParams>(ApplyWorkspaceEdit::METHOD)
+            .unwrap();
+        assert_extracts(params.edit);
+        let applied = lsp_types::ApplyWorkspaceEditResponse {
+            applied: true,
+            failure_reason: None,
+            failed_change: None,
+        };
+        client
+            .sender
+            .send(Response::new_ok(id, applied).into())
+            .unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(resp) => assert!(resp.error.is_none()),
+            msg => panic!("expected the command's response, got {:?}", msg),
+        }
+        shutdown(client, handle);
+    }
+
+    #[test]
+    fn test_to_position_counts_utf16_units() {
+        let source = "fn main() {\n    let s = \"😀\"; let x = 1;\n}\n";
+        // the emoji is two UTF-16 units but one char
+        let position = to_position(source, LspPosition::new(1, 17));
+        assert_eq!(position, Position::LineColumn(2, 17));
+    }
+
+    #[test]
+    fn test_to_lsp_position_counts_utf16_units() {
+        let source = "a\n\"😀\"b\n";
+        assert_eq!(to_lsp_position(source, 2), LspPosition::new(1, 0));
+        assert_eq!(to_lsp_position(source, 8), LspPosition::new(1, 4));
+        assert_eq!(
+            to_lsp_position(source, source.len()),
+            LspPosition::new(2, 0)
+        );
+    }
+
+    #[test]
+    fn test_fresh_name_avoids_existing_idents() {
+        assert_eq!(fresh_name("fn foo() {}", "extracted"), "extracted");
+        assert_eq!(
+            fresh_name("fn extracted() {} fn extracted_1() {}", "extracted"),
+            "extracted_2"
+        );
+    }
+}
@@ -1,16 +1,20 @@
 use log::{debug, info};
-use proc_macro2::Span;
+use once_cell::sync::Lazy;
+use proc_macro2::{Ident, Span};
 use quote::ToTokens;
 use regex::Regex;
-use rem_utils::format_source;
+use rem_utils::error::Error;
+use rem_utils::resolve::{resolve_fn, FnId, FnSpec};
+use rem_utils::{edit::splice, format_source};
 use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
 use std::collections::HashMap;
 use std::fs;
 use std::io::{BufWriter, Write};
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 use syn::{
-    visit_mut::VisitMut, ExprCall, ExprMethodCall, FnArg, GenericArgument, GenericParam,
+    visit_mut::VisitMut, Expr, ExprCall, ExprMethodCall, FnArg, GenericArgument, GenericParam,
     ImplItemMethod, ItemFn, Lifetime, PredicateLifetime, ReturnType, Signature, TraitItemMethod,
     TypeReference, WhereClause, WherePredicate,
 };
@@ -18,8 +22,17 @@ use syn::{
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////     REPAIR HELPERS     ////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A rustc suggestion replacing a whole line, e.g. `help: consider ...` followed by the new line
+static STANDARD_HELP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"help: consider.+\n.*\n(?P<line_number>\d+) \| (?P<replacement>.+)\n").unwrap()
+});
+
+/// A rustc suggestion adding a lifetime bound, e.g. `= help: consider adding ... bound: `'a: 'b``
+static BOUNDS_HELP: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"= help: consider.+bound: `(?P<constraint_lhs>'[a-z0-9]+): (?P<constraint_rhs>'[a-z0-9]+)`").unwrap()
+});
+
 pub struct RepairResult {
-    pub success: bool,
     pub repair_count: i32,
     pub has_non_elidible_lifetime: bool,
     pub has_struct_lt: bool,
@@ -27,9 +40,46 @@ pub struct RepairResult {
 
 pub trait RepairSystem {
     fn name(&self) -> &str;
-    fn repair_project(&self, src_path: &str, manifest_path: &str, fn_name: &str) -> RepairResult;
-    fn repair_file(&self, file_name: &str, new_file_name: &str) -> RepairResult;
-    fn repair_function(&self, file_name: &str, new_file_name: &str, fn_name: &str) -> RepairResult;
+    /// Repair `fn_name` in `src_path` with `cargo check`, building in
+    /// `target_dir` if given instead of the target directory of the crate.
+    fn repair_project(
+        &self,
+        src_path: &str,
+        manifest_path: &str,
+        target_dir: Option<&str>,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error>;
+    fn repair_file(&self, file_name: &str, new_file_name: &str) -> Result<RepairResult, Error>;
+    fn repair_function(
+        &self,
+        file_name: &str,
+        new_file_name: &str,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error>;
+
+    /// Same as `repair_function` on a source string. rustc only compiles real
+    /// files, so the source goes through a scratch file in the temp directory.
+    fn repair_source(&self, source: &str, fn_name: &str) -> Result<(String, RepairResult), Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let stem = format!("rem_repair_{}_{}", std::process::id(), nanos);
+        let scratch = std::env::temp_dir().join(format!("{}.rs", stem));
+        let scratch_name = scratch.to_str().ok_or_else(|| {
+            Error::Other(format!(
+                "temp path {} is not valid UTF-8",
+                scratch.display()
+            ))
+        })?;
+        fs::write(&scratch, source)?;
+        let res = self.repair_function(scratch_name, scratch_name, fn_name);
+        let repaired = fs::read_to_string(&scratch);
+        fs::remove_file(&scratch).unwrap_or_default();
+        // rustc leaves the binary in the working directory
+        fs::remove_file(&stem).unwrap_or_default();
+        Ok((repaired?, res?))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,7 +93,7 @@ pub struct RustcSpan {
     pub file_name: String,
 }
 
-pub fn repair_standard_help(stderr: &str, new_file_name: &str) -> bool {
+pub fn repair_standard_help(stderr: &str, new_file_name: &str) -> Result<bool, Error> {
     let deserializer = serde_json::Deserializer::from_str(stderr);
     let stream = deserializer.into_iter::<RustcError>();
     let mut helped = false;
@@ -52,11 +102,9 @@ pub fn repair_standard_help(stderr: &str, new_file_name: &str) -> bool {
             Ok(i) => i.rendered,
             Err(_) => stderr.to_string(),
         };
-        let re = Regex::new(r"help: consider.+\n.*\n(?P<line_number>\d+) \| (?P<replacement>.+)\n")
-            .unwrap();
-        let help_lines = re.captures_iter(rendered.as_str());
+        let help_lines = STANDARD_HELP.captures_iter(rendered.as_str());
 
-        let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
+        let file_content = fs::read_to_string(&new_file_name)?;
 
         let lines = file_content.split("\n");
         let mut lines_modifiable = Vec::new();
@@ -66,7 +114,7 @@ pub fn repair_standard_help(stderr: &str, new_file_name: &str) -> bool {
 
         let mut current_line = 0;
 
-        let out_file = fs::File::create(&new_file_name).unwrap();
+        let out_file = fs::File::create(&new_file_name)?;
         let mut writer = BufWriter::new(out_file);
         for captured in help_lines {
             /*
@@ -87,22 +135,22 @@ pub fn repair_standard_help(stderr: &str, new_file_name: &str) -> bool {
 
             helped = true;
             while current_line < line_number - 1 {
-                writeln!(writer, "{}", lines_modifiable[current_line]).unwrap();
+                writeln!(writer, "{}", lines_modifiable[current_line])?;
                 current_line += 1;
             }
-            writeln!(writer, "{}", replacement).unwrap();
+            writeln!(writer, "{}", replacement)?;
             current_line += 1;
         }
         while current_line < lines_modifiable.len() {
-            writeln!(writer, "{}", lines_modifiable[current_line]).unwrap();
+            writeln!(writer, "{}", lines_modifiable[current_line])?;
             current_line += 1;
         }
     }
-    helped
+    Ok(helped)
 }
 
 struct FnLifetimeBounder<'a> {
-    fn_name: &'a str,
+    fn_id: &'a FnId,
     lifetime: &'a str,
     bound: &'a str,
     success: bool,
@@ -110,9 +158,8 @@ struct FnLifetimeBounder<'a> {
 
 impl VisitMut for FnLifetimeBounder<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_bounder(&mut i.sig),
         }
@@ -120,17 +167,16 @@ impl VisitMut for FnLifetimeBounder<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_bounder(&mut i.sig),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_bounder(&mut i.sig),
         }
@@ -156,7 +202,7 @@ impl FnLifetimeBounder<'_> {
     }
 }
 
-pub fn repair_bounds_help(stderr: &str, new_file_name: &str, fn_name: &str) -> bool {
+pub fn repair_bounds_help(stderr: &str, new_file_name: &str, fn_name: &str) -> Result<bool, Error> {
     let deserializer = serde_json::Deserializer::from_str(stderr);
     let stream = deserializer.into_iter::<RustcError>();
     let mut helped = false;
@@ -165,8 +211,7 @@ pub fn repair_bounds_help(stderr: &str, new_file_name: &str, fn_name: &str) -> b
             Ok(i) => i.rendered,
             Err(_) => stderr.to_string(),
         };
-        let re = Regex::new(r"= help: consider.+bound: `(?P<constraint_lhs>'[a-z0-9]+): (?P<constraint_rhs>'[a-z0-9]+)`").unwrap();
-        let help_lines = re.captures_iter(rendered.as_str());
+        let help_lines = BOUNDS_HELP.captures_iter(rendered.as_str());
         /*
             &caps["line_number"],
             &caps["fn_sig"],
@@ -177,12 +222,14 @@ pub fn repair_bounds_help(stderr: &str, new_file_name: &str, fn_name: &str) -> b
             // println!("found helps: {}, {}",
             //          &captured["constraint_lhs"],
             //          &captured["constraint_rhs"]);
-            let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-            let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-                .map_err(|e| format!("{:?}", e))
-                .unwrap();
+            let file_content = fs::read_to_string(&new_file_name)?;
+            let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+            let fn_id = match resolve_fn(&file, fn_name) {
+                Ok(Some(fn_id)) => fn_id,
+                _ => continue,
+            };
             let mut visit = FnLifetimeBounder {
-                fn_name,
+                fn_id: &fn_id,
                 lifetime: &captured["constraint_lhs"],
                 bound: &captured["constraint_rhs"],
                 success: false,
@@ -191,33 +238,30 @@ pub fn repair_bounds_help(stderr: &str, new_file_name: &str, fn_name: &str) -> b
             let file = file.into_token_stream().to_string();
             match visit.success {
                 true => {
-                    fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
+                    fs::write(
+                        new_file_name.to_string(),
+                        splice(&file_content, &format_source(&file)),
+                    )?;
                     helped = true;
                 }
                 false => (),
             }
         }
     }
-    helped
+    Ok(helped)
 }
 
 pub fn repair_iteration(
     compile_cmd: &mut Command,
-    process_errors: &dyn Fn(&str) -> bool,
+    process_errors: &dyn Fn(&str) -> Result<bool, Error>,
     print_stats: bool,
     max_iterations: Option<i32>,
-) -> RepairResult {
+) -> Result<RepairResult, Error> {
     let mut count = 0;
     let max_iterations = max_iterations.unwrap_or(25);
-    let mut repair_result = RepairResult {
-        success: false,
-        repair_count: 0,
-        has_non_elidible_lifetime: false,
-        has_struct_lt: false,
-    };
 
     let success = loop {
-        let out = compile_cmd.output().unwrap();
+        let out = compile_cmd.output()?;
         let stderr = String::from_utf8_lossy(&out.stderr);
         if out.status.success() {
             break true;
@@ -225,7 +269,7 @@ pub fn repair_iteration(
         count += 1;
 
         let temp = stderr.to_string();
-        if !process_errors(temp.as_str()) {
+        if !process_errors(temp.as_str())? {
             break false;
         }
         if max_iterations == count {
@@ -238,9 +282,16 @@ pub fn repair_iteration(
         info!("status: {}", success);
     }
 
-    repair_result.success = success;
-    repair_result.repair_count = count;
-    repair_result
+    match success {
+        true => Ok(RepairResult {
+            repair_count: count,
+            has_non_elidible_lifetime: false,
+            has_struct_lt: false,
+        }),
+        false => Err(Error::RepairExhausted {
+            repair_count: count,
+        }),
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -315,7 +366,7 @@ impl VisitMut for FnLifetimeEliderArgHelper<'_> {
 }
 
 struct FnLifetimeElider<'a> {
-    fn_name: &'a str,
+    fn_id: &'a FnId,
     annotations_left: bool,
     has_struct_lt: bool,
 }
@@ -364,9 +415,8 @@ impl VisitMut for ChangeLtHelperElider<'_> {
 
 impl VisitMut for FnLifetimeElider<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_elider(&mut i.sig),
         }
@@ -374,17 +424,16 @@ impl VisitMut for FnLifetimeElider<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_elider(&mut i.sig),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.fn_lifetime_elider(&mut i.sig),
         }
@@ -584,7 +633,6 @@ impl FnLifetimeElider<'_> {
 }
 
 pub struct ElideLifetimeResult {
-    pub success: bool,
     pub annotations_left: bool,
     pub has_struct_lt: bool,
 }
@@ -596,24 +644,38 @@ Do not elide lifetimes when receiver (self) is in the input
 
 Elision rules are here: https://doc.rust-lang.org/nomicon/lifetime-elision.htm
 */
-pub fn elide_lifetimes_annotations(new_file_name: &str, fn_name: &str) -> ElideLifetimeResult {
-    let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
+pub fn elide_lifetimes_annotations_file(
+    file: &mut syn::File,
+    fn_name: &str,
+) -> Result<ElideLifetimeResult, Error> {
+    let fn_id =
+        resolve_fn(file, fn_name)?.ok_or_else(|| Error::CalleeNotFound(fn_name.to_string()))?;
     let mut visit = FnLifetimeElider {
-        fn_name,
+        fn_id: &fn_id,
         annotations_left: false,
         has_struct_lt: false,
     };
-    visit.visit_file_mut(&mut file);
-    let file = file.into_token_stream().to_string();
-    fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
-    ElideLifetimeResult {
-        success: true,
+    visit.visit_file_mut(file);
+    Ok(ElideLifetimeResult {
         annotations_left: visit.annotations_left,
         has_struct_lt: visit.has_struct_lt,
-    }
+    })
+}
+
+/// Same as `elide_lifetimes_annotations_file`, rewriting `new_file_name` in place
+pub fn elide_lifetimes_annotations(
+    new_file_name: &str,
+    fn_name: &str,
+) -> Result<ElideLifetimeResult, Error> {
+    let file_content = fs::read_to_string(&new_file_name)?;
+    let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+    let res = elide_lifetimes_annotations_file(&mut file, fn_name)?;
+    let file = file.into_token_stream().to_string();
+    fs::write(
+        new_file_name.to_string(),
+        splice(&file_content, &format_source(&file)),
+    )?;
+    Ok(res)
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -624,77 +686,78 @@ pub struct RenameFn<'a> {
     pub(crate) callee_postfix: &'a str,
 }
 
+impl RenameFn<'_> {
+    /// `ident` without the postfix, if it names the callee
+    fn renamed(&self, ident: &Ident) -> Option<Ident> {
+        let name = self
+            .callee_name
+            .strip_suffix(self.callee_postfix)
+            .unwrap_or(self.callee_name);
+        match ident == name || *ident == format!("{}{}", name, self.callee_postfix) {
+            true => Some(Ident::new(name, ident.span())),
+            false => None,
+        }
+    }
+}
+
 impl VisitMut for RenameFn<'_> {
     fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let callee = i.clone().method.into_token_stream().to_string();
-        match callee.contains(self.callee_name) {
-            true => {
-                i.method = syn::parse_str(callee.replace(self.callee_postfix, "").as_str()).unwrap()
-            }
-            false => syn::visit_mut::visit_expr_method_call_mut(self, i),
+        match self.renamed(&i.method) {
+            Some(method) => i.method = method,
+            None => syn::visit_mut::visit_expr_method_call_mut(self, i),
         }
     }
 
     fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
-        let callee = i.func.as_ref().into_token_stream().to_string();
-        match callee.contains(self.callee_name) {
-            true => {
-                debug!("callee: {} matched", &callee);
-                *i.func.as_mut() =
-                    syn::parse_str(callee.replace(self.callee_postfix, "").as_str()).unwrap();
+        if let Expr::Path(p) = i.func.as_mut() {
+            if let Some(segment) = p.path.segments.last_mut() {
+                if let Some(ident) = self.renamed(&segment.ident) {
+                    debug!("callee: {} matched", &segment.ident);
+                    segment.ident = ident;
+                }
             }
-            false => {}
         }
         syn::visit_mut::visit_expr_call_mut(self, i);
     }
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let callee = i.sig.ident.to_string();
-        match callee.contains(self.callee_name) {
-            true => {
-                i.sig.ident =
-                    syn::parse_str(callee.replace(self.callee_postfix, "").as_str()).unwrap();
-            }
-            false => {}
+        if let Some(ident) = self.renamed(&i.sig.ident) {
+            i.sig.ident = ident;
         }
         syn::visit_mut::visit_impl_item_method_mut(self, i);
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
-        let callee = i.sig.ident.to_string();
-        match callee.contains(self.callee_name) {
-            true => {
-                i.sig.ident =
-                    syn::parse_str(callee.replace(self.callee_postfix, "").as_str()).unwrap();
-            }
-            false => {}
+        if let Some(ident) = self.renamed(&i.sig.ident) {
+            i.sig.ident = ident;
         }
         syn::visit_mut::visit_item_fn_mut(self, i);
     }
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let callee = i.sig.ident.to_string();
-        match callee.contains(self.callee_name) {
-            true => {
-                i.sig.ident =
-                    syn::parse_str(callee.replace(self.callee_postfix, "").as_str()).unwrap();
-            }
-            false => {}
+        if let Some(ident) = self.renamed(&i.sig.ident) {
+            i.sig.ident = ident;
         }
         syn::visit_mut::visit_trait_item_method_mut(self, i);
     }
 }
 
-pub fn callee_renamer(new_file_name: &str, fn_name: &str) {
-    let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
+pub fn callee_renamer(new_file_name: &str, fn_name: &str) -> Result<(), Error> {
+    let file_content = fs::read_to_string(&new_file_name)?;
+    let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+    let callee_name = match FnSpec::parse(fn_name) {
+        Ok(spec) => spec.name,
+        Err(_) => fn_name.to_string(),
+    };
     let mut visitor = RenameFn {
-        callee_name: fn_name,
+        callee_name: &callee_name,
         callee_postfix: "____EXTRACT_THIS",
     };
     visitor.visit_file_mut(&mut file);
     let file = file.into_token_stream().to_string();
-    fs::write(new_file_name.to_string(), format_source(&file)).unwrap()
+    fs::write(
+        new_file_name.to_string(),
+        splice(&file_content, &format_source(&file)),
+    )?;
+    Ok(())
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -708,20 +771,14 @@ pub struct CargoError {
 pub fn repair_iteration_project(
     compile_cmd: &mut Command,
     src_path: &str,
-    process_errors: &dyn Fn(&RustcError) -> bool,
+    process_errors: &dyn Fn(&RustcError) -> Result<bool, Error>,
     print_stats: bool,
     max_iterations: Option<i32>,
-) -> RepairResult {
+) -> Result<RepairResult, Error> {
     let mut count = 0;
     let max_iterations = max_iterations.unwrap_or(25);
-    let mut repair_result = RepairResult {
-        success: false,
-        repair_count: 0,
-        has_non_elidible_lifetime: false,
-        has_struct_lt: false,
-    };
     let success = loop {
-        let out = compile_cmd.output().unwrap();
+        let out = compile_cmd.output()?;
         if out.status.success() {
             info!("repair succeeded");
             break true;
@@ -746,7 +803,7 @@ pub fn repair_iteration_project(
                             if src_path.contains(&span.file_name) {
                                 debug!("processing error: {}", &message.rendered);
                                 last_failure = message.rendered.clone();
-                                if process_errors(&message) {
+                                if process_errors(&message)? {
                                     help = true;
                                     break;
                                 }
@@ -776,7 +833,14 @@ pub fn repair_iteration_project(
         info!("status: {}", success);
     }
 
-    repair_result.success = success;
-    repair_result.repair_count = count;
-    repair_result
+    match success {
+        true => Ok(RepairResult {
+            repair_count: count,
+            has_non_elidible_lifetime: false,
+            has_struct_lt: false,
+        }),
+        false => Err(Error::RepairExhausted {
+            repair_count: count,
+        }),
+    }
 }
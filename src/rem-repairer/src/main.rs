@@ -11,7 +11,6 @@ mod repair_lifetime_simple;
 mod repair_lifetime_tightest_bound_first;
 mod repair_rustfix;
 
-use crate::common::RepairResult;
 use crate::RepairerType::{LoosestBoundsFirst, TightestBoundsFirst};
 use common::RepairSystem;
 
@@ -72,9 +71,13 @@ fn main() {
             let success = if *verbose {
                 print_repair_stat(&repair_system, file_name, new_file_name, fn_name)
             } else {
-                let RepairResult { success, .. } =
-                    repair_system.repair_function(file_name, new_file_name, fn_name);
-                success
+                match repair_system.repair_function(file_name, new_file_name, fn_name) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        false
+                    }
+                }
             };
             if !success {
                 exit(1)
@@ -95,9 +98,13 @@ fn main() {
             let success = if *verbose {
                 print_repair_stat_project(&repair_system, src_path, manifest_path, fn_name)
             } else {
-                let RepairResult { success, .. } =
-                    repair_system.repair_project(src_path, manifest_path, fn_name);
-                success
+                match repair_system.repair_project(src_path, manifest_path, None, fn_name) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        false
+                    }
+                }
             };
             if !success {
                 exit(1)
@@ -114,8 +121,8 @@ fn print_repair_stat_project(
 ) -> bool {
     println!("\n\n{}: {}", src_path, fn_name);
     let now = SystemTime::now();
-    let RepairResult { success, .. } =
-        repair_system.repair_project(src_path, manifest_path, fn_name);
+    let result = repair_system.repair_project(src_path, manifest_path, None, fn_name);
+    let success = result.is_ok();
     let time_elapsed = now.elapsed().unwrap();
     println!(
         "{}: {} refactored {} in {:#?}",
@@ -128,6 +135,9 @@ fn print_repair_stat_project(
         src_path,
         time_elapsed
     );
+    if let Err(e) = result {
+        println!("{}", e);
+    }
     success
 }
 
@@ -139,8 +149,8 @@ fn print_repair_stat(
 ) -> bool {
     println!("\n\n{}: {}", file_name, fn_name);
     let now = SystemTime::now();
-    let RepairResult { success, .. } =
-        repair_system.repair_function(file_name, new_file_name, fn_name);
+    let result = repair_system.repair_function(file_name, new_file_name, fn_name);
+    let success = result.is_ok();
     let time_elapsed = now.elapsed().unwrap();
     println!(
         "{}: {} refactored {} in {:#?}",
@@ -153,6 +163,9 @@ fn print_repair_stat(
         file_name,
         time_elapsed
     );
+    if let Err(e) = result {
+        println!("{}", e);
+    }
     success
 }
 
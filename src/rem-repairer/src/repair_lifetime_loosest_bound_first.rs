@@ -14,7 +14,9 @@ use crate::common::{
     repair_iteration_project, RepairResult, RepairSystem, RustcError,
 };
 use crate::repair_lifetime_simple;
-use rem_utils::{check_project, compile_file, format_source};
+use rem_utils::error::Error;
+use rem_utils::resolve::{resolve_fn, FnId};
+use rem_utils::{check_project, compile_file, edit::splice, format_source};
 
 pub struct Repairer {}
 
@@ -23,72 +25,63 @@ impl RepairSystem for Repairer {
         "_loosest_bounds_first_repairer"
     }
 
-    fn repair_project(&self, src_path: &str, manifest_path: &str, fn_name: &str) -> RepairResult {
-        let annot_res = annotate_loose_named_lifetime(src_path, fn_name);
-        if !annot_res.success {
-            return RepairResult {
-                success: false,
-                repair_count: 0,
-                has_non_elidible_lifetime: false,
-                has_struct_lt: false,
-            };
-        }
+    fn repair_project(
+        &self,
+        src_path: &str,
+        manifest_path: &str,
+        target_dir: Option<&str>,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error> {
+        let annot_res = annotate_loose_named_lifetime(src_path, fn_name)?;
         // println!("annotated: {}", fs::read_to_string(&src_path).unwrap());
         let mut compile_cmd = check_project(manifest_path, &vec![]);
+        if let Some(target_dir) = target_dir {
+            compile_cmd.env("CARGO_TARGET_DIR", target_dir);
+        }
         let process_errors =
             |ce: &RustcError| repair_bounds_help(ce.rendered.as_str(), src_path, fn_name);
-        match repair_iteration_project(&mut compile_cmd, src_path, &process_errors, true, Some(50))
-        {
-            RepairResult {
-                success: true,
-                repair_count,
-                ..
-            } => {
-                debug!("pre elision: {}", fs::read_to_string(&src_path).unwrap());
-                let elide_res = elide_lifetimes_annotations(src_path, fn_name);
-                callee_renamer(src_path, fn_name);
-                RepairResult {
-                    success: true,
-                    repair_count,
-                    has_non_elidible_lifetime: elide_res.annotations_left,
-                    has_struct_lt: elide_res.has_struct_lt || annot_res.has_struct_lt,
-                }
-            }
-            result => result,
-        }
+        let RepairResult { repair_count, .. } =
+            repair_iteration_project(&mut compile_cmd, src_path, &process_errors, true, Some(50))?;
+        debug!("pre elision: {}", fs::read_to_string(&src_path)?);
+        let elide_res = elide_lifetimes_annotations(src_path, fn_name)?;
+        callee_renamer(src_path, fn_name)?;
+        Ok(RepairResult {
+            repair_count,
+            has_non_elidible_lifetime: elide_res.annotations_left,
+            has_struct_lt: elide_res.has_struct_lt || annot_res.has_struct_lt,
+        })
     }
 
-    fn repair_file(&self, file_name: &str, new_file_name: &str) -> RepairResult {
+    fn repair_file(&self, file_name: &str, new_file_name: &str) -> Result<RepairResult, Error> {
         repair_lifetime_simple::Repairer {}.repair_file(file_name, new_file_name)
     }
 
-    fn repair_function(&self, file_name: &str, new_file_name: &str, fn_name: &str) -> RepairResult {
-        fs::copy(file_name, &new_file_name).unwrap();
-        annotate_loose_named_lifetime(&new_file_name, fn_name);
+    fn repair_function(
+        &self,
+        file_name: &str,
+        new_file_name: &str,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error> {
+        if file_name != new_file_name {
+            fs::copy(file_name, &new_file_name)?;
+        }
+        annotate_loose_named_lifetime(&new_file_name, fn_name)?;
         // println!("annotated: {}", fs::read_to_string(&new_file_name).unwrap());
-        let args: Vec<&str> = vec!["--error-format=json"];
+        let args: Vec<&str> = vec!["--error-format=json", "--edition=2021"];
 
         let mut compile_cmd = compile_file(&new_file_name, &args);
 
         let process_errors = |stderr: &str| repair_bounds_help(stderr, new_file_name, fn_name);
 
-        match repair_iteration(&mut compile_cmd, &process_errors, true, Some(50)) {
-            RepairResult {
-                success: true,
-                repair_count,
-                ..
-            } => {
-                // println!("repaired: {}", fs::read_to_string(&new_file_name).unwrap());
-                let elide_res = elide_lifetimes_annotations(new_file_name, fn_name);
-                RepairResult {
-                    success: true,
-                    repair_count,
-                    has_non_elidible_lifetime: elide_res.annotations_left,
-                    has_struct_lt: elide_res.has_struct_lt,
-                }
-            }
-            result => result,
-        }
+        let RepairResult { repair_count, .. } =
+            repair_iteration(&mut compile_cmd, &process_errors, true, Some(50))?;
+        // println!("repaired: {}", fs::read_to_string(&new_file_name).unwrap());
+        let elide_res = elide_lifetimes_annotations(new_file_name, fn_name)?;
+        Ok(RepairResult {
+            repair_count,
+            has_non_elidible_lifetime: elide_res.annotations_left,
+            has_struct_lt: elide_res.has_struct_lt,
+        })
     }
 }
 
@@ -227,7 +220,7 @@ impl VisitMut for LooseLifetimeAnnotatorFnArgHelper {
 }
 
 struct LooseLifetimeAnnotator<'a> {
-    fn_name: &'a str,
+    fn_id: &'a FnId,
     lt_num: i32,
     success: bool,
     has_struct_lt: bool,
@@ -235,9 +228,8 @@ struct LooseLifetimeAnnotator<'a> {
 
 impl VisitMut for LooseLifetimeAnnotator<'_> {
     fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.loose_lifetime_annotator(&mut i.sig),
         }
@@ -245,17 +237,16 @@ impl VisitMut for LooseLifetimeAnnotator<'_> {
     }
 
     fn visit_item_fn_mut(&mut self, i: &mut syn::ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.loose_lifetime_annotator(&mut i.sig),
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 
     fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
-        let id = i.sig.ident.to_string();
         //println!("caller name: {}, at: {}", self.caller_fn_name, &id);
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => self.loose_lifetime_annotator(&mut i.sig),
         }
@@ -320,33 +311,34 @@ impl LooseLifetimeAnnotator<'_> {
 }
 
 struct AnnotationResult {
-    success: bool,
     has_struct_lt: bool,
 }
 
-fn annotate_loose_named_lifetime(new_file_name: &str, fn_name: &str) -> AnnotationResult {
-    let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
+fn annotate_loose_named_lifetime(
+    new_file_name: &str,
+    fn_name: &str,
+) -> Result<AnnotationResult, Error> {
+    let file_content = fs::read_to_string(&new_file_name)?;
+    let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+    let fn_id =
+        resolve_fn(&file, fn_name)?.ok_or_else(|| Error::CalleeNotFound(fn_name.to_string()))?;
     let mut visit = LooseLifetimeAnnotator {
-        fn_name,
+        fn_id: &fn_id,
         success: false,
         has_struct_lt: false,
         lt_num: 0,
     };
     visit.visit_file_mut(&mut file);
+    if !visit.success {
+        return Err(Error::CalleeNotFound(fn_name.to_string()));
+    }
     let file = file.into_token_stream().to_string();
-    let success = match visit.success {
-        true => {
-            fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
-            true
-        }
-        false => false,
-    };
+    fs::write(
+        new_file_name.to_string(),
+        splice(&file_content, &format_source(&file)),
+    )?;
 
-    AnnotationResult {
-        success,
+    Ok(AnnotationResult {
         has_struct_lt: visit.has_struct_lt,
-    }
+    })
 }
@@ -4,6 +4,7 @@ use crate::common::{
     repair_bounds_help, repair_iteration, repair_standard_help, RepairResult, RepairSystem,
 };
 use rem_utils::compile_file;
+use rem_utils::error::Error;
 
 pub struct Repairer {}
 
@@ -16,29 +17,35 @@ impl RepairSystem for Repairer {
         &self,
         _src_path: &str,
         _manifest_path: &str,
+        _target_dir: Option<&str>,
         _fn_name: &str,
-    ) -> RepairResult {
-        RepairResult {
-            success: false,
-            repair_count: 0,
-            has_non_elidible_lifetime: false,
-            has_struct_lt: false,
-        }
+    ) -> Result<RepairResult, Error> {
+        Err(Error::Other(format!(
+            "{} does not repair projects",
+            self.name()
+        )))
     }
 
-    fn repair_file(&self, file_name: &str, new_file_name: &str) -> RepairResult {
+    fn repair_file(&self, file_name: &str, new_file_name: &str) -> Result<RepairResult, Error> {
         self.repair_function(file_name, new_file_name, "")
     }
 
-    fn repair_function(&self, file_name: &str, new_file_name: &str, fn_name: &str) -> RepairResult {
-        let args: Vec<&str> = vec!["--error-format=json"];
-        fs::copy(file_name, &new_file_name).unwrap();
+    fn repair_function(
+        &self,
+        file_name: &str,
+        new_file_name: &str,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error> {
+        let args: Vec<&str> = vec!["--error-format=json", "--edition=2021"];
+        if file_name != new_file_name {
+            fs::copy(file_name, &new_file_name)?;
+        }
 
         let mut compile_cmd = compile_file(&new_file_name, &args);
 
         let process_errors = |stderr: &str| {
-            repair_bounds_help(stderr, new_file_name, fn_name)
-                || repair_standard_help(stderr, new_file_name)
+            Ok(repair_bounds_help(stderr, new_file_name, fn_name)?
+                || repair_standard_help(stderr, new_file_name)?)
         };
 
         repair_iteration(&mut compile_cmd, &process_errors, true, None)
@@ -3,6 +3,7 @@ use quote::ToTokens;
 use regex::Regex;
 
 use log::debug;
+use once_cell::sync::Lazy;
 use std::fs;
 use syn::{visit_mut::VisitMut, FnArg, Lifetime, LifetimeDef, Type};
 
@@ -11,7 +12,9 @@ use crate::common::{
     repair_iteration_project, RepairResult, RepairSystem, RustcError,
 };
 use crate::repair_lifetime_simple;
-use rem_utils::{check_project, compile_file, format_source};
+use rem_utils::error::Error;
+use rem_utils::resolve::{resolve_fn, FnId};
+use rem_utils::{check_project, compile_file, edit::splice, format_source};
 
 pub struct Repairer {}
 
@@ -20,74 +23,73 @@ impl RepairSystem for Repairer {
         "_tightest_bounds_first_repairer"
     }
 
-    fn repair_project(&self, src_path: &str, manifest_path: &str, fn_name: &str) -> RepairResult {
-        annotate_tight_named_lifetime(src_path, fn_name);
+    fn repair_project(
+        &self,
+        src_path: &str,
+        manifest_path: &str,
+        target_dir: Option<&str>,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error> {
+        annotate_tight_named_lifetime(src_path, fn_name)?;
         let mut compile_cmd = check_project(manifest_path, &vec![]);
+        if let Some(target_dir) = target_dir {
+            compile_cmd.env("CARGO_TARGET_DIR", target_dir);
+        }
         let process_errors = |ce: &RustcError| {
-            if repair_bounds_help(ce.rendered.as_str(), src_path, fn_name) {
-                true
+            if repair_bounds_help(ce.rendered.as_str(), src_path, fn_name)? {
+                Ok(true)
             } else {
                 loosen_bounds(ce.rendered.as_str(), src_path, fn_name)
             }
         };
-        match repair_iteration_project(&mut compile_cmd, src_path, &process_errors, true, Some(50))
-        {
-            RepairResult {
-                success: true,
-                repair_count,
-                ..
-            } => {
-                debug!("pre elision: {}", fs::read_to_string(&src_path).unwrap());
-                let elide_res = elide_lifetimes_annotations(src_path, fn_name);
-                callee_renamer(src_path, fn_name);
-                RepairResult {
-                    success: true,
-                    repair_count,
-                    has_non_elidible_lifetime: elide_res.annotations_left,
-                    has_struct_lt: elide_res.has_struct_lt,
-                }
-            }
-            result => result,
-        }
+        let RepairResult { repair_count, .. } =
+            repair_iteration_project(&mut compile_cmd, src_path, &process_errors, true, Some(50))?;
+        debug!("pre elision: {}", fs::read_to_string(&src_path)?);
+        let elide_res = elide_lifetimes_annotations(src_path, fn_name)?;
+        callee_renamer(src_path, fn_name)?;
+        Ok(RepairResult {
+            repair_count,
+            has_non_elidible_lifetime: elide_res.annotations_left,
+            has_struct_lt: elide_res.has_struct_lt,
+        })
     }
 
-    fn repair_file(&self, file_name: &str, new_file_name: &str) -> RepairResult {
+    fn repair_file(&self, file_name: &str, new_file_name: &str) -> Result<RepairResult, Error> {
         repair_lifetime_simple::Repairer {}.repair_file(file_name, new_file_name)
     }
 
-    fn repair_function(&self, file_name: &str, new_file_name: &str, fn_name: &str) -> RepairResult {
-        fs::copy(file_name, &new_file_name).unwrap();
-        annotate_tight_named_lifetime(&new_file_name, fn_name);
+    fn repair_function(
+        &self,
+        file_name: &str,
+        new_file_name: &str,
+        fn_name: &str,
+    ) -> Result<RepairResult, Error> {
+        if file_name != new_file_name {
+            fs::copy(file_name, &new_file_name)?;
+        }
+        annotate_tight_named_lifetime(&new_file_name, fn_name)?;
         //println!("annotated: {}", fs::read_to_string(&new_file_name).unwrap());
-        let args: Vec<&str> = vec!["--error-format=json"];
+        let args: Vec<&str> = vec!["--error-format=json", "--edition=2021"];
 
         let mut compile_cmd = compile_file(&new_file_name, &args);
 
         let process_errors = |stderr: &str| {
-            if repair_bounds_help(stderr, new_file_name, fn_name) {
-                true
+            if repair_bounds_help(stderr, new_file_name, fn_name)? {
+                Ok(true)
             } else {
                 loosen_bounds(stderr, new_file_name, fn_name)
             }
         };
 
-        match repair_iteration(&mut compile_cmd, &process_errors, true, Some(50)) {
-            RepairResult {
-                success: true,
-                repair_count,
-                ..
-            } => {
-                // println!("repaired: {}", fs::read_to_string(&new_file_name).unwrap());
-                let elide_res = elide_lifetimes_annotations(new_file_name, fn_name);
-                RepairResult {
-                    success: true,
-                    repair_count,
-                    has_non_elidible_lifetime: elide_res.annotations_left,
-                    has_struct_lt: elide_res.has_struct_lt,
-                }
-            }
-            result => result,
-        }
+        let RepairResult { repair_count, .. } =
+            repair_iteration(&mut compile_cmd, &process_errors, true, Some(50))?;
+        // println!("repaired: {}", fs::read_to_string(&new_file_name).unwrap());
+        let elide_res = elide_lifetimes_annotations(new_file_name, fn_name)?;
+        Ok(RepairResult {
+            repair_count,
+            has_non_elidible_lifetime: elide_res.annotations_left,
+            has_struct_lt: elide_res.has_struct_lt,
+        })
     }
 }
 
@@ -125,14 +127,13 @@ impl VisitMut for TightLifetimeAnnotatorFnArgHelper {
 }
 
 struct TightLifetimeAnnotator<'a> {
-    fn_name: &'a str,
+    fn_id: &'a FnId,
     success: bool,
 }
 
 impl VisitMut for TightLifetimeAnnotator<'_> {
     fn visit_item_fn_mut(&mut self, i: &mut syn::ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => match (&mut i.sig.inputs, &mut i.sig.generics, &mut i.sig.output) {
                 (inputs, _, _) if inputs.len() == 0 => self.success = true,
@@ -169,31 +170,37 @@ impl VisitMut for TightLifetimeAnnotator<'_> {
                 }
             },
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 }
 
-pub fn annotate_tight_named_lifetime(new_file_name: &str, fn_name: &str) -> bool {
-    let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-    let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-        .map_err(|e| format!("{:?}", e))
-        .unwrap();
+pub fn annotate_tight_named_lifetime(new_file_name: &str, fn_name: &str) -> Result<bool, Error> {
+    let file_content = fs::read_to_string(&new_file_name)?;
+    let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+    let fn_id = match resolve_fn(&file, fn_name) {
+        Ok(Some(fn_id)) => fn_id,
+        _ => return Ok(false),
+    };
     let mut visit = TightLifetimeAnnotator {
-        fn_name,
+        fn_id: &fn_id,
         success: false,
     };
     visit.visit_file_mut(&mut file);
     let file = file.into_token_stream().to_string();
     match visit.success {
         true => {
-            fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
-            true
+            fs::write(
+                new_file_name.to_string(),
+                splice(&file_content, &format_source(&file)),
+            )?;
+            Ok(true)
         }
-        false => false,
+        false => Ok(false),
     }
 }
 
 struct BoundsLoosener<'a> {
-    fn_name: &'a str,
+    fn_id: &'a FnId,
     arg_name: &'a str,
     success: bool,
 }
@@ -226,8 +233,7 @@ impl VisitMut for ArgBoundLoosener<'_> {
 
 impl VisitMut for BoundsLoosener<'_> {
     fn visit_item_fn_mut(&mut self, i: &mut syn::ItemFn) {
-        let id = i.sig.ident.to_string();
-        match id == self.fn_name.to_string() {
+        match self.fn_id.is(&i.sig.ident) {
             false => (),
             true => {
                 let mut lt_count = 0;
@@ -261,10 +267,15 @@ impl VisitMut for BoundsLoosener<'_> {
                 }
             }
         }
+        syn::visit_mut::visit_item_fn_mut(self, i);
     }
 }
 
-pub fn loosen_bounds(stderr: &str, new_file_name: &str, fn_name: &str) -> bool {
+/// A borrow error on a reference argument, e.g. `error ... `*x``
+static REFERENCE_ERROR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"error.*`(?P<ref_full>\**(?P<ref>[a-z]+))`").unwrap());
+
+pub fn loosen_bounds(stderr: &str, new_file_name: &str, fn_name: &str) -> Result<bool, Error> {
     let deserializer = serde_json::Deserializer::from_str(stderr);
     let stream = deserializer.into_iter::<RustcError>();
     let mut helped = false;
@@ -273,17 +284,18 @@ pub fn loosen_bounds(stderr: &str, new_file_name: &str, fn_name: &str) -> bool {
             Ok(item) => item.rendered,
             Err(_) => stderr.to_string(),
         };
-        let reference_re = Regex::new(r"error.*`(?P<ref_full>\**(?P<ref>[a-z]+))`").unwrap();
-        let error_lines = reference_re.captures_iter(rendered.as_str());
+        let error_lines = REFERENCE_ERROR.captures_iter(rendered.as_str());
 
         for captured in error_lines {
             //println!("ref_full: {}, ref: {}", &captured["ref_full"], &captured["ref"]);
-            let file_content: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
-            let mut file = syn::parse_str::<syn::File>(file_content.as_str())
-                .map_err(|e| format!("{:?}", e))
-                .unwrap();
+            let file_content = fs::read_to_string(&new_file_name)?;
+            let mut file = syn::parse_str::<syn::File>(file_content.as_str())?;
+            let fn_id = match resolve_fn(&file, fn_name) {
+                Ok(Some(fn_id)) => fn_id,
+                _ => continue,
+            };
             let mut visit = BoundsLoosener {
-                fn_name,
+                fn_id: &fn_id,
                 arg_name: &captured["ref"],
                 success: false,
             };
@@ -291,12 +303,15 @@ pub fn loosen_bounds(stderr: &str, new_file_name: &str, fn_name: &str) -> bool {
             let file = file.into_token_stream().to_string();
             match visit.success {
                 true => {
-                    fs::write(new_file_name.to_string(), format_source(&file)).unwrap();
+                    fs::write(
+                        new_file_name.to_string(),
+                        splice(&file_content, &format_source(&file)),
+                    )?;
                     helped = true
                 }
                 false => (),
             }
         }
     }
-    helped
+    Ok(helped)
 }
@@ -1,6 +1,7 @@
 use crate::common::{repair_iteration, RepairResult, RepairSystem};
 
 use rem_utils::compile_file;
+use rem_utils::error::Error;
 use std::collections::HashSet;
 use std::fs;
 
@@ -15,19 +16,20 @@ impl RepairSystem for Repairer {
         &self,
         _src_path: &str,
         _manifest_path: &str,
+        _target_dir: Option<&str>,
         _fn_name: &str,
-    ) -> RepairResult {
-        RepairResult {
-            success: false,
-            repair_count: 0,
-            has_non_elidible_lifetime: false,
-            has_struct_lt: false,
-        }
+    ) -> Result<RepairResult, Error> {
+        Err(Error::Other(format!(
+            "{} does not repair projects",
+            self.name()
+        )))
     }
 
-    fn repair_file(&self, file_name: &str, new_file_name: &str) -> RepairResult {
-        fs::copy(file_name, &new_file_name).unwrap();
-        let args = vec!["--error-format=json"];
+    fn repair_file(&self, file_name: &str, new_file_name: &str) -> Result<RepairResult, Error> {
+        if file_name != new_file_name {
+            fs::copy(file_name, &new_file_name)?;
+        }
+        let args = vec!["--error-format=json", "--edition=2021"];
 
         let mut compile_cmd = compile_file(&new_file_name, &args);
 
@@ -37,23 +39,28 @@ impl RepairSystem for Repairer {
                 &HashSet::new(),
                 rustfix::Filter::Everything,
             )
-            .expect("rustfix failed to run on error json");
+            .map_err(|e| Error::Other(format!("rustfix failed to run on error json: {}", e)))?;
 
             if suggestions.len() == 0 {
-                return false;
+                return Ok(false);
             }
 
-            let code: String = fs::read_to_string(&new_file_name).unwrap().parse().unwrap();
+            let code = fs::read_to_string(&new_file_name)?;
             let fixed = rustfix::apply_suggestions(&code, &suggestions)
-                .expect("could not apply suggestions");
-            fs::write(new_file_name.to_string(), fixed).unwrap();
-            true
+                .map_err(|e| Error::Other(format!("could not apply suggestions: {}", e)))?;
+            fs::write(new_file_name.to_string(), fixed)?;
+            Ok(true)
         };
 
         repair_iteration(&mut compile_cmd, &process_errors, true, None)
     }
 
-    fn repair_function(&self, file_name: &str, new_file_name: &str, _: &str) -> RepairResult {
+    fn repair_function(
+        &self,
+        file_name: &str,
+        new_file_name: &str,
+        _: &str,
+    ) -> Result<RepairResult, Error> {
         self.repair_file(file_name, new_file_name)
     }
 }
@@ -1,7 +1,4 @@
 use std::collections::HashMap;
-use std::fs;
-use std::fs::OpenOptions;
-use std::io::Write;
 
 use syn::visit::Visit;
 use syn::{Expr, ExprPath, ItemFn, Path};
@@ -12,14 +9,17 @@ use crate::labelling::Label;
 /// Annotations of an AST
 pub type Annotations<'a> = HashMap<&'a dyn ASTKey, Label>;
 
-/// A pair of an AST and its annotations
-pub type Annotated<'a, T> = (Annotations<'a>, T);
+/// The variables labels were made for, with their spans: the other way
+/// around from `Annotations`, for the labels of bindings
+pub type Bindings = HashMap<Label, syn::Ident>;
 
-pub const LOOKUP_FILE: &str = "/tmp/annotation_rev_lookup";
+/// An AST, its annotations and the variables of their labels
+pub type Annotated<'a, T> = (Annotations<'a>, T, Bindings);
 
 /// Internal helper struct to annotate an AST
 struct ASTAnnotator<'a> {
     annotations: Annotations<'a>,
+    bindings: Bindings,
     next_label: Label,
     env: crate::labelling::ScopedContext<syn::Ident, Label>,
 }
@@ -29,23 +29,28 @@ impl<'a> ASTAnnotator<'a> {
         let map = HashMap::new();
         let label = Label::new();
         let context = Default::default();
-        fs::write(LOOKUP_FILE, "").unwrap();
         ASTAnnotator {
             annotations: map,
+            bindings: HashMap::new(),
             next_label: label,
             env: context,
         }
     }
 
-    // return annotations
-    pub fn annotations(self) -> HashMap<&'a dyn ASTKey, Label> {
-        self.annotations
+    // return annotations and the variables of their labels
+    pub fn annotations(self) -> (HashMap<&'a dyn ASTKey, Label>, Bindings) {
+        (self.annotations, self.bindings)
     }
 
     fn add_binding(&mut self, var: &'a syn::Ident, value: Label) {
         self.env.add_binding(var.clone(), value)
     }
 
+    // remember which variable `label` was made for
+    fn add_reverse_binding(&mut self, label: Label, var: syn::Ident) {
+        self.bindings.insert(label, var);
+    }
+
     // check if Ident, if not look up expr in AST map
     pub fn lookup_expr(&mut self, expr: &'a syn::Expr) -> Option<Label> {
         if let Expr::Path(syn::ExprPath {
@@ -87,8 +92,13 @@ impl<'a> syn::visit::Visit<'a> for ASTAnnotator<'a> {
     fn visit_item_fn(&mut self, f: &'a syn::ItemFn) {
         for arg in f.sig.inputs.iter() {
             match arg {
-                // item functions are standalone functions - we should never see a self
-                syn::FnArg::Receiver(_) => unreachable!(),
+                // a method parsed as a function: `self` is bound like any input
+                syn::FnArg::Receiver(r) => {
+                    let value = self.new_label();
+                    self.annotations.insert(r, value);
+                    self.add_reverse_binding(value, syn::Ident::from(r.self_token));
+                    self.env.add_binding(syn::Ident::from(r.self_token), value)
+                }
                 syn::FnArg::Typed(syn::PatType {
                     pat:
                         box syn::Pat::Ident(syn::PatIdent {
@@ -100,13 +110,7 @@ impl<'a> syn::visit::Visit<'a> for ASTAnnotator<'a> {
                 }) => {
                     let value = self.new_label();
                     self.annotations.insert(ident, value);
-                    // println!("{} -> {}", value, ident);
-                    let mut file = OpenOptions::new()
-                        .write(true)
-                        .append(true)
-                        .open(LOOKUP_FILE)
-                        .unwrap();
-                    writeln!(file, "{} -> {}", value, ident).unwrap();
+                    self.add_reverse_binding(value, ident.clone());
                     self.add_binding(ident, value)
                 }
                 _ => (),
@@ -138,13 +142,7 @@ impl<'a> syn::visit::Visit<'a> for ASTAnnotator<'a> {
                 // bind LHS identifier with new label
                 self.add_binding(ident, label);
                 self.annotations.insert(ident, label);
-                // println!("{} -> {}", label, ident);
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .append(true)
-                    .open(LOOKUP_FILE)
-                    .unwrap();
-                writeln!(file, "{} -> {}", label, ident).unwrap();
+                self.add_reverse_binding(label, ident.clone());
                 self.annotations.insert(&i.pat, label);
             }
             // Case of the form `let lhs = rhs`
@@ -156,13 +154,7 @@ impl<'a> syn::visit::Visit<'a> for ASTAnnotator<'a> {
             }) => {
                 self.add_binding(ident, label);
                 self.annotations.insert(ident, label);
-                // println!("{} -> {}", label, ident);
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .append(true)
-                    .open(LOOKUP_FILE)
-                    .unwrap();
-                writeln!(file, "{} -> {}", label, ident).unwrap();
+                self.add_reverse_binding(label, ident.clone());
                 self.annotations.insert(&i.pat, label);
             }
             _lb => {
@@ -217,5 +209,27 @@ pub fn annotate_ast<'a>(ast: &'a ItemFn) -> Annotated<'a, &'a ItemFn> {
 
     ast_annotation.visit_item_fn(ast);
 
-    (ast_annotation.annotations(), ast)
+    let (annotations, bindings) = ast_annotation.annotations();
+    (annotations, ast, bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_map_labels_back() {
+        let f: ItemFn = syn::parse_str("fn f(a: i32) { let b = a; let c: i32 = b; }").unwrap();
+        let g: ItemFn = syn::parse_str("fn g(x: &i32) { let y = x; }").unwrap();
+        let (annotations, _, bindings) = annotate_ast(&f);
+        // annotating another function does not touch the bindings of the first
+        let (_, _, other) = annotate_ast(&g);
+        let mut names: Vec<String> = bindings.values().map(|ident| ident.to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b", "c"]);
+        for (label, ident) in bindings.iter() {
+            assert_eq!(annotations.get(&(ident as &dyn ASTKey)), Some(label));
+        }
+        assert_eq!(other.len(), 2);
+    }
 }
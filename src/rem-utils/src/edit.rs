@@ -0,0 +1,587 @@
+use log::debug;
+use proc_macro2::{Delimiter, Group, LineColumn, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::collections::HashMap;
+use std::str::FromStr;
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item, TraitItem};
+
+use crate::error::Error;
+
+/// Replace the bytes `start..end` of a source with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Byte offsets of the `proc_macro2` line/column positions in a source.
+pub struct Offsets<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Offsets<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Offsets {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn offset(&self, lc: LineColumn) -> usize {
+        let start = match self.line_starts.get(lc.line - 1) {
+            Some(start) => *start,
+            None => return self.source.len(),
+        };
+        self.source[start..]
+            .char_indices()
+            .nth(lc.column)
+            .map(|(i, _)| start + i)
+            .unwrap_or(self.source.len())
+    }
+
+    pub fn range(&self, span: Span) -> (usize, usize) {
+        (self.offset(span.start()), self.offset(span.end()))
+    }
+
+    /// The whitespace the line containing `offset` starts with.
+    fn indent(&self, offset: usize) -> &'a str {
+        let start = self.source[..offset]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let line = &self.source[start..];
+        &line[..line.len() - line.trim_start().len()]
+    }
+}
+
+/// An item of a file, impl, trait or module, as far as diffing goes.
+struct Node {
+    key: String,
+    start: usize,
+    end: usize,
+    tokens: String,
+    stream: TokenStream,
+    /// Items of a container, with everything but the items as the header
+    children: Option<(String, Vec<Node>)>,
+}
+
+fn tokens<T: ToTokens>(t: &T) -> String {
+    without_trailing_commas(t.to_token_stream()).to_string()
+}
+
+/// `stream` without the trailing commas rustfmt adds or removes, except the
+/// one making `(a,)` a tuple.
+fn without_trailing_commas(stream: TokenStream) -> TokenStream {
+    let trees: Vec<TokenTree> = stream
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Group(g) => {
+                let mut trees: Vec<TokenTree> =
+                    without_trailing_commas(g.stream()).into_iter().collect();
+                let is_comma =
+                    |tree: &TokenTree| matches!(tree, TokenTree::Punct(p) if p.as_char() == ',');
+                let commas = trees.iter().filter(|tree| is_comma(tree)).count();
+                if trees.last().map_or(false, is_comma)
+                    && (g.delimiter() != Delimiter::Parenthesis || commas > 1)
+                {
+                    trees.pop();
+                }
+                let mut group = Group::new(g.delimiter(), trees.into_iter().collect());
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            tree => tree,
+        })
+        .collect();
+    trees.into_iter().collect()
+}
+
+fn node<T: ToTokens>(offsets: &Offsets, key: String, t: &T) -> Node {
+    let (start, end) = offsets.range(t.span());
+    let stream = t.to_token_stream();
+    Node {
+        key,
+        start,
+        end,
+        tokens: without_trailing_commas(stream.clone()).to_string(),
+        stream,
+        children: None,
+    }
+}
+
+fn item_node(offsets: &Offsets, item: &Item) -> Node {
+    match item {
+        Item::Fn(i) => node(offsets, format!("fn {}", i.sig.ident), item),
+        Item::Enum(i) => node(offsets, format!("enum {}", i.ident), item),
+        Item::Struct(i) => node(offsets, format!("struct {}", i.ident), item),
+        Item::Union(i) => node(offsets, format!("union {}", i.ident), item),
+        Item::Type(i) => node(offsets, format!("type {}", i.ident), item),
+        Item::Const(i) => node(offsets, format!("const {}", i.ident), item),
+        Item::Static(i) => node(offsets, format!("static {}", i.ident), item),
+        Item::Impl(i) => {
+            let mut header = i.clone();
+            header.items.clear();
+            let mut n = node(
+                offsets,
+                format!(
+                    "impl {} {} for {}",
+                    tokens(&i.generics),
+                    i.trait_
+                        .as_ref()
+                        .map(|(_, p, _)| tokens(p))
+                        .unwrap_or_default(),
+                    tokens(&i.self_ty)
+                ),
+                item,
+            );
+            let children = i.items.iter().map(|i| impl_item_node(offsets, i)).collect();
+            n.children = Some((tokens(&header), children));
+            n
+        }
+        Item::Trait(i) => {
+            let mut header = i.clone();
+            header.items.clear();
+            let mut n = node(offsets, format!("trait {}", i.ident), item);
+            let children = i
+                .items
+                .iter()
+                .map(|i| trait_item_node(offsets, i))
+                .collect();
+            n.children = Some((tokens(&header), children));
+            n
+        }
+        Item::Mod(i) => {
+            let mut n = node(offsets, format!("mod {}", i.ident), item);
+            if let Some((_, items)) = &i.content {
+                let mut header = i.clone();
+                if let Some((_, items)) = header.content.as_mut() {
+                    items.clear();
+                }
+                let children = items.iter().map(|i| item_node(offsets, i)).collect();
+                n.children = Some((tokens(&header), children));
+            }
+            n
+        }
+        _ => node(offsets, tokens(item), item),
+    }
+}
+
+fn impl_item_node(offsets: &Offsets, item: &ImplItem) -> Node {
+    match item {
+        ImplItem::Method(i) => node(offsets, format!("fn {}", i.sig.ident), item),
+        ImplItem::Const(i) => node(offsets, format!("const {}", i.ident), item),
+        ImplItem::Type(i) => node(offsets, format!("type {}", i.ident), item),
+        _ => node(offsets, tokens(item), item),
+    }
+}
+
+fn trait_item_node(offsets: &Offsets, item: &TraitItem) -> Node {
+    match item {
+        TraitItem::Method(i) => node(offsets, format!("fn {}", i.sig.ident), item),
+        TraitItem::Const(i) => node(offsets, format!("const {}", i.ident), item),
+        TraitItem::Type(i) => node(offsets, format!("type {}", i.ident), item),
+        _ => node(offsets, tokens(item), item),
+    }
+}
+
+/// Keys made unique by counting repeated ones, e.g. several `impl Foo`.
+fn keyed(nodes: &[Node]) -> Vec<String> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    nodes
+        .iter()
+        .map(|n| {
+            let count = seen.entry(n.key.as_str()).or_insert(0);
+            *count += 1;
+            format!("{}#{}", n.key, count)
+        })
+        .collect()
+}
+
+fn diff_nodes(
+    before: &Offsets,
+    after: &Offsets,
+    old: &[Node],
+    new: &[Node],
+    edits: &mut Vec<TextEdit>,
+) -> bool {
+    let old_keys = keyed(old);
+    let new_keys = keyed(new);
+    let old_by_key: HashMap<&String, &Node> = old_keys.iter().zip(old.iter()).collect();
+    let new_by_key: HashMap<&String, &Node> = new_keys.iter().zip(new.iter()).collect();
+
+    // items that are gone, with the whitespace before them
+    for (key, n) in old_keys.iter().zip(old.iter()) {
+        if !new_by_key.contains_key(key) {
+            let start = before.source[..n.start].trim_end().len();
+            edits.push(TextEdit {
+                start,
+                end: n.end,
+                text: String::new(),
+            });
+        }
+    }
+
+    let mut anchor: Option<&Node> = None;
+    let mut pending: Vec<&Node> = vec![];
+    for (key, n) in new_keys.iter().zip(new.iter()) {
+        let o = match old_by_key.get(key) {
+            Some(o) => *o,
+            None => {
+                pending.push(n);
+                continue;
+            }
+        };
+        if !pending.is_empty() {
+            insert(before, after, anchor, Some(o), &pending, edits);
+            pending.clear();
+        }
+        anchor = Some(o);
+        if o.tokens == n.tokens {
+            continue;
+        }
+        let mut inner = vec![];
+        match (&o.children, &n.children) {
+            (Some((old_header, old_children)), Some((new_header, new_children)))
+                if old_header == new_header
+                    && diff_nodes(before, after, old_children, new_children, &mut inner) =>
+            {
+                edits.append(&mut inner)
+            }
+            _ => edits.append(&mut splice_node(before, after, o, n)),
+        }
+    }
+    if !pending.is_empty() {
+        if anchor.is_none() {
+            // nothing to insert next to in this container
+            return false;
+        }
+        insert(before, after, anchor, None, &pending, edits);
+    }
+    true
+}
+
+/// Insert new items after `anchor`, or before `next` if they come first.
+fn insert(
+    before: &Offsets,
+    after: &Offsets,
+    anchor: Option<&Node>,
+    next: Option<&Node>,
+    items: &[&Node],
+    edits: &mut Vec<TextEdit>,
+) {
+    let text = |indent: &str| {
+        items
+            .iter()
+            .map(|n| after.source[n.start..n.end].to_string())
+            .collect::<Vec<_>>()
+            .join(format!("\n\n{}", indent).as_str())
+    };
+    match (anchor, next) {
+        (Some(anchor), _) => {
+            let indent = before.indent(anchor.start);
+            edits.push(TextEdit {
+                start: anchor.end,
+                end: anchor.end,
+                text: format!("\n\n{}{}", indent, text(indent)),
+            })
+        }
+        (None, Some(next)) => {
+            let indent = before.indent(next.start);
+            edits.push(TextEdit {
+                start: next.start,
+                end: next.start,
+                text: format!("{}\n\n{}", text(indent), indent),
+            })
+        }
+        (None, None) => (),
+    }
+}
+
+/// A token of an item with its bytes in the source. Tokens sharing bytes,
+/// e.g. the ones a doc comment stands for, are one leaf, and so are
+/// attributes so that `///` and `#[doc]` match.
+struct Leaf {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+fn push_leaf(out: &mut Vec<Leaf>, (start, end): (usize, usize), text: String) {
+    match out.last_mut() {
+        Some(last) if start < last.end => {
+            last.text = format!("{} {}", last.text, text);
+            last.end = last.end.max(end);
+        }
+        _ => out.push(Leaf { start, end, text }),
+    }
+}
+
+fn leaves(offsets: &Offsets, stream: TokenStream, out: &mut Vec<Leaf>) {
+    let mut iter = stream.into_iter().peekable();
+    while let Some(tt) = iter.next() {
+        match tt {
+            TokenTree::Punct(p) if p.as_char() == '#' => match iter.peek() {
+                Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket => {
+                    let range = (offsets.range(p.span()).0, offsets.range(g.span()).1);
+                    push_leaf(out, range, format!("# {}", g));
+                    iter.next();
+                }
+                _ => push_leaf(out, offsets.range(p.span()), p.to_string()),
+            },
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => {
+                        leaves(offsets, g.stream(), out);
+                        continue;
+                    }
+                };
+                push_leaf(out, offsets.range(g.span_open()), open.to_string());
+                leaves(offsets, g.stream(), out);
+                push_leaf(out, offsets.range(g.span_close()), close.to_string());
+            }
+            tt => push_leaf(out, offsets.range(tt.span()), tt.to_string()),
+        }
+    }
+}
+
+/// The edits turning the item `o` into `n`, token by token: the text between
+/// tokens that are kept, comments included, stays as it was. Falls back to
+/// replacing the whole item when splicing does not give back `n`.
+fn splice_node(before: &Offsets, after: &Offsets, o: &Node, n: &Node) -> Vec<TextEdit> {
+    let whole = vec![TextEdit {
+        start: o.start,
+        end: o.end,
+        text: after.source[n.start..n.end].to_string(),
+    }];
+    let (mut old, mut new) = (vec![], vec![]);
+    leaves(before, o.stream.clone(), &mut old);
+    leaves(after, n.stream.clone(), &mut new);
+    if old.is_empty() || new.is_empty() {
+        return whole;
+    }
+    let old_texts: Vec<&str> = old.iter().map(|l| l.text.as_str()).collect();
+    let new_texts: Vec<&str> = new.iter().map(|l| l.text.as_str()).collect();
+
+    let blank = |from: usize, to: usize| before.source[from..to].trim().is_empty();
+    let mut edits = vec![];
+    for op in capture_diff_slices(Algorithm::Patience, &old_texts, &new_texts) {
+        let (o_range, n_range) = (op.old_range(), op.new_range());
+        let (a, b, c, d) = (o_range.start, o_range.end, n_range.start, n_range.end);
+        let edit = match op {
+            DiffOp::Equal { .. } => continue,
+            // between kept tokens and plain whitespace, the spacing is the new one
+            _ if a > 0
+                && c > 0
+                && b < old.len()
+                && d < new.len()
+                && blank(old[a - 1].end, old[a].start)
+                && blank(old[b - 1].end, old[b].start) =>
+            {
+                TextEdit {
+                    start: old[a - 1].end,
+                    end: old[b].start,
+                    text: after.source[new[c - 1].end..new[d].start].to_string(),
+                }
+            }
+            // keep the text around the replaced tokens
+            DiffOp::Replace { .. } => TextEdit {
+                start: old[o_range.start].start,
+                end: old[o_range.end - 1].end,
+                text: after.source[new[n_range.start].start..new[n_range.end - 1].end].to_string(),
+            },
+            // the deleted tokens go with the text before them
+            DiffOp::Delete { .. } => match o_range.start {
+                0 => TextEdit {
+                    start: old[0].start,
+                    end: old.get(o_range.end).map(|l| l.start).unwrap_or(o.end),
+                    text: String::new(),
+                },
+                i => TextEdit {
+                    start: old[i - 1].end,
+                    end: old[o_range.end - 1].end,
+                    text: String::new(),
+                },
+            },
+            // inserted tokens take the place of plain whitespace, or come
+            // with the text after them
+            DiffOp::Insert { .. } => match old.get(o_range.start) {
+                Some(next)
+                    if o_range.start > 0
+                        && before.source[old[o_range.start - 1].end..next.start]
+                            .trim()
+                            .is_empty() =>
+                {
+                    TextEdit {
+                        start: old[o_range.start - 1].end,
+                        end: next.start,
+                        text: after.source[new[n_range.start - 1].end..new[n_range.end].start]
+                            .to_string(),
+                    }
+                }
+                Some(next) => TextEdit {
+                    start: next.start,
+                    end: next.start,
+                    text: after.source[new[n_range.start].start
+                        ..new.get(n_range.end).map(|l| l.start).unwrap_or(n.end)]
+                        .to_string(),
+                },
+                None => TextEdit {
+                    start: o.end,
+                    end: o.end,
+                    text: after.source[new[n_range.start - 1].end..new[n_range.end - 1].end]
+                        .to_string(),
+                },
+            },
+        };
+        edits.push(edit);
+    }
+
+    // spliced tokens may run into their neighbours, or spans may be off
+    let mut spliced = apply_edits(&before.source[..o.end], &edits);
+    spliced.drain(..o.start);
+    let lexed = |s: &str| TokenStream::from_str(s).map(|t| t.to_string()).ok();
+    match lexed(spliced.as_str()) {
+        Some(tokens) if Some(&tokens) == lexed(&after.source[n.start..n.end]).as_ref() => edits,
+        _ => {
+            debug!("could not splice {}, replacing it", n.key);
+            whole
+        }
+    }
+}
+
+/// The edits turning `before` into `after`, item by item: items that are
+/// unchanged up to formatting are left alone, so are the comments and
+/// formatting around them, and changed items are spliced token by token.
+/// Edits are sorted and do not overlap.
+pub fn item_edits(before: &str, after: &str) -> Result<Vec<TextEdit>, Error> {
+    let old_file = syn::parse_str::<syn::File>(before)?;
+    let new_file = syn::parse_str::<syn::File>(after)?;
+    let (old_offsets, new_offsets) = (Offsets::new(before), Offsets::new(after));
+    let old: Vec<Node> = old_file
+        .items
+        .iter()
+        .map(|i| item_node(&old_offsets, i))
+        .collect();
+    let new: Vec<Node> = new_file
+        .items
+        .iter()
+        .map(|i| item_node(&new_offsets, i))
+        .collect();
+
+    let mut edits = vec![];
+    if old_file.attrs != new_file.attrs
+        || !diff_nodes(&old_offsets, &new_offsets, &old, &new, &mut edits)
+    {
+        edits = vec![TextEdit {
+            start: 0,
+            end: before.len(),
+            text: after.to_string(),
+        }];
+    }
+    edits.sort_by_key(|e| (e.start, e.end));
+    Ok(edits)
+}
+
+pub fn apply_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut result = String::new();
+    let mut last = 0;
+    for edit in edits {
+        result.push_str(&source[last..edit.start]);
+        result.push_str(edit.text.as_str());
+        last = edit.end;
+    }
+    result.push_str(&source[last..]);
+    result
+}
+
+/// `after`, with the text of `before` wherever they have the same tokens:
+/// for stages that print a whole `syn::File`, which loses comments and
+/// formatting.
+pub fn splice(before: &str, after: &str) -> String {
+    match item_edits(before, after) {
+        Ok(edits) => apply_edits(before, &edits),
+        Err(e) => {
+            debug!("could not splice: {}", e);
+            after.to_string()
+        }
+    }
+}
+
+/// The edits as a unified diff of `file_name`.
+pub fn unified_diff(file_name: &str, source: &str, edits: &[TextEdit]) -> String {
+    let edited = apply_edits(source, edits);
+    similar::TextDiff::from_lines(source, edited.as_str())
+        .unified_diff()
+        .header(
+            format!("a/{}", file_name).as_str(),
+            format!("b/{}", file_name).as_str(),
+        )
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_edits_keep_untouched_items() {
+        let before = "// keep me\nfn  a() { 1 ; }\n\nfn b() {}\n";
+        let after = "fn a() {\n    1;\n}\nfn b() {\n    c()\n}\nfn c() {}\n";
+        let edits = item_edits(before, after).unwrap();
+        assert_eq!(
+            apply_edits(before, &edits),
+            "// keep me\nfn  a() { 1 ; }\n\nfn b() {\n    c()\n}\n\nfn c() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_item_edits_go_into_impls() {
+        let before = "impl A {\n    // keep me\n    fn a() {}\n}\n";
+        let after = "impl A {\n    fn a() {\n        b()\n    }\n    fn b() {}\n}\n";
+        let edits = item_edits(before, after).unwrap();
+        assert_eq!(
+            apply_edits(before, &edits),
+            "impl A {\n    // keep me\n    fn a() {\n        b()\n    }\n\n    fn b() {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_item_edits_ignore_trailing_commas() {
+        let before = "struct A { n: i32 }\nfn a() -> (i32,) { (1,) }\n";
+        let after = "struct A {\n    n: i32,\n}\nfn a() -> (i32,) {\n    (1)\n}\n";
+        let edits = item_edits(before, after).unwrap();
+        assert_eq!(
+            apply_edits(before, &edits),
+            "struct A { n: i32 }\nfn a() -> (i32,) { (1) }\n"
+        );
+    }
+
+    #[test]
+    fn test_splice_takes_new_spacing() {
+        let before = "fn a(x: &'a i32) {\n    let y = x * 2; // keep\n}\n";
+        let after = "fn a(x: &i32) {\n    let y = b(x);\n}\n";
+        assert_eq!(
+            splice(before, after),
+            "fn a(x: &i32) {\n    let y = b(x); // keep\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_splice_keeps_comments_in_changed_items() {
+        let before = "/// docs\nfn a() {\n    // one\n    let x = 1;\n    let y = x; // two\n}\n";
+        let after = "#[doc = \" docs\"]\nfn a() {\n    let x = 1;\n    let y = b(x);\n}\n";
+        assert_eq!(
+            splice(before, after),
+            "/// docs\nfn a() {\n    // one\n    let x = 1;\n    let y = b(x); // two\n}\n"
+        );
+    }
+}
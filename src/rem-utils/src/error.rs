@@ -1,3 +1,5 @@
+use proc_macro2::{LineColumn, Span};
+use std::fmt::Display;
 use std::string::FromUtf8Error;
 
 #[derive(Debug)]
@@ -5,8 +7,87 @@ pub enum Error {
     IO(std::io::Error),
     StringFormat(FromUtf8Error),
     TypeError(crate::typ::Error),
+    /// The input is not valid Rust
+    Parse(syn::Error),
+    /// No function with that name in the input
+    CallerNotFound(String),
+    CalleeNotFound(String),
+    /// Several functions go by that name, see `resolve::FnSpec`
+    AmbiguousFn(String),
+    /// The selection does not make a valid extraction
+    InvalidSelection(String),
+    /// The input uses something REM does not handle (yet)
+    Unsupported {
+        construct: String,
+        start: LineColumn,
+        end: LineColumn,
+    },
+    /// The repairer gave up before the code compiled
+    RepairExhausted {
+        repair_count: i32,
+    },
     Other(String),
 }
+
+impl Error {
+    pub fn unsupported(construct: &str, span: Span) -> Self {
+        Error::Unsupported {
+            construct: construct.into(),
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IO(e) => write!(f, "{}", e),
+            Error::StringFormat(e) => write!(f, "{}", e),
+            Error::TypeError(e) => write!(f, "{:?}", e),
+            Error::Parse(e) => {
+                let start = e.span().start();
+                write!(
+                    f,
+                    "could not parse input at {}:{}: {}",
+                    start.line,
+                    start.column + 1,
+                    e
+                )
+            }
+            Error::CallerNotFound(name) => write!(f, "caller `{}` not found", name),
+            Error::CalleeNotFound(name) => write!(f, "callee `{}` not found", name),
+            Error::AmbiguousFn(name) => write!(
+                f,
+                "`{}` names several functions, qualify it as `Type::{}` or `{}@LINE`",
+                name, name, name
+            ),
+            Error::InvalidSelection(reason) => write!(f, "invalid selection: {}", reason),
+            Error::Unsupported {
+                construct,
+                start,
+                end,
+            } => write!(
+                f,
+                "unsupported {} at {}:{}-{}:{}",
+                construct,
+                start.line,
+                start.column + 1,
+                end.line,
+                end.column + 1
+            ),
+            Error::RepairExhausted { repair_count } => {
+                write!(
+                    f,
+                    "could not repair lifetimes after {} attempts",
+                    repair_count
+                )
+            }
+            Error::Other(st) => write!(f, "{}", st),
+        }
+    }
+}
+
 impl From<Error> for String {
     fn from(val: Error) -> Self {
         match val {
@@ -14,10 +95,17 @@ impl From<Error> for String {
             Error::TypeError(e) => format!("TypeError({:?})", e),
             Error::StringFormat(f) => format!("{:?}", f),
             Error::Other(st) => format!("Other Error: {}", st),
+            e => e.to_string(),
         }
     }
 }
 
+impl From<syn::Error> for Error {
+    fn from(v: syn::Error) -> Self {
+        Error::Parse(v)
+    }
+}
+
 impl From<crate::typ::Error> for Error {
     fn from(val: crate::typ::Error) -> Self {
         Error::TypeError(val)
@@ -113,6 +113,11 @@ impl Display for SymbolicFileSystem {
 }
 
 impl SymbolicFileSystem {
+    /// An empty file system, to be filled with `write`
+    pub fn new() -> Self {
+        SymbolicFileSystem(Rc::new(RefCell::new(HashMap::new())))
+    }
+
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
         let mut map = HashMap::new();
         let mut to_visit: Vec<PathBuf> = {
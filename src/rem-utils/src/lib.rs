@@ -12,13 +12,16 @@ pub extern crate rustc_span;
 pub extern crate string_cache;
 
 pub mod annotation;
+pub mod edit;
 pub mod error;
 pub mod filesystem;
 pub mod formatter;
 pub mod labelling;
 pub mod location;
 pub mod macros;
+pub mod mutability;
 pub mod parser;
+pub mod resolve;
 pub mod typ;
 pub mod wrappers;
 
@@ -71,23 +74,24 @@ pub fn build_project(manifest_path: &str, cargo_args: &Vec<&str>) -> Command {
 
 pub struct FindCallee<'a> {
     pub found: bool,
-    pub callee_fn_name: &'a str,
+    pub callee: &'a resolve::CallSites,
 }
 
 impl VisitMut for FindCallee<'_> {
     fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
-        let callee = i.func.as_ref().into_token_stream().to_string();
-        debug!("looking at callee: {}", callee);
-        match callee.contains(self.callee_fn_name) {
+        debug!(
+            "looking at callee: {}",
+            i.func.as_ref().into_token_stream().to_string()
+        );
+        match self.callee.is_call(&i.func) {
             true => self.found = true,
             false => syn::visit_mut::visit_expr_call_mut(self, i),
         }
     }
 
     fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
-        let callee = i.method.clone().into_token_stream().to_string();
-        debug!("looking at callee: {}", callee);
-        match callee.contains(self.callee_fn_name) {
+        debug!("looking at callee: {}", i.method);
+        match self.callee.is_method_call(i) {
             true => self.found = true,
             false => syn::visit_mut::visit_expr_method_call_mut(self, i),
         }
@@ -248,7 +252,7 @@ pub fn find_caller(
         caller_fn_name: caller_name,
         callee_finder: &mut FindCallee {
             found: false,
-            callee_fn_name: callee_name,
+            callee: &resolve::CallSites::named(callee_name),
         },
         found: false,
         caller: String::new(),
@@ -278,7 +282,7 @@ pub fn find_caller(
 pub fn format_source(src: &str) -> String {
     let rustfmt = {
         let rustfmt_path = format!("{}/bin/rustfmt", cargo_home().unwrap().to_string_lossy());
-        println!("{}", &rustfmt_path);
+        debug!("formatting with {}", &rustfmt_path);
         let mut proc = Command::new(&rustfmt_path)
             .arg("--edition=2021")
             .stdin(Stdio::piped())
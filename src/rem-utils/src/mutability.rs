@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use proc_macro2::Ident;
+use syn::visit::Visit;
+use syn::{ExprMethodCall, FnArg, Pat, Signature, Type};
+
+use crate::error::Error;
+use crate::filesystem::FileSystem;
+
+/// Methods of the standard library taking `&mut self`, as far as the common
+/// collections, options, iterators and I/O go. Names also common for methods
+/// taking `&self` or `self`, e.g. `replace` of `str`, `take` and `swap` of
+/// `Cell`, `reverse` of `Ordering` or `rotate_left` of integers, are left
+/// out, since the borrower only sees the name.
+const STD_MUT_METHODS: &[&str] = &[
+    // Vec, VecDeque, String, slices and maps
+    "push",
+    "push_str",
+    "push_back",
+    "push_front",
+    "pop",
+    "pop_back",
+    "pop_front",
+    "insert",
+    "remove",
+    "swap_remove",
+    "truncate",
+    "clear",
+    "extend",
+    "extend_from_slice",
+    "append",
+    "drain",
+    "retain",
+    "retain_mut",
+    "dedup",
+    "dedup_by",
+    "dedup_by_key",
+    "resize",
+    "resize_with",
+    "split_off",
+    "reserve",
+    "shrink_to_fit",
+    "sort",
+    "sort_by",
+    "sort_by_key",
+    "sort_unstable",
+    "sort_unstable_by",
+    "sort_unstable_by_key",
+    "fill",
+    "iter_mut",
+    "values_mut",
+    "get_mut",
+    "first_mut",
+    "last_mut",
+    "split_at_mut",
+    "chunks_mut",
+    "as_mut",
+    "as_mut_slice",
+    "make_contiguous",
+    // Option
+    "get_or_insert",
+    "get_or_insert_with",
+    // iterators
+    "next_back",
+    "by_ref",
+    // I/O
+    "read_to_string",
+    "read_to_end",
+    "read_exact",
+    "write_all",
+    "write_fmt",
+    "flush",
+    "seek",
+];
+
+/// The methods taking `&mut self`, by name, so that the borrower can tell
+/// `v.push(x)` from `v.len()` without knowing the type of `v`. A name that
+/// some method of the program takes `&self` or `self` with is ambiguous, and
+/// none of its calls is taken to mutate the receiver.
+#[derive(Debug, Clone, Default)]
+pub struct MutMethods {
+    names: HashSet<String>,
+    /// of the methods of the program not taking `&mut self`
+    shared: HashSet<String>,
+}
+
+struct MutReceivers<'a> {
+    names: &'a mut HashSet<String>,
+    shared: &'a mut HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for MutReceivers<'_> {
+    fn visit_signature(&mut self, i: &'ast Signature) {
+        let takes_mut_self = match i.inputs.first() {
+            Some(FnArg::Receiver(r)) => r.reference.is_some() && r.mutability.is_some(),
+            // `self: &mut Self`
+            Some(FnArg::Typed(t)) => match (t.pat.as_ref(), t.ty.as_ref()) {
+                (Pat::Ident(p), Type::Reference(r)) => p.ident == "self" && r.mutability.is_some(),
+                _ => false,
+            },
+            None => false,
+        };
+        let takes_self = match i.inputs.first() {
+            Some(FnArg::Receiver(_)) => true,
+            Some(FnArg::Typed(t)) => {
+                matches!(t.pat.as_ref(), Pat::Ident(p) if p.ident == "self")
+            }
+            None => false,
+        };
+        match (takes_mut_self, takes_self) {
+            (true, _) => self.names.insert(i.ident.to_string()),
+            (false, true) => self.shared.insert(i.ident.to_string()),
+            (false, false) => false,
+        };
+    }
+}
+
+/// The sources of the crate of `manifest_path` that parse.
+pub fn crate_files(manifest_path: &str) -> Result<Vec<syn::File>, Error> {
+    let root = Path::new(manifest_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("src");
+    let mut files = vec![];
+    let mut to_visit: Vec<PathBuf> = vec![root];
+    while let Some(path) = to_visit.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                to_visit.push(entry?.path());
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            match syn::parse_file(&fs::read_to_string(&path)?) {
+                Ok(file) => files.push(file),
+                Err(e) => debug!("skipping {}: {}", path.display(), e),
+            }
+        }
+    }
+    Ok(files)
+}
+
+impl MutMethods {
+    /// The methods of the standard library taking `&mut self`.
+    pub fn std() -> Self {
+        MutMethods {
+            names: STD_MUT_METHODS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            shared: HashSet::new(),
+        }
+    }
+
+    pub fn contains(&self, method: &Ident) -> bool {
+        let method = method.to_string();
+        self.names.contains(&method) && !self.shared.contains(&method)
+    }
+
+    /// Add the methods defined in `file` taking `&mut self`, and the names of
+    /// those taking `&self` or `self`, which are ambiguous.
+    pub fn add_file(&mut self, file: &syn::File) {
+        MutReceivers {
+            names: &mut self.names,
+            shared: &mut self.shared,
+        }
+        .visit_file(file)
+    }
+
+    /// Add the methods defined in the sources of the crate of `manifest_path`
+    /// taking `&mut self`. Sources that do not parse are skipped.
+    pub fn add_crate(&mut self, manifest_path: &str) -> Result<(), Error> {
+        crate_files(manifest_path)?
+            .iter()
+            .for_each(|file| self.add_file(file));
+        Ok(())
+    }
+
+    /// Add the methods of calls needing `&mut self`, one call per line, as
+    /// the IntelliJ plugin lists them.
+    pub fn add_calls(&mut self, content: &str) {
+        content
+            .lines()
+            .filter_map(|call| syn::parse_str::<ExprMethodCall>(call).ok())
+            .for_each(|call| {
+                self.names.insert(call.method.to_string());
+            })
+    }
+
+    /// Same as `add_calls` on the file `path`, if there is one.
+    pub fn add_calls_file<S: FileSystem>(&mut self, fs: &S, path: &str) -> Result<(), Error>
+    where
+        Error: From<S::FSError>,
+    {
+        match fs.exists(path)? {
+            true => self.add_calls(fs.read(path)?.as_str()),
+            false => debug!("no mutable method calls at {}", path),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_names_are_ambiguous() {
+        let file: syn::File = syn::parse_str(
+            "
+struct Counter(u32);
+impl Counter {
+    fn bump(&mut self) { self.0 += 1 }
+    fn reset(&mut self) { self.0 = 0 }
+}
+struct Flag(bool);
+impl Flag {
+    fn reset(self) -> Self { Flag(false) }
+}
+",
+        )
+        .unwrap();
+        let mut mut_methods = MutMethods::std();
+        mut_methods.add_file(&file);
+        let is_mut =
+            |name: &str| mut_methods.contains(&Ident::new(name, proc_macro2::Span::call_site()));
+        assert!(is_mut("push"));
+        assert!(is_mut("bump"));
+        assert!(!is_mut("reset"));
+        assert!(!is_mut("take"));
+        assert!(!is_mut("len"));
+    }
+}
@@ -0,0 +1,538 @@
+use proc_macro2::{Ident, LineColumn};
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::visit::Visit;
+use syn::{
+    Expr, ExprMethodCall, FnArg, ImplItem, ImplItemMethod, Item, ItemFn, ItemImpl, ItemMod,
+    ItemTrait, Local, Pat, Signature, TraitItem, TraitItemMethod, Type,
+};
+
+use crate::error::Error;
+
+/// A function as given on the command line: `name`, qualified by the modules,
+/// type, trait or function it is defined in (`Type::name`, `m::Trait::name`),
+/// and/or the line its name is on (`name@12`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FnSpec {
+    pub path: Vec<String>,
+    pub name: String,
+    pub line: Option<usize>,
+}
+
+impl FnSpec {
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let invalid = || Error::Other(format!("invalid function `{}`", spec));
+        let (path, line) = match spec.split_once('@') {
+            Some((path, line)) => (path, Some(line.parse().map_err(|_| invalid())?)),
+            None => (spec, None),
+        };
+        let mut path: Vec<String> = path.split("::").map(|s| s.trim().to_string()).collect();
+        let name = path
+            .pop()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(invalid)?;
+        Ok(FnSpec { path, name, line })
+    }
+}
+
+/// A function definition in a parsed file: its name and where that name is.
+/// Functions of other impls or modules with the same name are other `FnId`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FnId {
+    pub name: String,
+    pub start: LineColumn,
+}
+
+impl FnId {
+    /// Whether `ident`, the name of a function definition, names this one.
+    pub fn is(&self, ident: &Ident) -> bool {
+        ident == self.name.as_str() && ident.span().start() == self.start
+    }
+
+    /// Whether `items` define this function, directly or in an impl or trait.
+    pub fn is_defined_in(&self, items: &[Item]) -> bool {
+        items.iter().any(|item| match item {
+            Item::Fn(f) => self.is(&f.sig.ident),
+            Item::Impl(i) => i.items.iter().any(|item| match item {
+                ImplItem::Method(m) => self.is(&m.sig.ident),
+                _ => false,
+            }),
+            Item::Trait(t) => t.items.iter().any(|item| match item {
+                TraitItem::Method(m) => self.is(&m.sig.ident),
+                _ => false,
+            }),
+            _ => false,
+        })
+    }
+}
+
+/// Whether the function called in `func(...)` is named `name`, whatever path
+/// it is called through (`name`, `Self::name`, `Type::name`...).
+pub fn calls(func: &Expr, name: &str) -> bool {
+    match func {
+        Expr::Path(p) => p
+            .path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.ident == name),
+        Expr::Paren(p) => calls(&p.expr, name),
+        _ => false,
+    }
+}
+
+/// The calls of a function (the callee) from another one (the caller), told
+/// apart from calls of other functions of the same name by the type or module
+/// they are called through: `Self::bar`, `A::bar` or `a.bar()` with `a: A`
+/// only call the `bar` of `A`. When the file defines a single function of
+/// that name, or the type of a receiver is unknown and no other method has
+/// the name, any call naming it is taken to be to the callee.
+#[derive(Debug, Clone, Default)]
+pub struct CallSites {
+    name: String,
+    /// Whether the callee is defined in the file, so that the fields below
+    /// are known
+    resolved: bool,
+    /// The type and trait the callee is defined in, none for a free function
+    owner: Vec<String>,
+    /// The module the callee is defined in, if any
+    module: Option<String>,
+    /// How many functions, and how many methods, of the file have the name
+    fns: usize,
+    methods: usize,
+    /// The type and trait `Self` stands for in the caller
+    caller_self: Vec<String>,
+    /// The types of the caller's inputs and locals, as far as they are written
+    locals: HashMap<String, String>,
+}
+
+impl CallSites {
+    /// Any call naming `name`, whatever it is called through.
+    pub fn named(name: &str) -> Self {
+        CallSites {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The calls of `callee` in `caller`, both functions of `file`.
+    pub fn resolve(file: &syn::File, caller: &FnId, callee: &FnId) -> Self {
+        let mut collector = CallSitesCollector {
+            caller,
+            callee,
+            scopes: vec![],
+            modules: vec![],
+            sites: CallSites::named(&callee.name),
+        };
+        collector.visit_file(file);
+        collector.sites
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether `func(...)` calls the callee.
+    pub fn is_call(&self, func: &Expr) -> bool {
+        let path = match func {
+            Expr::Paren(p) => return self.is_call(&p.expr),
+            Expr::Path(p) => p,
+            _ => return false,
+        };
+        if !calls(func, &self.name) {
+            return false;
+        }
+        if !self.resolved || self.fns <= 1 {
+            return true;
+        }
+        let qualifier = match &path.qself {
+            Some(qself) => Some(type_name(&qself.ty)),
+            None => path
+                .path
+                .segments
+                .iter()
+                .rev()
+                .nth(1)
+                .map(|segment| segment.ident.to_string()),
+        };
+        match qualifier {
+            None => self.owner.is_empty(),
+            Some(q) if q == "Self" => self.owner.iter().any(|o| self.caller_self.contains(o)),
+            Some(q) if self.owner.is_empty() => {
+                matches!(q.as_str(), "self" | "super" | "crate") || self.module.as_ref() == Some(&q)
+            }
+            Some(q) => self.owner.contains(&q),
+        }
+    }
+
+    /// Whether `receiver.method(...)` calls the callee.
+    pub fn is_method_call(&self, call: &ExprMethodCall) -> bool {
+        if call.method != self.name.as_str() {
+            return false;
+        }
+        match self.receiver_types(&call.receiver) {
+            Some(types) if self.resolved => self.owner.iter().any(|o| types.contains(o)),
+            _ => self.methods <= 1,
+        }
+    }
+
+    /// The type (and trait) `receiver` is of, if the caller tells.
+    fn receiver_types(&self, receiver: &Expr) -> Option<Vec<String>> {
+        match receiver {
+            Expr::Paren(p) => self.receiver_types(&p.expr),
+            Expr::Reference(r) => self.receiver_types(&r.expr),
+            Expr::Struct(s) => s.path.segments.last().map(|s| vec![s.ident.to_string()]),
+            Expr::Path(p) => {
+                let ident = p.path.get_ident()?.to_string();
+                match ident.as_str() {
+                    "self" => Some(self.caller_self.clone()),
+                    _ => match self.locals.get(&ident)?.as_str() {
+                        "Self" => Some(self.caller_self.clone()),
+                        ty => Some(vec![ty.to_string()]),
+                    },
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Fills in the `CallSites` of `callee` in `caller`.
+struct CallSitesCollector<'a> {
+    caller: &'a FnId,
+    callee: &'a FnId,
+    /// The type and trait of each impl or trait the visitor is in, nothing for
+    /// modules and functions, whose items are not methods
+    scopes: Vec<Vec<String>>,
+    modules: Vec<String>,
+    sites: CallSites,
+}
+
+impl CallSitesCollector<'_> {
+    fn check(&mut self, sig: &Signature) {
+        if sig.ident != self.sites.name.as_str() {
+            return;
+        }
+        let owner = self.scopes.last().cloned().unwrap_or_default();
+        let receiver = sig.receiver().is_some();
+        self.sites.fns += 1;
+        if receiver {
+            self.sites.methods += 1;
+        }
+        if self.callee.is(&sig.ident) {
+            self.sites.resolved = true;
+            self.sites.owner = owner;
+            self.sites.module = self.modules.last().cloned();
+        }
+    }
+
+    fn check_caller(&mut self, sig: &Signature, block: Option<&syn::Block>) {
+        if !self.caller.is(&sig.ident) {
+            return;
+        }
+        self.sites.caller_self = self.scopes.last().cloned().unwrap_or_default();
+        let mut locals = Locals {
+            locals: &mut self.sites.locals,
+        };
+        for input in sig.inputs.iter() {
+            if let FnArg::Typed(t) = input {
+                locals.bind(&t.pat, &t.ty)
+            }
+        }
+        if let Some(block) = block {
+            locals.visit_block(block)
+        }
+    }
+
+    fn visit_fn<F: FnOnce(&mut Self)>(
+        &mut self,
+        sig: &Signature,
+        block: Option<&syn::Block>,
+        visit: F,
+    ) {
+        self.check(sig);
+        self.check_caller(sig, block);
+        self.scopes.push(vec![]);
+        visit(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for CallSitesCollector<'_> {
+    fn visit_item_mod(&mut self, i: &'ast ItemMod) {
+        self.scopes.push(vec![]);
+        self.modules.push(i.ident.to_string());
+        syn::visit::visit_item_mod(self, i);
+        self.modules.pop();
+        self.scopes.pop();
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        let mut scope = vec![type_name(&i.self_ty)];
+        if let Some((_, path, _)) = &i.trait_ {
+            scope.extend(path.segments.last().map(|s| s.ident.to_string()));
+        }
+        self.scopes.push(scope);
+        syn::visit::visit_item_impl(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast ItemTrait) {
+        self.scopes.push(vec![i.ident.to_string()]);
+        syn::visit::visit_item_trait(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+        self.visit_fn(&i.sig, Some(&i.block), |v| syn::visit::visit_item_fn(v, i))
+    }
+
+    fn visit_impl_item_method(&mut self, i: &'ast ImplItemMethod) {
+        self.visit_fn(&i.sig, Some(&i.block), |v| {
+            syn::visit::visit_impl_item_method(v, i)
+        })
+    }
+
+    fn visit_trait_item_method(&mut self, i: &'ast TraitItemMethod) {
+        self.visit_fn(&i.sig, i.default.as_ref(), |v| {
+            syn::visit::visit_trait_item_method(v, i)
+        })
+    }
+}
+
+/// The types of the locals of a function, where they are written down or
+/// built with a struct expression.
+struct Locals<'a> {
+    locals: &'a mut HashMap<String, String>,
+}
+
+impl Locals<'_> {
+    fn bind(&mut self, pat: &Pat, ty: &Type) {
+        if let Pat::Ident(p) = pat {
+            self.locals.insert(p.ident.to_string(), type_name(ty));
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Locals<'_> {
+    fn visit_local(&mut self, i: &'ast Local) {
+        match (&i.pat, &i.init) {
+            (Pat::Type(t), _) => self.bind(&t.pat, &t.ty),
+            (Pat::Ident(p), Some((_, init))) => match init.as_ref() {
+                Expr::Struct(s) => {
+                    if let Some(segment) = s.path.segments.last() {
+                        self.locals
+                            .insert(p.ident.to_string(), segment.ident.to_string());
+                    }
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+        syn::visit::visit_local(self, i)
+    }
+
+    fn visit_item(&mut self, _: &'ast Item) {
+        // the locals of nested functions are their own
+    }
+}
+
+struct FnFinder<'a> {
+    spec: &'a FnSpec,
+    /// Names of the items the visitor is in, several for `impl Trait for Type`
+    scopes: Vec<Vec<String>>,
+    found: Vec<FnId>,
+}
+
+impl FnFinder<'_> {
+    fn check(&mut self, ident: &Ident) {
+        let start = ident.span().start();
+        let qualified = self.spec.path.len() <= self.scopes.len()
+            && self
+                .spec
+                .path
+                .iter()
+                .rev()
+                .zip(self.scopes.iter().rev())
+                .all(|(name, scope)| scope.contains(name));
+        if ident == self.spec.name.as_str()
+            && qualified
+            && self.spec.line.map_or(true, |line| line == start.line)
+        {
+            self.found.push(FnId {
+                name: self.spec.name.clone(),
+                start,
+            })
+        }
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Reference(r) => type_name(&r.elem),
+        Type::Paren(p) => type_name(&p.elem),
+        Type::Path(p) => p
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string())
+            .unwrap_or_default(),
+        ty => ty.to_token_stream().to_string(),
+    }
+}
+
+impl<'ast> Visit<'ast> for FnFinder<'_> {
+    fn visit_item_mod(&mut self, i: &'ast ItemMod) {
+        self.scopes.push(vec![i.ident.to_string()]);
+        syn::visit::visit_item_mod(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast ItemImpl) {
+        let mut scope = vec![type_name(&i.self_ty)];
+        if let Some((_, path, _)) = &i.trait_ {
+            scope.extend(path.segments.last().map(|s| s.ident.to_string()));
+        }
+        self.scopes.push(scope);
+        syn::visit::visit_item_impl(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast ItemTrait) {
+        self.scopes.push(vec![i.ident.to_string()]);
+        syn::visit::visit_item_trait(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_item_fn(&mut self, i: &'ast ItemFn) {
+        self.check(&i.sig.ident);
+        self.scopes.push(vec![i.sig.ident.to_string()]);
+        syn::visit::visit_item_fn(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_impl_item_method(&mut self, i: &'ast ImplItemMethod) {
+        self.check(&i.sig.ident);
+        self.scopes.push(vec![i.sig.ident.to_string()]);
+        syn::visit::visit_impl_item_method(self, i);
+        self.scopes.pop();
+    }
+
+    fn visit_trait_item_method(&mut self, i: &'ast TraitItemMethod) {
+        self.check(&i.sig.ident);
+        self.scopes.push(vec![i.sig.ident.to_string()]);
+        syn::visit::visit_trait_item_method(self, i);
+        self.scopes.pop();
+    }
+}
+
+/// The function of `file` that `spec` (see `FnSpec`) stands for, if any. It is
+/// an error for `spec` to stand for several functions.
+pub fn resolve_fn(file: &syn::File, spec: &str) -> Result<Option<FnId>, Error> {
+    let fn_spec = FnSpec::parse(spec)?;
+    let mut finder = FnFinder {
+        spec: &fn_spec,
+        scopes: vec![],
+        found: vec![],
+    };
+    finder.visit_file(file);
+    match finder.found.len() {
+        0 | 1 => Ok(finder.found.pop()),
+        _ => Err(Error::AmbiguousFn(spec.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+struct A;
+struct B;
+impl A { fn bar(&self) {} fn foobar(&self) {} }
+impl B { fn bar(&self) {} }
+mod m { fn bar() {} }
+";
+
+    #[test]
+    fn test_resolve_fn_tells_impls_apart() {
+        let file = syn::parse_str::<syn::File>(SOURCE).unwrap();
+        assert!(matches!(
+            resolve_fn(&file, "bar"),
+            Err(Error::AmbiguousFn(_))
+        ));
+        let a_bar = resolve_fn(&file, "A::bar").unwrap().unwrap();
+        assert_eq!(
+            a_bar.start,
+            LineColumn {
+                line: 4,
+                column: 12
+            }
+        );
+        let b_bar = resolve_fn(&file, "bar@5").unwrap().unwrap();
+        assert_eq!(
+            b_bar.start,
+            LineColumn {
+                line: 5,
+                column: 12
+            }
+        );
+        assert!(resolve_fn(&file, "m::bar").unwrap().is_some());
+        assert!(resolve_fn(&file, "B::foobar").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_call_sites_tell_impls_apart() {
+        let file = syn::parse_str::<syn::File>(
+            "
+struct A;
+struct B;
+impl A {
+    fn bar(&self) {}
+    fn new_foo(&self, b: &B) { A::bar(self); B::bar(b); self.bar(); b.bar(); }
+}
+impl B { fn bar(&self) {} }
+fn bar() {}
+fn free_caller(a: A) { let b = B {}; a.bar(); b.bar(); bar(); }
+",
+        )
+        .unwrap();
+        let id = |spec: &str| resolve_fn(&file, spec).unwrap().unwrap();
+        let call = |s: &str| syn::parse_str::<Expr>(s).unwrap();
+        let method_call = |s: &str| syn::parse_str::<ExprMethodCall>(s).unwrap();
+
+        let a_bar = CallSites::resolve(&file, &id("A::new_foo"), &id("A::bar"));
+        assert!(a_bar.is_call(&call("A::bar")));
+        assert!(a_bar.is_call(&call("Self::bar")));
+        assert!(a_bar.is_call(&call("<A as Clone>::bar")));
+        assert!(!a_bar.is_call(&call("B::bar")));
+        assert!(!a_bar.is_call(&call("bar")));
+        assert!(a_bar.is_method_call(&method_call("self.bar()")));
+        assert!(a_bar.is_method_call(&method_call("(&self).bar()")));
+        assert!(!a_bar.is_method_call(&method_call("b.bar()")));
+        // two methods are named `bar`, an unknown receiver could be either
+        assert!(!a_bar.is_method_call(&method_call("self.b.bar()")));
+
+        let a_bar = CallSites::resolve(&file, &id("free_caller"), &id("A::bar"));
+        assert!(a_bar.is_method_call(&method_call("a.bar()")));
+        assert!(!a_bar.is_method_call(&method_call("b.bar()")));
+
+        let free_bar = CallSites::resolve(&file, &id("free_caller"), &id("bar@9"));
+        assert!(free_bar.is_call(&call("bar")));
+        assert!(free_bar.is_call(&call("crate::bar")));
+        assert!(!free_bar.is_call(&call("A::bar")));
+        assert!(!free_bar.is_method_call(&method_call("a.bar()")));
+
+        // without the file, names are all there is
+        assert!(CallSites::named("bar").is_call(&call("B::bar")));
+        assert!(CallSites::named("bar").is_method_call(&method_call("b.bar()")));
+    }
+
+    #[test]
+    fn test_calls_matches_whole_names() {
+        let call = |s: &str| syn::parse_str::<Expr>(s).unwrap();
+        assert!(calls(&call("bar"), "bar"));
+        assert!(calls(&call("Self::bar"), "bar"));
+        assert!(!calls(&call("foobar"), "bar"));
+        assert!(!calls(&call("bar_extracted"), "bar"));
+    }
+}
@@ -5,6 +5,15 @@ use std::hash::Hash;
 use syn::punctuated::Punctuated;
 use syn::{FieldsNamed, Path, PathSegment, Type, TypeArray};
 
+/// Primitive types, all of them `Copy`.
+const COPY_PRIMITIVES: &[&str] = &[
+    "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128",
+    "usize", "f32", "f64",
+];
+
+/// Standard type constructors that are `Copy` when their arguments are.
+const COPY_CONSTRUCTORS: &[&str] = &["Option", "Result", "Wrapping", "Reverse"];
+
 /// Mapping of function names to type signatures
 pub type TypeMap = HashMap<crate::location::Loc, RustTypeSignature>;
 
@@ -145,12 +154,16 @@ pub enum RustType {
     Reference(RustMutability, Box<RustType>),
     /// *mut T
     Pointer(Box<RustType>),
+
+    /// any other type constructor with arguments, e.g. `Result<T, E>`, by the
+    /// last segment of its path, the arguments as written
+    Applied(syn::Ident, Vec<Type>),
 }
 
 impl RustType {
     fn uses(&self, set: &mut HashSet<syn::Ident>) {
         match self {
-            RustType::CAlias(id) => {
+            RustType::CAlias(id) | RustType::Applied(id, _) => {
                 set.insert(id.clone());
                 ()
             }
@@ -199,6 +212,13 @@ impl RustType {
                     }
                 }
             }
+            RustType::Applied(id, _) if path.contains(id) => true,
+            // the arguments of a generic alias are not substituted
+            RustType::Applied(id, _) if ctxt.0.contains_key(id) => {
+                path.insert(id.clone());
+                *self = ctxt.0[id].clone();
+                self.resolve_checked(path, ctxt)
+            }
             RustType::Option(elt)
             | RustType::Vec(elt)
             | RustType::Pointer(elt)
@@ -226,6 +246,84 @@ impl RustType {
         self.resolve_checked(&mut set, ctxt);
         set
     }
+
+    /// The type constructor of a path type, `Option` and `Vec` included, with
+    /// its arguments as written. Unlike `From<Type>`, never panics.
+    pub fn head(ty: &Type) -> Option<RustType> {
+        match ty {
+            Type::Path(syn::TypePath { qself: None, path }) => {
+                path.segments
+                    .last()
+                    .map(|segment| match segment.arguments.is_empty() {
+                        true => RustType::CAlias(segment.ident.clone()),
+                        false => applied(segment),
+                    })
+            }
+            Type::Paren(syn::TypeParen { elem, .. }) | Type::Group(syn::TypeGroup { elem, .. }) => {
+                RustType::head(elem)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether values of the type are `Copy`, given the types of the program
+    /// that are, e.g. by `#[derive(Clone, Copy)]`. Type parameters and types
+    /// of other crates are taken not to be.
+    pub fn is_copy(&self, copy_types: &HashSet<syn::Ident>) -> bool {
+        match self {
+            RustType::CVoid
+            | RustType::CInt { .. }
+            | RustType::CFloat(_)
+            | RustType::Unit
+            | RustType::I32
+            | RustType::U8
+            | RustType::SizeT
+            | RustType::Isize
+            | RustType::Usize
+            | RustType::Never
+            | RustType::ExternFn(..)
+            | RustType::Pointer(_) => true,
+            RustType::Reference(mutability, _) => *mutability == RustMutability::Immutable,
+            RustType::Array(ty, _) | RustType::Option(ty) => ty.is_copy(copy_types),
+            RustType::Vec(_) | RustType::TVar(_) => false,
+            RustType::CAlias(id) => {
+                COPY_PRIMITIVES.iter().any(|name| id == name) || copy_types.contains(id)
+            }
+            RustType::Applied(id, args) => {
+                (COPY_CONSTRUCTORS.iter().any(|name| id == name) || copy_types.contains(id))
+                    && args.iter().all(|ty| is_copy(ty, copy_types))
+            }
+        }
+    }
+}
+
+/// Same as `RustType::is_copy` on any type, tuples and references included.
+pub fn is_copy(ty: &Type, copy_types: &HashSet<syn::Ident>) -> bool {
+    match ty {
+        Type::Reference(syn::TypeReference { mutability, .. }) => mutability.is_none(),
+        Type::Ptr(_) | Type::Never(_) | Type::BareFn(_) => true,
+        Type::Array(TypeArray { elem, .. })
+        | Type::Paren(syn::TypeParen { elem, .. })
+        | Type::Group(syn::TypeGroup { elem, .. }) => is_copy(elem, copy_types),
+        Type::Tuple(syn::TypeTuple { elems, .. }) => elems.iter().all(|ty| is_copy(ty, copy_types)),
+        _ => RustType::head(ty).is_some_and(|head| head.is_copy(copy_types)),
+    }
+}
+
+fn applied(segment: &PathSegment) -> RustType {
+    let args = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+            args, ..
+        }) => args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    RustType::Applied(segment.ident.clone(), args)
 }
 
 impl std::fmt::Display for RustType {
@@ -260,6 +358,15 @@ impl std::fmt::Display for RustType {
                     .join(","),
                 body
             ),
+            RustType::Applied(ident, args) => write!(
+                f,
+                "{}<{}>",
+                ident,
+                args.iter()
+                    .map(|v| v.to_token_stream().to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
         }
     }
 }
@@ -389,6 +496,25 @@ impl Into<Type> for RustType {
                     output: syn::ReturnType::Type(Default::default(), Box::new(res.into())),
                 })
             }
+            RustType::Applied(ident, args) => Type::Path(syn::TypePath {
+                qself: None,
+                path: syn::Path {
+                    leading_colon: None,
+                    segments: [PathSegment {
+                        ident,
+                        arguments: syn::PathArguments::AngleBracketed(
+                            syn::AngleBracketedGenericArguments {
+                                colon2_token: None,
+                                lt_token: Default::default(),
+                                args: args.into_iter().map(syn::GenericArgument::Type).collect(),
+                                gt_token: Default::default(),
+                            },
+                        ),
+                    }]
+                    .into_iter()
+                    .collect(),
+                },
+            }),
         }
     }
 }
@@ -517,6 +643,15 @@ impl From<Type> for RustType {
                     _txt => RustType::CAlias(ident.clone()),
                 }
             }
+            Type::Path(syn::TypePath {
+                path: Path { ref segments, .. },
+                ..
+            }) if segments.last().is_some_and(|segment| {
+                matches!(segment.arguments, syn::PathArguments::AngleBracketed(_))
+            }) =>
+            {
+                applied(segments.last().unwrap())
+            }
             Type::Ptr(syn::TypePtr {
                 const_token,
                 mutability,
@@ -925,6 +1060,27 @@ impl<'ast> syn::visit::Visit<'ast> for CTypeContextCollector {
     }
 }
 
+#[derive(Debug, Default, Clone)]
+struct AliasCollector {
+    aliases: HashMap<syn::Ident, RustType>,
+}
+
+impl<'ast> syn::visit::Visit<'ast> for AliasCollector {
+    fn visit_item_type(&mut self, i: &'ast syn::ItemType) {
+        if let Some(typ) = RustType::head(&i.ty) {
+            self.aliases.insert(i.ident.clone(), typ);
+        }
+    }
+}
+
+/// The type aliases of a whole program, by the heads of the types they stand
+/// for (see `RustType::head`), and none of its structs.
+pub fn alias_context(file: &syn::File) -> ProgramTypeContext {
+    let mut collector = AliasCollector::default();
+    syn::visit::Visit::visit_file(&mut collector, file);
+    (collector.aliases, HashMap::new())
+}
+
 fn check_recursive(
     checking: &syn::Ident,
     current: syn::Ident,
@@ -994,3 +1150,52 @@ impl UnifyValue for RustType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(ty: &str, ctxt: &ProgramTypeContext) -> Option<RustType> {
+        let mut head = RustType::head(&syn::parse_str::<Type>(ty).unwrap());
+        if let Some(head) = head.as_mut() {
+            head.resolve(ctxt);
+        }
+        head
+    }
+
+    #[test]
+    fn test_alias_context_resolves_heads() {
+        let file = syn::parse_str::<syn::File>(
+            "
+enum E { A }
+type Res<T> = std::result::Result<T, E>;
+type Fallible = Res<()>;
+type Pair = (i32, i32);
+",
+        )
+        .unwrap();
+        let ctxt = alias_context(&file);
+        let is = |ty: &str, name: &str| matches!(head(ty, &ctxt), Some(RustType::Applied(id, _)) if id == name);
+        assert!(is("Fallible", "Result"));
+        assert!(is("Res<u8>", "Result"));
+        assert!(is("io::Result<u8>", "Result"));
+        assert!(is("Option<(i32, i32)>", "Option"));
+        assert!(head("Pair", &ctxt)
+            .is_some_and(|head| head == RustType::CAlias(syn::parse_str("Pair").unwrap())));
+        assert!(head("&str", &ctxt).is_none());
+    }
+
+    #[test]
+    fn test_is_copy() {
+        let copy_types: HashSet<syn::Ident> = [syn::parse_str("Point").unwrap()].into();
+        let is = |ty: &str| is_copy(&syn::parse_str::<Type>(ty).unwrap(), &copy_types);
+        assert!(is("i32"));
+        assert!(is("(usize, [f64; 3])"));
+        assert!(is("Option<&str>"));
+        assert!(is("Point"));
+        assert!(!is("String"));
+        assert!(!is("&mut i32"));
+        assert!(!is("Option<Vec<u8>>"));
+        assert!(!is("T"));
+    }
+}